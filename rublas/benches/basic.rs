@@ -1,3 +1,5 @@
+#![allow(unused_imports, unused_variables, non_snake_case, clippy::useless_vec, clippy::unit_arg)]
+
 use ndarray::linalg::general_mat_mul;
 use ndarray::linalg::general_mat_vec_mul;
 use ndarray::prelude::*;
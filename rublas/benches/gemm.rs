@@ -1,3 +1,5 @@
+#![allow(unexpected_cfgs, unused_imports, unused_variables, non_snake_case, clippy::useless_vec, clippy::unit_arg)]
+
 #[cfg(feature = "openblas")]
 extern crate ndarray_blas as ndarray;
 
@@ -90,11 +92,14 @@ fn gemm_rublas_owned(crit: &mut Criterion) {
                 BenchmarkId::new(format!("M{}_K{}_f32", Msize, Ksize), 0),
                 Msize,
                 |bench, msize| {
+                    let lhs = BlasTensor::ones(vec![*Msize, *Ksize]);
+                    let rhs = BlasTensor::ones(vec![*Ksize, *Msize]);
                     let exec = BlasExecutor::new();
+                    // the output cycles through the pool, so only the first
+                    // iteration allocates one
                     bench.iter(|| {
-                        let lhs = BlasTensor::ones(vec![*Msize, *Ksize]);
-                        let rhs = BlasTensor::ones(vec![*Ksize, *Msize]);
-                        black_box(exec.gemm_owned(lhs, rhs));
+                        let out = black_box(exec.gemm_owned(lhs.clone(), rhs.clone()));
+                        exec.recycle(out);
                     });
                 },
             );
@@ -1,3 +1,5 @@
+#![allow(unexpected_cfgs, unused_imports, clippy::useless_vec)]
+
 #[cfg(feature = "openblas")]
 extern crate ndarray_blas as ndarray;
 
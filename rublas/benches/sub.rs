@@ -1,3 +1,5 @@
+#![allow(unexpected_cfgs, unused_variables, non_snake_case, clippy::useless_vec, clippy::unit_arg)]
+
 #[cfg(feature = "openblas")]
 extern crate ndarray_blas as ndarray;
 
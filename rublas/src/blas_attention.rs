@@ -0,0 +1,329 @@
+use ndarray::linalg::general_mat_mul;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+use num_traits::Float;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{BlasTensor, DType, TensorElement};
+
+// rows of queries and keys handled per step, so the scores held at once are
+// at most BLOCK x BLOCK per (batch, head)
+const BLOCK: usize = 64;
+
+// causal lets query i attend keys 0..=i only, counted from the first query
+// and key; scale defaults to 1 / sqrt(dim)
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct AttentionParams {
+    pub causal: bool,
+    pub scale: Option<f64>,
+}
+
+// softmax(q k^T * scale) v over [batch, heads, seq, dim] Float/Double
+// tensors, fused: the scores are computed block by block with an online
+// softmax instead of materialising [seq_q, seq_k]. The key padding mask is
+// a Bool [batch, seq_k] tensor where true marks keys to ignore. Queries that
+// see no key at all get a zero output row.
+impl BlasExecutor {
+    pub fn attention_compute_owned(
+        &self,
+        op: BlasOpCode,
+        q: &BlasTensor,
+        k: &BlasTensor,
+        v: &BlasTensor,
+        key_padding: Option<&BlasTensor>,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::Attention(params) => self.attention_owned(q, k, v, key_padding, params),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    pub fn attention_owned(
+        &self,
+        q: &BlasTensor,
+        k: &BlasTensor,
+        v: &BlasTensor,
+        key_padding: Option<&BlasTensor>,
+        params: AttentionParams,
+    ) -> BlasTensor {
+        check_attention(q, k, v, key_padding);
+        match q.dtype() {
+            DType::Float => attention::<f32>(q, k, v, key_padding, params),
+            _ => attention::<f64>(q, k, v, key_padding, params),
+        }
+    }
+}
+
+fn attention<T: TensorElement + Float + LinalgScalar>(
+    q: &BlasTensor,
+    k: &BlasTensor,
+    v: &BlasTensor,
+    key_padding: Option<&BlasTensor>,
+    params: AttentionParams,
+) -> BlasTensor {
+    let q = q.view::<T>().into_dimensionality::<Ix4>().unwrap();
+    let k = k.view::<T>().into_dimensionality::<Ix4>().unwrap();
+    let v = v.view::<T>().into_dimensionality::<Ix4>().unwrap();
+    let padding = key_padding.map(|mask| mask.view::<bool>().into_dimensionality::<Ix2>().unwrap());
+    let (batch, heads, seq_q, dim) = q.dim();
+    let scale = params.scale.unwrap_or_else(|| 1.0 / (dim as f64).sqrt());
+    let scale = T::from(scale).unwrap();
+
+    let mut out_data = Array4::<T>::zeros([batch, heads, seq_q, v.shape()[3]]);
+    for b in 0..batch {
+        let keep = |key: usize| padding.as_ref().is_none_or(|mask| !mask[[b, key]]);
+        for h in 0..heads {
+            let (q, k, v) = (
+                q.slice(s![b, h, .., ..]),
+                k.slice(s![b, h, .., ..]),
+                v.slice(s![b, h, .., ..]),
+            );
+            let mut out = out_data.slice_mut(s![b, h, .., ..]);
+            for q0 in (0..seq_q).step_by(BLOCK) {
+                let q1 = (q0 + BLOCK).min(seq_q);
+                let rows = attend_block(
+                    q.slice(s![q0..q1, ..]),
+                    k,
+                    v,
+                    q0,
+                    scale,
+                    params.causal,
+                    &keep,
+                );
+                out.slice_mut(s![q0..q1, ..]).assign(&rows);
+            }
+        }
+    }
+    BlasTensor::from_array(out_data.into_dyn())
+}
+
+// output rows of the queries starting at q0, visiting the keys block by
+// block while keeping the running max and sum of every row
+fn attend_block<T: TensorElement + Float + LinalgScalar>(
+    q: ArrayView2<'_, T>,
+    k: ArrayView2<'_, T>,
+    v: ArrayView2<'_, T>,
+    q0: usize,
+    scale: T,
+    causal: bool,
+    keep: &dyn Fn(usize) -> bool,
+) -> Array2<T> {
+    let rows = q.nrows();
+    let seq_k = if causal {
+        k.nrows().min(q0 + rows)
+    } else {
+        k.nrows()
+    };
+    let mut max = vec![T::neg_infinity(); rows];
+    let mut sum = vec![T::zero(); rows];
+    let mut acc = Array2::<T>::zeros([rows, v.ncols()]);
+    let mut scores = Array2::<T>::zeros([rows, BLOCK.min(seq_k)]);
+    for k0 in (0..seq_k).step_by(BLOCK) {
+        let k1 = (k0 + BLOCK).min(seq_k);
+        let mut scores = scores.slice_mut(s![.., ..k1 - k0]);
+        general_mat_mul(
+            scale,
+            &q,
+            &k.slice(s![k0..k1, ..]).t(),
+            T::zero(),
+            &mut scores,
+        );
+        for (r, mut row) in scores.outer_iter_mut().enumerate() {
+            for (c, score) in row.iter_mut().enumerate() {
+                let key = k0 + c;
+                if !keep(key) || causal && key > q0 + r {
+                    *score = T::neg_infinity();
+                }
+            }
+            let block_max = row.fold(T::neg_infinity(), |m, &x| if x > m { x } else { m });
+            let new_max = if block_max > max[r] {
+                block_max
+            } else {
+                max[r]
+            };
+            if new_max == T::neg_infinity() {
+                // nothing visible to this query yet
+                row.fill(T::zero());
+                continue;
+            }
+            // rescale what was accumulated under the old max
+            let correction = (max[r] - new_max).exp();
+            row.mapv_inplace(|x| (x - new_max).exp());
+            sum[r] = sum[r] * correction + row.sum();
+            acc.row_mut(r).mapv_inplace(|x| x * correction);
+            max[r] = new_max;
+        }
+        general_mat_mul(
+            T::one(),
+            &scores,
+            &v.slice(s![k0..k1, ..]),
+            T::one(),
+            &mut acc,
+        );
+    }
+    for (mut row, &total) in acc.outer_iter_mut().zip(sum.iter()) {
+        if total > T::zero() {
+            row.mapv_inplace(|x| x / total);
+        }
+    }
+    acc
+}
+
+fn check_attention(
+    q: &BlasTensor,
+    k: &BlasTensor,
+    v: &BlasTensor,
+    key_padding: Option<&BlasTensor>,
+) {
+    if !matches!(q.dtype(), DType::Float | DType::Double) {
+        panic!(
+            "attention only supports Float and Double tensors, got {:?}",
+            q.dtype()
+        );
+    }
+    for operand in [k, v] {
+        if operand.dtype() != q.dtype() {
+            panic!(
+                "operands' types not match: {:?} vs {:?}",
+                q.dtype(),
+                operand.dtype()
+            );
+        }
+    }
+    let (qs, ks, vs) = (q.shape(), k.shape(), v.shape());
+    let valid = qs.len() == 4
+        && ks.len() == 4
+        && vs.len() == 4
+        && qs[..2] == ks[..2]
+        && ks[..3] == vs[..3]
+        && qs[3] == ks[3];
+    if !valid {
+        panic!(
+            "attention shapes not match: q {:?}, k {:?}, v {:?}",
+            qs, ks, vs
+        );
+    }
+    if let Some(mask) = key_padding {
+        if mask.dtype() != DType::Bool || mask.shape() != [ks[0], ks[2]] {
+            panic!(
+                "key padding mask must be Bool of shape {:?}, got {:?} of shape {:?}",
+                [ks[0], ks[2]],
+                mask.dtype(),
+                mask.shape()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // unfused attention: full scores, additive mask, softmax, matmul
+    fn reference(
+        q: &BlasTensor,
+        k: &BlasTensor,
+        v: &BlasTensor,
+        mask: &[Vec<bool>],
+        causal: bool,
+    ) -> BlasTensor {
+        let exec = BlasExecutor::new();
+        let q4 = q.view::<f64>().into_dimensionality::<Ix4>().unwrap();
+        let k4 = k.view::<f64>().into_dimensionality::<Ix4>().unwrap();
+        let v4 = v.view::<f64>().into_dimensionality::<Ix4>().unwrap();
+        let (b, h, sq, d) = q4.dim();
+        let sk = k4.shape()[2];
+        let mut scores = Array4::<f64>::zeros([b, h, sq, sk]);
+        let mut additive = Array4::<f64>::zeros([b, h, sq, sk]);
+        for ((bi, hi, i, j), score) in scores.indexed_iter_mut() {
+            *score = q4
+                .slice(s![bi, hi, i, ..])
+                .dot(&k4.slice(s![bi, hi, j, ..]))
+                / (d as f64).sqrt();
+            if mask[bi][j] || causal && j > i {
+                additive[[bi, hi, i, j]] = f64::NEG_INFINITY;
+            }
+        }
+        let scores = BlasTensor::from_array(scores.into_dyn());
+        let additive = BlasTensor::from_array(additive.into_dyn());
+        let p = exec.softmax_owned(scores, Some(&additive));
+        let p = p.view::<f64>().into_dimensionality::<Ix4>().unwrap();
+        let out = Array4::from_shape_fn([b, h, sq, v4.shape()[3]], |(bi, hi, i, j)| {
+            p.slice(s![bi, hi, i, ..]).dot(&v4.slice(s![bi, hi, .., j]))
+        });
+        BlasTensor::from_array(out.into_dyn())
+    }
+
+    #[test]
+    fn test_attention_single_key() {
+        let exec = BlasExecutor::new();
+        let q = BlasTensor::ones(vec![1, 1, 2, 2]);
+        let k = BlasTensor::ones(vec![1, 1, 1, 2]);
+        let v = BlasTensor::from_vec_shape(vec![3.0, -1.0, 7.0], vec![1, 1, 1, 3]);
+        let op = BlasOpCode::Attention(AttentionParams::default());
+        let c = exec.attention_compute_owned(op, &q, &k, &v, None);
+        let cref =
+            BlasTensor::from_vec_shape(vec![3.0, -1.0, 7.0, 3.0, -1.0, 7.0], vec![1, 1, 2, 3]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_attention_blocked_matches_reference() {
+        let exec = BlasExecutor::new();
+        // sequences longer than a block so the online softmax spans blocks
+        let q = BlasTensor::normal_double(vec![2, 2, 70, 8], 0.0, 1.0);
+        let k = BlasTensor::normal_double(vec![2, 2, 150, 8], 0.0, 1.0);
+        let v = BlasTensor::normal_double(vec![2, 2, 150, 5], 0.0, 1.0);
+        let c = exec.attention_owned(&q, &k, &v, None, AttentionParams::default());
+        let cref = reference(&q, &k, &v, &[vec![false; 150], vec![false; 150]], false);
+        assert!(c.all_close(&cref, 1e-10));
+    }
+
+    #[test]
+    fn test_attention_causal_and_padding() {
+        let exec = BlasExecutor::new();
+        let q = BlasTensor::normal_double(vec![2, 3, 100, 4], 0.0, 1.0);
+        let k = BlasTensor::normal_double(vec![2, 3, 100, 4], 0.0, 1.0);
+        let v = BlasTensor::normal_double(vec![2, 3, 100, 4], 0.0, 1.0);
+        let mask: Vec<Vec<bool>> = (0..2)
+            .map(|b| (0..100).map(|j| b == 1 && j >= 80).collect())
+            .collect();
+        let padding = BlasTensor::from_vec_shape_bool(mask.concat(), vec![2, 100]);
+        let params = AttentionParams {
+            causal: true,
+            scale: None,
+        };
+        let c = exec.attention_owned(&q, &k, &v, Some(&padding), params);
+        let cref = reference(&q, &k, &v, &mask, true);
+        assert!(c.all_close(&cref, 1e-10));
+
+        // the first query only sees the first key
+        let first = c.view::<f64>();
+        assert!((first[[0, 1, 0, 2]] - v.view::<f64>()[[0, 1, 0, 2]]).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_attention_fully_padded_f32() {
+        let exec = BlasExecutor::new();
+        let q = BlasTensor::uniform(vec![1, 1, 3, 4], -1.0, 1.0);
+        let k = BlasTensor::uniform(vec![1, 1, 3, 4], -1.0, 1.0);
+        let v = BlasTensor::uniform(vec![1, 1, 3, 4], -1.0, 1.0);
+        let padding = BlasTensor::from_vec_shape_bool(vec![true; 3], vec![1, 3]);
+        let params = AttentionParams {
+            causal: false,
+            scale: Some(0.5),
+        };
+        let c = exec.attention_owned(&q, &k, &v, Some(&padding), params);
+        assert_eq!(c, BlasTensor::zeros(vec![1, 1, 3, 4]));
+    }
+
+    #[test]
+    #[should_panic(expected = "attention shapes not match")]
+    fn test_attention_shape_mismatch() {
+        let exec = BlasExecutor::new();
+        let q = BlasTensor::zeros(vec![1, 1, 3, 4]);
+        let k = BlasTensor::zeros(vec![1, 1, 3, 5]);
+        exec.attention_owned(&q, &k, &k, None, AttentionParams::default());
+    }
+}
@@ -0,0 +1,585 @@
+use ndarray::prelude::*;
+use ndarray::{LinalgScalar, Zip};
+use num_traits::Float;
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{check_operands, with_float_dtype, BlasTensor, DType, TensorElement};
+use crate::blas_unary::erf;
+
+// handle of a tensor recorded on a Tape
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct TapeVar(usize);
+
+impl TapeVar {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Record {
+    Leaf,
+    Binary(BlasOpCode, TapeVar, TapeVar),
+    Unary(BlasOpCode, TapeVar),
+    Reduce(BlasOpCode, TapeVar, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    value: BlasTensor,
+    record: Record,
+}
+
+// Reverse-mode autodiff over Float/Double tensors. Every op runs eagerly on
+// the executor and its result is kept on the tape together with the opcode
+// and operands, so backward can walk the tape in reverse. Elementwise
+// operands must share type and shape, Gemm takes 2-D operands and Gemv a
+// 2-D matrix and a 1-D vector.
+#[derive(Debug, Clone, Default)]
+pub struct Tape {
+    nodes: Vec<Node>,
+}
+
+// gradients indexed by TapeVar, None for vars the output does not depend on
+#[derive(Debug, Clone)]
+pub struct Gradients {
+    grads: Vec<Option<BlasTensor>>,
+}
+
+impl Gradients {
+    pub fn get(&self, var: TapeVar) -> Option<&BlasTensor> {
+        self.grads[var.0].as_ref()
+    }
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn value(&self, var: TapeVar) -> &BlasTensor {
+        &self.nodes[var.0].value
+    }
+
+    pub fn leaf(&mut self, value: BlasTensor) -> TapeVar {
+        check_float(&value);
+        self.push(value, Record::Leaf)
+    }
+
+    // Add/Sub/Mul/Div and their Float typed forms, GemmF/GemmD, GemvF/GemvD
+    pub fn binary(
+        &mut self,
+        exec: &BlasExecutor,
+        op: BlasOpCode,
+        lhs: TapeVar,
+        rhs: TapeVar,
+    ) -> TapeVar {
+        let (a, b) = (self.value(lhs), self.value(rhs));
+        if a.dtype() != b.dtype() {
+            panic!(
+                "operands' types not match: {:?} vs {:?}",
+                a.dtype(),
+                b.dtype()
+            );
+        }
+        let value = match op {
+            BlasOpCode::Add
+            | BlasOpCode::Sub
+            | BlasOpCode::Mul
+            | BlasOpCode::Div
+            | BlasOpCode::AddF
+            | BlasOpCode::SubF
+            | BlasOpCode::MulF
+            | BlasOpCode::DivF => {
+                check_operands(a, b);
+                exec.binary_compute_owned(op, a.clone(), b.clone())
+            }
+            BlasOpCode::GemmF | BlasOpCode::GemmD => {
+                if a.ndims() != 2 || b.ndims() != 2 {
+                    panic!("{:?} needs operands with 2 dims", op);
+                }
+                match op {
+                    BlasOpCode::GemmF => exec.binary_compute_owned(op, a.clone(), b.clone()),
+                    _ => a.matmul(b),
+                }
+            }
+            BlasOpCode::GemvF | BlasOpCode::GemvD => {
+                if a.ndims() != 2 || b.ndims() != 1 {
+                    panic!("{:?} needs a matrix with 2 dims and a vector", op);
+                }
+                check_float(a);
+                a.matmul(b)
+            }
+            _ => panic!("no gradient for opcode {:?}", op),
+        };
+        self.push(value, Record::Binary(op, lhs, rhs))
+    }
+
+    // unary elementwise and tensor-scalar opcodes
+    pub fn unary(&mut self, exec: &BlasExecutor, op: BlasOpCode, input: TapeVar) -> TapeVar {
+        let x = self.value(input).clone();
+        let value = if is_scalar_op(op) {
+            exec.scalar_compute_owned(op, x)
+        } else {
+            exec.unary_compute_owned(op, x)
+        };
+        self.push(value, Record::Unary(op, input))
+    }
+
+    // ReduceSum/ReduceMean/ReduceMax/ReduceMin/ReduceProd
+    pub fn reduce(
+        &mut self,
+        exec: &BlasExecutor,
+        op: BlasOpCode,
+        input: TapeVar,
+        axis: Option<usize>,
+        keepdims: bool,
+    ) -> TapeVar {
+        if matches!(op, BlasOpCode::ArgMax | BlasOpCode::ArgMin) {
+            panic!("no gradient for opcode {:?}", op);
+        }
+        let value = exec.reduce_compute_owned(op, self.value(input).clone(), axis, keepdims);
+        self.push(value, Record::Reduce(op, input, axis))
+    }
+
+    // gradients of the sum of output's elements, so a single element loss
+    // gets the plain gradient
+    pub fn backward(&self, exec: &BlasExecutor, output: TapeVar) -> Gradients {
+        let mut grads: Vec<Option<BlasTensor>> = vec![None; self.nodes.len()];
+        let seed = self.value(output);
+        grads[output.0] = Some(with_float_dtype!(seed.dtype(), T => {
+            BlasTensor::from_array(ArrayD::<T>::from_elem(seed.shape(), 1.0))
+        }));
+        for idx in (0..=output.0).rev() {
+            let grad = match grads[idx].take() {
+                Some(grad) => grad,
+                None => continue,
+            };
+            let node = &self.nodes[idx];
+            let inputs =
+                with_float_dtype!(node.value.dtype(), T => self.node_grads::<T>(exec, node, &grad));
+            for (var, input_grad) in inputs {
+                match &mut grads[var.0] {
+                    Some(acc) => exec.binary_compute_inplace(BlasOpCode::Add, acc, &input_grad),
+                    slot => *slot = Some(input_grad),
+                }
+            }
+            grads[idx] = Some(grad);
+        }
+        Gradients { grads }
+    }
+
+    fn push(&mut self, value: BlasTensor, record: Record) -> TapeVar {
+        self.nodes.push(Node { value, record });
+        TapeVar(self.nodes.len() - 1)
+    }
+
+    // gradients w.r.t. the operands of node given the one of its value
+    fn node_grads<T>(
+        &self,
+        exec: &BlasExecutor,
+        node: &Node,
+        grad: &BlasTensor,
+    ) -> Vec<(TapeVar, BlasTensor)>
+    where
+        T: TensorElement + Float + LinalgScalar,
+    {
+        let g = grad.view::<T>();
+        match node.record {
+            Record::Leaf => vec![],
+            Record::Binary(op, lhs, rhs) => {
+                let (a, b) = (self.value(lhs).view::<T>(), self.value(rhs).view::<T>());
+                let (ga, gb) = match op {
+                    BlasOpCode::Add | BlasOpCode::AddF => (g.to_owned(), g.to_owned()),
+                    BlasOpCode::Sub | BlasOpCode::SubF => (g.to_owned(), g.mapv(|g| -g)),
+                    BlasOpCode::Mul | BlasOpCode::MulF => (&g * &b, &g * &a),
+                    BlasOpCode::Div | BlasOpCode::DivF => (
+                        &g / &b,
+                        Zip::from(&g)
+                            .and(&a)
+                            .and(&b)
+                            .apply_collect(|&g, &a, &b| -g * a / (b * b)),
+                    ),
+                    BlasOpCode::GemmF | BlasOpCode::GemmD => {
+                        let (a, b, g) = (matrix(a), matrix(b), matrix(g.view()));
+                        (g.dot(&b.t()).into_dyn(), a.t().dot(&g).into_dyn())
+                    }
+                    // gemv: outer product for the matrix, a^T g for the vector
+                    _ => {
+                        let (a, x) = (matrix(a), vector(b));
+                        let g = vector(g.view());
+                        let ga = g.insert_axis(Axis(1)).dot(&x.insert_axis(Axis(0)));
+                        (ga.into_dyn(), a.t().dot(&g).into_dyn())
+                    }
+                };
+                vec![
+                    (lhs, BlasTensor::from_array(ga)),
+                    (rhs, BlasTensor::from_array(gb)),
+                ]
+            }
+            Record::Unary(op, input) => {
+                let x = self.value(input).view::<T>();
+                let y = node.value.view::<T>();
+                let local = local_grad::<T>(op);
+                let gx = Zip::from(&g)
+                    .and(&x)
+                    .and(&y)
+                    .apply_collect(|&g, &x, &y| g * local(x, y));
+                vec![(input, BlasTensor::from_array(gx))]
+            }
+            Record::Reduce(op, input, axis) => {
+                let x = self.value(input);
+                let gx = reduce_grad::<T>(exec, op, x, &node.value, g, axis);
+                vec![(input, BlasTensor::from_array(gx))]
+            }
+        }
+    }
+}
+
+pub(crate) fn is_scalar_op(op: BlasOpCode) -> bool {
+    matches!(
+        op,
+        BlasOpCode::AddScalar(_)
+            | BlasOpCode::SubScalar(_)
+            | BlasOpCode::MulScalar(_)
+            | BlasOpCode::DivScalar(_)
+            | BlasOpCode::PowScalar(_)
+            | BlasOpCode::MinScalar(_)
+            | BlasOpCode::MaxScalar(_)
+            | BlasOpCode::RSubScalar(_)
+            | BlasOpCode::RDivScalar(_)
+    )
+}
+
+// dy/dx of an elementwise op from its input x and output y; the step
+// functions Floor/Ceil/Round/Sign have zero gradient
+fn local_grad<T: Float + 'static>(op: BlasOpCode) -> Box<dyn Fn(T, T) -> T> {
+    let cast = |x: f64| T::from(x).unwrap();
+    let (zero, one) = (T::zero(), T::one());
+    match op {
+        BlasOpCode::Neg => Box::new(move |_, _| -one),
+        BlasOpCode::Abs => Box::new(move |x, _| if x == zero { zero } else { x.signum() }),
+        BlasOpCode::Exp => Box::new(|_, y| y),
+        BlasOpCode::Log => Box::new(|x, _| x.recip()),
+        BlasOpCode::Sqrt => Box::new(move |_, y| (cast(2.0) * y).recip()),
+        BlasOpCode::Rsqrt => Box::new(move |_, y| cast(-0.5) * y * y * y),
+        BlasOpCode::Sin => Box::new(|x, _| x.cos()),
+        BlasOpCode::Cos => Box::new(|x, _| -x.sin()),
+        BlasOpCode::Tanh => Box::new(move |_, y| one - y * y),
+        BlasOpCode::Sigmoid => Box::new(move |_, y| y * (one - y)),
+        BlasOpCode::Relu => Box::new(move |x, _| if x > zero { one } else { zero }),
+        BlasOpCode::Gelu => Box::new(|x, _| {
+            let x = x.to_f64().unwrap();
+            let cdf = 0.5 * (1.0 + erf(x * FRAC_1_SQRT_2));
+            let pdf = (-0.5 * x * x).exp() / (2.0 * PI).sqrt();
+            T::from(cdf + x * pdf).unwrap()
+        }),
+        BlasOpCode::Erf => Box::new(|x, _| {
+            let x = x.to_f64().unwrap();
+            T::from(2.0 / PI.sqrt() * (-x * x).exp()).unwrap()
+        }),
+        BlasOpCode::Floor | BlasOpCode::Ceil | BlasOpCode::Round | BlasOpCode::Sign => {
+            Box::new(move |_, _| zero)
+        }
+        BlasOpCode::Reciprocal => Box::new(|_, y| -y * y),
+        BlasOpCode::AddScalar(_) | BlasOpCode::SubScalar(_) => Box::new(move |_, _| one),
+        BlasOpCode::MulScalar(imm) => Box::new(move |_, _| cast(imm)),
+        BlasOpCode::DivScalar(imm) => Box::new(move |_, _| cast(imm).recip()),
+        BlasOpCode::PowScalar(imm) => Box::new(move |x, _| cast(imm) * x.powf(cast(imm - 1.0))),
+        BlasOpCode::MinScalar(imm) => Box::new(move |x, _| if x < cast(imm) { one } else { zero }),
+        BlasOpCode::MaxScalar(imm) => Box::new(move |x, _| if x > cast(imm) { one } else { zero }),
+        BlasOpCode::RSubScalar(_) => Box::new(move |_, _| -one),
+        BlasOpCode::RDivScalar(_) => Box::new(|x, y| -y / x),
+        _ => panic!("no gradient for opcode {:?}", op),
+    }
+}
+
+// spreads the gradient of a reduction back over its input; Max/Min split it
+// evenly between tied extremes, Prod takes the product of the other factors
+// so zeros are handled
+fn reduce_grad<T: TensorElement + Float>(
+    exec: &BlasExecutor,
+    op: BlasOpCode,
+    x: &BlasTensor,
+    y: &BlasTensor,
+    g: ArrayViewD<'_, T>,
+    axis: Option<usize>,
+) -> ArrayD<T> {
+    let shape = x.shape();
+    let kept: Vec<usize> = match axis {
+        Some(axis) => {
+            let mut kept = shape.clone();
+            kept[axis] = 1;
+            kept
+        }
+        None => vec![1; shape.len()],
+    };
+    let g = keepdims(g, &kept);
+    let g = g.broadcast(IxDyn(&shape)).unwrap();
+    let x_view = x.view::<T>();
+    match op {
+        BlasOpCode::ReduceSum => g.to_owned(),
+        BlasOpCode::ReduceMean => {
+            let count = T::from(x.numel() / kept.iter().product::<usize>()).unwrap();
+            g.mapv(|g| g / count)
+        }
+        BlasOpCode::ReduceMax | BlasOpCode::ReduceMin => {
+            let y = keepdims(y.view::<T>(), &kept);
+            let y = y.broadcast(IxDyn(&shape)).unwrap();
+            let hits =
+                Zip::from(&x_view)
+                    .and(&y)
+                    .apply_collect(|&x, &y| if x == y { T::one() } else { T::zero() });
+            let counts = exec.sum_owned(BlasTensor::from_array(hits.clone()), axis, true);
+            let counts = keepdims(counts.view::<T>(), &kept);
+            let counts = counts.broadcast(IxDyn(&shape)).unwrap();
+            Zip::from(&hits)
+                .and(&g)
+                .and(&counts)
+                .apply_collect(|&hit, &g, &count| hit * g / count)
+        }
+        BlasOpCode::ReduceProd => {
+            // a single lane over all elements when axis is None
+            let mut others = match axis {
+                Some(_) => x_view.to_owned(),
+                None => Array::from_iter(x_view.iter().cloned()).into_dyn(),
+            };
+            for mut lane in others.lanes_mut(Axis(axis.unwrap_or(0))) {
+                // prefix products times suffix products
+                let factors = lane.to_owned();
+                let mut acc = T::one();
+                for (value, &x) in lane.iter_mut().zip(factors.iter()) {
+                    *value = acc;
+                    acc = acc * x;
+                }
+                let mut acc = T::one();
+                for i in (0..factors.len()).rev() {
+                    lane[i] = lane[i] * acc;
+                    acc = acc * factors[i];
+                }
+            }
+            if axis.is_none() {
+                others = others.into_shape(IxDyn(&shape)).unwrap();
+            }
+            &others * &g
+        }
+        _ => panic!("no gradient for opcode {:?}", op),
+    }
+}
+
+// reshapes a reduction result or its gradient to the keepdims shape
+fn keepdims<T: TensorElement>(view: ArrayViewD<'_, T>, kept: &[usize]) -> ArrayD<T> {
+    Array::from_shape_vec(IxDyn(kept), view.iter().cloned().collect()).unwrap()
+}
+
+fn matrix<T>(view: ArrayViewD<'_, T>) -> ArrayView2<'_, T> {
+    view.into_dimensionality::<Ix2>().unwrap()
+}
+
+fn vector<T>(view: ArrayViewD<'_, T>) -> ArrayView1<'_, T> {
+    view.into_dimensionality::<Ix1>().unwrap()
+}
+
+fn check_float(tensor: &BlasTensor) {
+    if !matches!(tensor.dtype(), DType::Float | DType::Double) {
+        panic!(
+            "autodiff only supports Float and Double tensors, got {:?}",
+            tensor.dtype()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // compares the tape gradients of a scalar valued f64 program with
+    // central finite differences
+    fn check_grads<F>(inputs: Vec<BlasTensor>, program: F)
+    where
+        F: Fn(&BlasExecutor, &mut Tape, &[TapeVar]) -> TapeVar,
+    {
+        let exec = BlasExecutor::new();
+        let run = |inputs: &[BlasTensor]| {
+            let mut tape = Tape::new();
+            let vars: Vec<TapeVar> = inputs.iter().map(|x| tape.leaf(x.clone())).collect();
+            let out = program(&exec, &mut tape, &vars);
+            (tape, vars, out)
+        };
+        let (tape, vars, out) = run(&inputs);
+        let grads = tape.backward(&exec, out);
+        let h = 1e-6;
+        for (i, var) in vars.iter().enumerate() {
+            let grad = grads.get(*var).unwrap().view::<f64>().to_owned();
+            for (j, &analytic) in grad.iter().enumerate() {
+                let eval = |delta: f64| {
+                    let mut shifted = inputs.clone();
+                    *shifted[i].view_mut::<f64>().iter_mut().nth(j).unwrap() += delta;
+                    let (tape, _, out) = run(&shifted);
+                    tape.value(out).view::<f64>().sum()
+                };
+                let numeric = (eval(h) - eval(-h)) / (2.0 * h);
+                assert!(
+                    (analytic - numeric).abs() < 1e-5 * (1.0 + numeric.abs()),
+                    "input {} element {}: {} vs {}",
+                    i,
+                    j,
+                    analytic,
+                    numeric
+                );
+            }
+        }
+    }
+
+    fn positive(shape: Vec<usize>) -> BlasTensor {
+        BlasTensor::uniform_double(shape, 0.5, 2.0)
+    }
+
+    #[test]
+    fn test_grad_elementwise_chain() {
+        // ((a + b) * c) / d - a
+        let shape = vec![2, 3];
+        let inputs = vec![
+            BlasTensor::normal_double(shape.clone(), 0.0, 1.0),
+            BlasTensor::normal_double(shape.clone(), 0.0, 1.0),
+            BlasTensor::normal_double(shape.clone(), 0.0, 1.0),
+            positive(shape),
+        ];
+        check_grads(inputs, |exec, tape, x| {
+            let sum = tape.binary(exec, BlasOpCode::Add, x[0], x[1]);
+            let prod = tape.binary(exec, BlasOpCode::Mul, sum, x[2]);
+            let quot = tape.binary(exec, BlasOpCode::Div, prod, x[3]);
+            tape.binary(exec, BlasOpCode::Sub, quot, x[0])
+        });
+    }
+
+    #[test]
+    fn test_grad_gemm_gemv() {
+        let inputs = vec![
+            BlasTensor::normal_double(vec![3, 4], 0.0, 1.0),
+            BlasTensor::normal_double(vec![4, 2], 0.0, 1.0),
+            BlasTensor::normal_double(vec![2], 0.0, 1.0),
+        ];
+        check_grads(inputs, |exec, tape, x| {
+            let ab = tape.binary(exec, BlasOpCode::GemmD, x[0], x[1]);
+            let abv = tape.binary(exec, BlasOpCode::GemvD, ab, x[2]);
+            let sq = tape.binary(exec, BlasOpCode::Mul, abv, abv);
+            tape.reduce(exec, BlasOpCode::ReduceSum, sq, None, false)
+        });
+    }
+
+    #[test]
+    fn test_grad_unary_and_scalar_ops() {
+        let ops = [
+            BlasOpCode::Neg,
+            BlasOpCode::Abs,
+            BlasOpCode::Exp,
+            BlasOpCode::Log,
+            BlasOpCode::Sqrt,
+            BlasOpCode::Rsqrt,
+            BlasOpCode::Sin,
+            BlasOpCode::Cos,
+            BlasOpCode::Tanh,
+            BlasOpCode::Sigmoid,
+            BlasOpCode::Relu,
+            BlasOpCode::Gelu,
+            BlasOpCode::Erf,
+            BlasOpCode::Reciprocal,
+            BlasOpCode::AddScalar(1.5),
+            BlasOpCode::SubScalar(1.5),
+            BlasOpCode::MulScalar(-3.0),
+            BlasOpCode::DivScalar(4.0),
+            BlasOpCode::PowScalar(2.5),
+            BlasOpCode::MinScalar(1.0),
+            BlasOpCode::MaxScalar(1.0),
+            BlasOpCode::RSubScalar(2.0),
+            BlasOpCode::RDivScalar(2.0),
+        ];
+        for op in ops {
+            check_grads(vec![positive(vec![2, 2, 3])], |exec, tape, x| {
+                tape.unary(exec, op, x[0])
+            });
+        }
+    }
+
+    #[test]
+    fn test_grad_reductions() {
+        let ops = [
+            BlasOpCode::ReduceSum,
+            BlasOpCode::ReduceMean,
+            BlasOpCode::ReduceMax,
+            BlasOpCode::ReduceMin,
+            BlasOpCode::ReduceProd,
+        ];
+        for op in ops {
+            for (axis, keepdims) in [(None, false), (Some(1), false), (Some(0), true)] {
+                let input = BlasTensor::normal_double(vec![3, 4], 0.0, 1.0);
+                check_grads(vec![input], |exec, tape, x| {
+                    let r = tape.reduce(exec, op, x[0], axis, keepdims);
+                    // weights the reduced elements differently
+                    let sq = tape.binary(exec, BlasOpCode::Mul, r, r);
+                    tape.binary(exec, BlasOpCode::Add, sq, r)
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_grad_exact_f32() {
+        // loss = sum(relu(a b) * 2) with a reused operand
+        let exec = BlasExecutor::new();
+        let mut tape = Tape::new();
+        let a = tape.leaf(BlasTensor::from_vec_shape(
+            vec![1.0, -2.0, 3.0, 4.0],
+            vec![2, 2],
+        ));
+        let b = tape.leaf(BlasTensor::from_vec_shape(
+            vec![1.0, 0.0, 0.0, 1.0],
+            vec![2, 2],
+        ));
+        let c = tape.binary(&exec, BlasOpCode::GemmF, a, b);
+        let r = tape.unary(&exec, BlasOpCode::Relu, c);
+        let d = tape.binary(&exec, BlasOpCode::AddF, r, r);
+        let loss = tape.reduce(&exec, BlasOpCode::ReduceSum, d, None, false);
+        assert_eq!(tape.value(loss), &BlasTensor::from_vec(vec![16.0]));
+        assert_eq!(tape.len(), 6);
+
+        let grads = tape.backward(&exec, loss);
+        let ga = BlasTensor::from_vec_shape(vec![2.0, 0.0, 2.0, 2.0], vec![2, 2]);
+        assert_eq!(grads.get(a), Some(&ga));
+        let gb = BlasTensor::from_vec_shape(vec![8.0, 6.0, 4.0, 8.0], vec![2, 2]);
+        assert_eq!(grads.get(b), Some(&gb));
+    }
+
+    #[test]
+    fn test_grad_unused_input() {
+        let exec = BlasExecutor::new();
+        let mut tape = Tape::new();
+        let a = tape.leaf(BlasTensor::from_vec(vec![1.0, 2.0]));
+        let b = tape.leaf(BlasTensor::from_vec(vec![3.0, 4.0]));
+        let c = tape.unary(&exec, BlasOpCode::Exp, b);
+        let grads = tape.backward(&exec, a);
+        assert_eq!(grads.get(a), Some(&BlasTensor::ones(vec![2])));
+        assert_eq!(grads.get(b), None);
+        assert_eq!(grads.get(c), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "no gradient for opcode ArgMax")]
+    fn test_grad_argmax() {
+        let exec = BlasExecutor::new();
+        let mut tape = Tape::new();
+        let a = tape.leaf(BlasTensor::from_vec(vec![1.0, 2.0]));
+        tape.reduce(&exec, BlasOpCode::ArgMax, a, None, false);
+    }
+}
@@ -0,0 +1,269 @@
+use ndarray::prelude::*;
+use std::collections::HashMap;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_tensor::{with_any_dtype, BlasTensor, DType, TensorElement, TensorKind};
+
+// counters of the executor's buffer pool; allocations only counts buffers
+// the pool could not serve, so a steady-state loop that recycles its
+// tensors keeps it constant; discards counts returns freed for being over
+// the pool's limits
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct PoolStats {
+    pub allocations: usize,
+    pub reuses: usize,
+    pub returns: usize,
+    pub discards: usize,
+    pub pooled_buffers: usize,
+    pub pooled_bytes: usize,
+}
+
+// buffers kept per bucket and bytes kept overall, returns past either are
+// freed instead of pooled
+const MAX_BUCKET_BUFFERS: usize = 8;
+const MAX_POOLED_BYTES: usize = 256 << 20;
+
+// Free buffers bucketed by element type and power of two capacity. A
+// returned buffer goes to the bucket of its capacity rounded down. A
+// request for n elements is served from the bucket of n rounded up, where
+// every buffer fits, or else by a large enough buffer of the bucket below,
+// so recycled tensors of odd sizes serve requests of their own size. The
+// buffers are kept as empty 1-D TensorKinds to erase their element type,
+// next to their capacity.
+#[derive(Debug, Default)]
+pub(crate) struct BufferPool {
+    buckets: HashMap<(DType, usize), Vec<(usize, TensorKind)>>,
+    stats: PoolStats,
+}
+
+impl BufferPool {
+    fn take<T: TensorElement>(&mut self, len: usize) -> Vec<T> {
+        let bucket = len.max(1).next_power_of_two();
+        let found = self
+            .buckets
+            .get_mut(&(T::DTYPE, bucket))
+            .and_then(Vec::pop)
+            .or_else(|| {
+                let below = self.buckets.get_mut(&(T::DTYPE, bucket / 2))?;
+                let idx = below.iter().position(|&(capacity, _)| capacity >= len)?;
+                Some(below.swap_remove(idx))
+            });
+        match found {
+            Some((_, kind)) => {
+                let buffer = T::into_storage(kind).unwrap().into_raw_vec();
+                self.stats.reuses += 1;
+                self.stats.pooled_buffers -= 1;
+                self.stats.pooled_bytes -= buffer.capacity() * std::mem::size_of::<T>();
+                buffer
+            }
+            None => {
+                self.stats.allocations += 1;
+                Vec::with_capacity(bucket)
+            }
+        }
+    }
+
+    fn give<T: TensorElement>(&mut self, mut buffer: Vec<T>) {
+        let capacity = buffer.capacity();
+        self.stats.returns += 1;
+        if capacity == 0 {
+            return;
+        }
+        // rounded down to a power of two
+        let bucket = 1 << (usize::BITS - 1 - capacity.leading_zeros());
+        let bytes = capacity * std::mem::size_of::<T>();
+        let buffers = self.buckets.entry((T::DTYPE, bucket)).or_default();
+        if buffers.len() >= MAX_BUCKET_BUFFERS || self.stats.pooled_bytes + bytes > MAX_POOLED_BYTES
+        {
+            self.stats.discards += 1;
+            return;
+        }
+        buffer.clear();
+        self.stats.pooled_buffers += 1;
+        self.stats.pooled_bytes += bytes;
+        buffers.push((capacity, T::wrap(Array1::from(buffer).into_dyn())));
+    }
+}
+
+// Owned-result kernels (gemm_owned, the typed elementwise *_owned, conv and
+// the LazyGraph kernels) draw their output buffers from the pool, and the
+// kernels return their own temporaries to it; caller tensors only go back
+// through recycle. The unary, scalar and untyped binary owned kernels write
+// into the operand they consume instead.
+impl BlasExecutor {
+    pub fn recycle(&self, tensor: BlasTensor) {
+        let mut pool = self.pool.lock().unwrap();
+        with_any_dtype!(tensor.dtype(), T => {
+            let storage = T::into_storage(tensor.data).unwrap();
+            pool.give(storage.into_raw_vec())
+        })
+    }
+
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.lock().unwrap().stats
+    }
+
+    // frees every pooled buffer, the counters other than the pooled ones
+    // keep running
+    pub fn clear_pool(&self) {
+        let mut pool = self.pool.lock().unwrap();
+        pool.buckets.clear();
+        pool.stats.pooled_buffers = 0;
+        pool.stats.pooled_bytes = 0;
+    }
+
+    // row-major copy of the logical tensor in a pooled buffer
+    pub fn copy_pooled(&self, tensor: &BlasTensor) -> BlasTensor {
+        with_any_dtype!(tensor.dtype(), T => {
+            let mut buffer = self.take_buffer::<T>(tensor.numel());
+            buffer.extend(tensor.view::<T>().iter().cloned());
+            BlasTensor::from_array(ArrayD::from_shape_vec(tensor.shape(), buffer).unwrap())
+        })
+    }
+
+    // empty buffer with room for len elements
+    pub(crate) fn take_buffer<T: TensorElement>(&self, len: usize) -> Vec<T> {
+        self.pool.lock().unwrap().take(len)
+    }
+
+    pub(crate) fn pooled_array<T: TensorElement>(&self, shape: &[usize], fill: T) -> ArrayD<T> {
+        let len = shape.iter().product();
+        let mut buffer = self.take_buffer::<T>(len);
+        buffer.resize(len, fill);
+        ArrayD::from_shape_vec(shape, buffer).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blas_opcode::BlasOpCode;
+
+    #[test]
+    fn test_pool_reuses_bucket() {
+        let exec = BlasExecutor::new();
+        let a = exec.copy_pooled(&BlasTensor::ones(vec![3, 5]));
+        assert_eq!(a, BlasTensor::ones(vec![3, 5]));
+        exec.recycle(a);
+        let stats = exec.pool_stats();
+        assert_eq!((stats.allocations, stats.returns), (1, 1));
+        assert_eq!((stats.pooled_buffers, stats.pooled_bytes), (1, 16 * 4));
+
+        // 9..=16 elements share the bucket, other types and sizes do not
+        let b = exec.copy_pooled(&BlasTensor::zeros(vec![9]));
+        assert_eq!(b, BlasTensor::zeros(vec![9]));
+        exec.copy_pooled(&BlasTensor::zeros(vec![17]));
+        exec.copy_pooled(&BlasTensor::zeros_double(vec![9]));
+        let stats = exec.pool_stats();
+        assert_eq!((stats.allocations, stats.reuses), (3, 1));
+        assert_eq!(stats.pooled_buffers, 0);
+    }
+
+    #[test]
+    fn test_pool_reuses_odd_capacity() {
+        let exec = BlasExecutor::new();
+        // a 12 element buffer sits in bucket 8 but still serves 12
+        exec.recycle(BlasTensor::zeros(vec![12]));
+        exec.copy_pooled(&BlasTensor::zeros(vec![13]));
+        assert_eq!(exec.pool_stats().reuses, 0);
+        let a = exec.copy_pooled(&BlasTensor::zeros(vec![12]));
+        assert_eq!(exec.pool_stats().reuses, 1);
+        exec.recycle(a);
+        exec.copy_pooled(&BlasTensor::zeros(vec![7]));
+        assert_eq!(exec.pool_stats().reuses, 2);
+    }
+
+    #[test]
+    fn test_gemm_steady_state_no_allocation() {
+        let exec = BlasExecutor::new();
+        let lhs = BlasTensor::uniform(vec![8, 6], -1.0, 1.0);
+        let rhs = BlasTensor::uniform(vec![6, 8], -1.0, 1.0);
+        let cref = lhs.matmul(&rhs);
+        let mut allocations = vec![];
+        for _ in 0..5 {
+            let out = exec.gemm_owned(lhs.clone(), rhs.clone());
+            assert!(out.all_close(&cref, 1e-6));
+            exec.recycle(out);
+            allocations.push(exec.pool_stats().allocations);
+        }
+        // the output buffer cycles through the pool
+        assert_eq!(allocations, [1, 1, 1, 1, 1]);
+        assert_eq!(exec.pool_stats().pooled_buffers, 1);
+    }
+
+    #[test]
+    fn test_elementwise_steady_state_no_allocation() {
+        let exec = BlasExecutor::new();
+        let lhs = BlasTensor::uniform(vec![8, 6], -1.0, 1.0);
+        let rhs = BlasTensor::uniform(vec![8, 6], -1.0, 1.0);
+        // the mixed-layout pair takes the logical path
+        let fortran = BlasTensor::from_array_fortran(rhs.view::<f32>().to_owned());
+        let cref = &lhs + &rhs;
+        for _ in 0..5 {
+            let out = exec.addf32_owned(lhs.clone(), rhs.clone());
+            assert!(out.all_close(&cref, 1e-6));
+            exec.recycle(out);
+            let out = exec.addf32_owned(lhs.clone(), fortran.clone());
+            assert!(out.all_close(&cref, 1e-6));
+            exec.recycle(out);
+        }
+        let stats = exec.pool_stats();
+        assert_eq!((stats.allocations, stats.reuses), (1, 9));
+    }
+
+    #[test]
+    fn test_pool_does_not_grow_without_recycle() {
+        let exec = BlasExecutor::new();
+        let lhs = BlasTensor::uniform(vec![16, 16], -1.0, 1.0);
+        let rhs = BlasTensor::uniform(vec![16, 16], -1.0, 1.0);
+        for _ in 0..20 {
+            exec.gemm_owned(lhs.clone(), rhs.clone());
+        }
+        let stats = exec.pool_stats();
+        assert_eq!((stats.pooled_buffers, stats.pooled_bytes), (0, 0));
+
+        // conv recycles its own gemm results and reuses them
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::uniform(vec![1, 8, 8, 3], -1.0, 1.0);
+        let w = BlasTensor::uniform(vec![4, 3, 3, 3], -1.0, 1.0);
+        let mut pooled_bytes = vec![];
+        for _ in 0..20 {
+            exec.conv2d_owned(&x, &w, crate::blas_conv::ConvParams::default());
+            pooled_bytes.push(exec.pool_stats().pooled_bytes);
+        }
+        assert!(pooled_bytes.iter().all(|&bytes| bytes == pooled_bytes[0]));
+        assert_eq!(exec.pool_stats().allocations, 1);
+    }
+
+    #[test]
+    fn test_pool_bucket_limit() {
+        let exec = BlasExecutor::new();
+        for _ in 0..MAX_BUCKET_BUFFERS + 3 {
+            exec.recycle(BlasTensor::zeros(vec![16]));
+        }
+        let stats = exec.pool_stats();
+        assert_eq!(
+            (stats.pooled_buffers, stats.discards),
+            (MAX_BUCKET_BUFFERS, 3)
+        );
+        assert_eq!(stats.pooled_bytes, MAX_BUCKET_BUFFERS * 16 * 4);
+    }
+
+    #[test]
+    fn test_clear_pool() {
+        let exec = BlasExecutor::new();
+        exec.recycle(BlasTensor::from_vec_shape_i32(vec![1, 2, 3], vec![3]));
+        exec.recycle(BlasTensor::from_vec_shape_bool(vec![true], vec![1]));
+        assert_eq!(exec.pool_stats().pooled_buffers, 2);
+        exec.clear_pool();
+        let stats = exec.pool_stats();
+        assert_eq!((stats.pooled_buffers, stats.pooled_bytes), (0, 0));
+        assert_eq!(stats.returns, 2);
+        let c = exec.unary_compute_owned(
+            BlasOpCode::Neg,
+            exec.copy_pooled(&BlasTensor::from_vec_shape_i32(vec![1, 2], vec![2])),
+        );
+        assert_eq!(c, BlasTensor::from_vec_shape_i32(vec![-1, -2], vec![2]));
+        assert_eq!(exec.pool_stats().allocations, 1);
+    }
+}
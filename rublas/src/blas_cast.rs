@@ -0,0 +1,184 @@
+use std::borrow::Cow;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_any_dtype, BlasTensor, CastMode, DType, TensorElement};
+use crate::blas_view::BlasTensorView;
+
+impl BlasExecutor {
+    pub fn cast_compute_owned(&self, op: BlasOpCode, input: BlasTensor) -> BlasTensor {
+        match op {
+            BlasOpCode::Cast(dtype, mode) => self.cast_owned(input, dtype, mode),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // a cast to the tensor's own type hands the operand back untouched
+    pub fn cast_owned(&self, input: BlasTensor, dtype: DType, mode: CastMode) -> BlasTensor {
+        if input.dtype() == dtype {
+            return input;
+        }
+        cast(&input.as_view(), dtype, mode)
+    }
+
+    // copies the window into a new tensor of the target type
+    pub fn cast_view(&self, input: &BlasTensorView, dtype: DType, mode: CastMode) -> BlasTensor {
+        if input.dtype() == dtype {
+            return input.to_owned();
+        }
+        cast(input, dtype, mode)
+    }
+
+    // borrows operands already of dtype, implicit promotion always saturates
+    pub(crate) fn promote_to<'a>(
+        &self,
+        input: &'a BlasTensor,
+        dtype: DType,
+    ) -> Cow<'a, BlasTensor> {
+        if input.dtype() == dtype {
+            Cow::Borrowed(input)
+        } else {
+            Cow::Owned(cast(&input.as_view(), dtype, CastMode::Saturating))
+        }
+    }
+}
+
+fn cast(input: &BlasTensorView, dtype: DType, mode: CastMode) -> BlasTensor {
+    with_any_dtype!(input.dtype(), S => with_any_dtype!(dtype, D => {
+        let out_data = input.view::<S>().mapv(|x| D::cast_from(x.as_f64(), mode));
+        BlasTensor::from_array(out_data)
+    }))
+}
+
+// every element type round-trips through f64 exactly, so casts go via f64
+pub(crate) trait CastFrom {
+    fn cast_from(value: f64, mode: CastMode) -> Self;
+}
+
+impl CastFrom for f32 {
+    fn cast_from(value: f64, _mode: CastMode) -> Self {
+        value as f32
+    }
+}
+
+impl CastFrom for f64 {
+    fn cast_from(value: f64, _mode: CastMode) -> Self {
+        value
+    }
+}
+
+macro_rules! impl_cast_from_int {
+    ($elem:ty) => {
+        impl CastFrom for $elem {
+            // `as` from a float already saturates and maps NaN to 0
+            fn cast_from(value: f64, mode: CastMode) -> Self {
+                match mode {
+                    CastMode::Saturating => value as $elem,
+                    CastMode::Truncating => (value as i64) as $elem,
+                }
+            }
+        }
+    };
+}
+
+impl_cast_from_int!(i32);
+impl_cast_from_int!(i8);
+
+impl CastFrom for bool {
+    fn cast_from(value: f64, _mode: CastMode) -> Self {
+        value != 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_promote_lattice() {
+        assert_eq!(DType::Bool.promote(DType::Int8), DType::Int8);
+        assert_eq!(DType::Int8.promote(DType::Int32), DType::Int32);
+        assert_eq!(DType::Int32.promote(DType::Float), DType::Float);
+        assert_eq!(DType::Double.promote(DType::Float), DType::Double);
+        assert_eq!(DType::Int32.promote(DType::Int32), DType::Int32);
+    }
+
+    #[test]
+    fn test_cast_modes() {
+        let exec = BlasExecutor::new();
+        let a = || BlasTensor::from_vec(vec![300.7, -200.2, 1.9, -1.9, f32::NAN]);
+
+        let c = exec.cast_owned(a(), DType::Int8, CastMode::Saturating);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i8(vec![127, -128, 1, -1, 0], vec![5])
+        );
+
+        let op = BlasOpCode::Cast(DType::Int8, CastMode::Truncating);
+        let c = exec.cast_compute_owned(op, a());
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i8(vec![44, 56, 1, -1, 0], vec![5])
+        );
+    }
+
+    #[test]
+    fn test_cast_bool() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![0, 3, -1, 0], vec![2, 2]);
+        let c = exec.cast_owned(a, DType::Bool, CastMode::Saturating);
+        let cref = BlasTensor::from_vec_shape_bool(vec![false, true, true, false], vec![2, 2]);
+        assert_eq!(c, cref);
+
+        let c = exec.cast_owned(c, DType::Double, CastMode::Saturating);
+        let cref = BlasTensor::from_vec_shape_f64(vec![0.0, 1.0, 1.0, 0.0], vec![2, 2]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_binary_compute_promoted() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4], vec![2, 1, 2]);
+        let b = BlasTensor::from_vec_shape(vec![0.5; 4], vec![2, 1, 2]);
+        let c = exec.binary_compute_owned(BlasOpCode::Add, a, b);
+        let cref = BlasTensor::from_vec_shape(vec![1.5, 2.5, 3.5, 4.5], vec![2, 1, 2]);
+        assert_eq!(c, cref);
+
+        let a = BlasTensor::from_vec_shape_i8(vec![100, -100], vec![2]);
+        let b = BlasTensor::from_vec_shape_i32(vec![3, 3], vec![2]);
+        let c = exec.binary_compute_owned(BlasOpCode::Mul, a, b);
+        assert_eq!(c, BlasTensor::from_vec_shape_i32(vec![300, -300], vec![2]));
+
+        let a = BlasTensor::from_vec_shape(vec![1.0, 3.0], vec![2]);
+        let b = BlasTensor::from_vec_shape_f64(vec![4.0, 4.0], vec![2]);
+        let c = exec.binary_compute_owned(BlasOpCode::Div, a, b);
+        assert_eq!(c, BlasTensor::from_vec_shape_f64(vec![0.25, 0.75], vec![2]));
+    }
+
+    #[test]
+    fn test_operators_and_compare_promoted() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3], vec![3]);
+        let b = BlasTensor::from_vec_shape_f64(vec![1.5, 1.5, 1.5], vec![3]);
+        let c = &a - &b;
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_f64(vec![-0.5, 0.5, 1.5], vec![3])
+        );
+
+        let c = exec.compare_compute_owned(BlasOpCode::Gt, a, b);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_bool(vec![false, true, true], vec![3])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "operands' shapes not match")]
+    fn test_binary_compute_promoted_shape_mismatch() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2], vec![2]);
+        let b = BlasTensor::from_vec_shape(vec![1.0, 2.0], vec![1, 2]);
+        exec.binary_compute_owned(BlasOpCode::Add, a, b);
+    }
+}
@@ -0,0 +1,189 @@
+use ndarray::Zip;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{
+    check_operands, check_shapes, with_any_dtype, BlasTensor, DType, TensorElement,
+};
+
+// operands must share shape, mixed types are promoted by DType::promote;
+// LogicalNot is a unary op and goes through unary_compute_owned/inplace
+impl BlasExecutor {
+    pub fn compare_compute_owned(
+        &self,
+        op: BlasOpCode,
+        lhs: BlasTensor,
+        rhs: BlasTensor,
+    ) -> BlasTensor {
+        check_shapes(&lhs.shape, &rhs.shape);
+        let dtype = lhs.dtype().promote(rhs.dtype());
+        let lhs = self.promote_to(&lhs, dtype);
+        let rhs = self.promote_to(&rhs, dtype);
+        with_any_dtype!(dtype, T => compare::<T>(op, &lhs, &rhs))
+    }
+
+    pub fn logical_compute_owned(
+        &self,
+        op: BlasOpCode,
+        lhs: BlasTensor,
+        rhs: BlasTensor,
+    ) -> BlasTensor {
+        check_operands(&lhs, &rhs);
+        if lhs.dtype() != DType::Bool {
+            panic!("logical opcodes only support Bool tensors");
+        }
+        let logical: fn(bool, bool) -> bool = match op {
+            BlasOpCode::LogicalAnd => |x, y| x && y,
+            BlasOpCode::LogicalOr => |x, y| x || y,
+            _ => panic!("not wired opcode"),
+        };
+        let out_data = Zip::from(&lhs.view::<bool>())
+            .and(&rhs.view::<bool>())
+            .apply_collect(|&x, &y| logical(x, y));
+        BlasTensor::from_array(out_data)
+    }
+
+    pub fn ternary_compute_owned(
+        &self,
+        op: BlasOpCode,
+        cond: BlasTensor,
+        lhs: BlasTensor,
+        rhs: BlasTensor,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::Where => self.where_owned(cond, lhs, rhs),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // elementwise select, result takes the promoted type of lhs/rhs
+    pub fn where_owned(&self, cond: BlasTensor, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_shapes(&lhs.shape, &rhs.shape);
+        check_shapes(&cond.shape, &lhs.shape);
+        if cond.dtype() != DType::Bool {
+            panic!("condition of where must be a Bool tensor");
+        }
+        let dtype = lhs.dtype().promote(rhs.dtype());
+        let lhs = self.promote_to(&lhs, dtype);
+        let rhs = self.promote_to(&rhs, dtype);
+        with_any_dtype!(dtype, T => {
+            let out_data = Zip::from(&cond.view::<bool>())
+                .and(&lhs.view::<T>())
+                .and(&rhs.view::<T>())
+                .apply_collect(|&c, &x, &y| if c { x } else { y });
+            BlasTensor::from_array(out_data)
+        })
+    }
+}
+
+fn compare<T: TensorElement + PartialOrd>(
+    op: BlasOpCode,
+    lhs: &BlasTensor,
+    rhs: &BlasTensor,
+) -> BlasTensor {
+    let compare: fn(&T, &T) -> bool = match op {
+        BlasOpCode::Eq => |x, y| x == y,
+        BlasOpCode::Ne => |x, y| x != y,
+        BlasOpCode::Lt => |x, y| x < y,
+        BlasOpCode::Le => |x, y| x <= y,
+        BlasOpCode::Gt => |x, y| x > y,
+        BlasOpCode::Ge => |x, y| x >= y,
+        _ => panic!("not wired opcode"),
+    };
+    let out_data = Zip::from(&lhs.view::<T>())
+        .and(&rhs.view::<T>())
+        .apply_collect(compare);
+    BlasTensor::from_array(out_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_compute_owned_f32() {
+        let exec = BlasExecutor::new();
+        let lhs = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let rhs = vec![6.0, 2.0, 4.0, 3.0, 5.0, 1.0];
+        let cases = vec![
+            (BlasOpCode::Eq, vec![false, true, false, false, true, false]),
+            (BlasOpCode::Ne, vec![true, false, true, true, false, true]),
+            (BlasOpCode::Lt, vec![true, false, true, false, false, false]),
+            (BlasOpCode::Le, vec![true, true, true, false, true, false]),
+            (BlasOpCode::Gt, vec![false, false, false, true, false, true]),
+            (BlasOpCode::Ge, vec![false, true, false, true, true, true]),
+        ];
+        for (op, expected) in cases {
+            let a = BlasTensor::from_vec_shape(lhs.clone(), vec![2, 3]);
+            let b = BlasTensor::from_vec_shape(rhs.clone(), vec![2, 3]);
+            let c = exec.compare_compute_owned(op, a, b);
+            assert_eq!(c, BlasTensor::from_vec_shape_bool(expected, vec![2, 3]));
+        }
+    }
+
+    #[test]
+    fn test_compare_compute_owned_i32_rank3() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, -2, 3, 0], vec![2, 1, 2]);
+        let b = BlasTensor::from_vec_shape_i32(vec![0, 0, 0, 0], vec![2, 1, 2]);
+        let c = exec.compare_compute_owned(BlasOpCode::Gt, a, b);
+        assert_eq!(c.shape(), [2, 1, 2]);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_bool(vec![true, false, true, false], vec![2, 1, 2])
+        );
+    }
+
+    #[test]
+    fn test_logical_compute_owned() {
+        let exec = BlasExecutor::new();
+        let a = || BlasTensor::from_vec_shape_bool(vec![true, true, false, false], vec![2, 2]);
+        let b = || BlasTensor::from_vec_shape_bool(vec![true, false, true, false], vec![2, 2]);
+
+        let c = exec.logical_compute_owned(BlasOpCode::LogicalAnd, a(), b());
+        let cref = BlasTensor::from_vec_shape_bool(vec![true, false, false, false], vec![2, 2]);
+        assert_eq!(c, cref);
+
+        let c = exec.logical_compute_owned(BlasOpCode::LogicalOr, a(), b());
+        let cref = BlasTensor::from_vec_shape_bool(vec![true, true, true, false], vec![2, 2]);
+        assert_eq!(c, cref);
+
+        let c = exec.unary_compute_owned(BlasOpCode::LogicalNot, a());
+        let cref = BlasTensor::from_vec_shape_bool(vec![false, false, true, true], vec![2, 2]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_where_owned_relu_backward() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::from_vec_shape(vec![-1.0, 2.0, 0.0, 3.0], vec![2, 2]);
+        let grad = BlasTensor::from_vec_shape(vec![0.1, 0.2, 0.3, 0.4], vec![2, 2]);
+        let mask = exec.compare_compute_owned(BlasOpCode::Gt, x, BlasTensor::zeros(vec![2, 2]));
+        let c = exec.ternary_compute_owned(
+            BlasOpCode::Where,
+            mask,
+            grad,
+            BlasTensor::zeros(vec![2, 2]),
+        );
+        let cref = BlasTensor::from_vec_shape(vec![0.0, 0.2, 0.0, 0.4], vec![2, 2]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    #[should_panic(expected = "operands' shapes not match")]
+    fn test_compare_shape_mismatch() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::zeros(vec![2, 3]);
+        let b = BlasTensor::zeros(vec![3, 2]);
+        exec.compare_compute_owned(BlasOpCode::Eq, a, b);
+    }
+
+    #[test]
+    #[should_panic(expected = "logical opcodes only support Bool tensors")]
+    fn test_logical_compute_non_bool() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::zeros(vec![2, 2]);
+        let b = BlasTensor::zeros(vec![2, 2]);
+        exec.logical_compute_owned(BlasOpCode::LogicalAnd, a, b);
+    }
+}
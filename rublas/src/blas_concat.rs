@@ -0,0 +1,264 @@
+use ndarray::prelude::*;
+
+use crate::blas_error::{BlasError, BlasResult};
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_any_dtype, BlasTensor};
+
+// Joining and splitting along a logical axis. The number and shapes of the
+// operands are only known at runtime, so invalid ones come back as a
+// BlasError instead of a panic.
+impl BlasExecutor {
+    pub fn join_compute_owned(
+        &self,
+        op: BlasOpCode,
+        inputs: &[BlasTensor],
+        axis: usize,
+    ) -> BlasResult<BlasTensor> {
+        match op {
+            BlasOpCode::Concat => self.concat_owned(inputs, axis),
+            BlasOpCode::Stack => self.stack_owned(inputs, axis),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // sizes are ignored by Chunk
+    pub fn split_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: &BlasTensor,
+        axis: usize,
+        sizes: &[usize],
+    ) -> BlasResult<Vec<BlasTensor>> {
+        match op {
+            BlasOpCode::Split => self.split_owned(input, axis, sizes),
+            BlasOpCode::Chunk(chunks) => self.chunk_owned(input, axis, chunks),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // inputs agree on every dim but axis
+    pub fn concat_owned(&self, inputs: &[BlasTensor], axis: usize) -> BlasResult<BlasTensor> {
+        let first = check_joinable(inputs, |shape| {
+            let mut shape = shape.to_vec();
+            if axis < shape.len() {
+                shape[axis] = 0;
+            }
+            shape
+        })?;
+        check_axis(axis, first.ndims())?;
+        with_any_dtype!(first.dtype(), T => {
+            let views: Vec<_> = inputs.iter().map(|input| input.view::<T>()).collect();
+            Ok(BlasTensor::from_array(ndarray::concatenate(Axis(axis), &views).unwrap()))
+        })
+    }
+
+    // inputs share their shape and get joined along a new axis
+    pub fn stack_owned(&self, inputs: &[BlasTensor], axis: usize) -> BlasResult<BlasTensor> {
+        let first = check_joinable(inputs, |shape| shape.to_vec())?;
+        check_axis(axis, first.ndims() + 1)?;
+        check_rank(first.ndims() + 1)?;
+        with_any_dtype!(first.dtype(), T => {
+            let views: Vec<_> = inputs
+                .iter()
+                .map(|input| input.view::<T>().insert_axis(Axis(axis)))
+                .collect();
+            Ok(BlasTensor::from_array(ndarray::concatenate(Axis(axis), &views).unwrap()))
+        })
+    }
+
+    // sizes must add up to the dim of axis, each output keeps the rank
+    pub fn split_owned(
+        &self,
+        input: &BlasTensor,
+        axis: usize,
+        sizes: &[usize],
+    ) -> BlasResult<Vec<BlasTensor>> {
+        check_axis(axis, input.ndims())?;
+        let dim = input.shape[axis];
+        if sizes.is_empty() || sizes.iter().sum::<usize>() != dim {
+            return Err(BlasError::InvalidSplit {
+                dim,
+                sizes: sizes.to_vec(),
+            });
+        }
+        let mut start = 0;
+        let outputs = sizes
+            .iter()
+            .map(|&size| {
+                let output = input.narrow(axis, start, size).to_owned();
+                start += size;
+                output
+            })
+            .collect();
+        Ok(outputs)
+    }
+
+    // chunks of ceil(dim / chunks) elements, the last one may be smaller and
+    // fewer chunks come back when the dim is too short to fill them all
+    pub fn chunk_owned(
+        &self,
+        input: &BlasTensor,
+        axis: usize,
+        chunks: usize,
+    ) -> BlasResult<Vec<BlasTensor>> {
+        check_axis(axis, input.ndims())?;
+        let dim = input.shape[axis];
+        if chunks == 0 || dim == 0 {
+            return Err(BlasError::InvalidSplit {
+                dim,
+                sizes: vec![0; chunks],
+            });
+        }
+        let size = dim.div_ceil(chunks);
+        let mut sizes = vec![size; dim / size];
+        if !dim.is_multiple_of(size) {
+            sizes.push(dim % size);
+        }
+        self.split_owned(input, axis, &sizes)
+    }
+}
+
+// all inputs share the dtype of the first one and the same shape once
+// masked by key; returns the first input
+fn check_joinable<F>(inputs: &[BlasTensor], key: F) -> BlasResult<&BlasTensor>
+where
+    F: Fn(&[usize]) -> Vec<usize>,
+{
+    let first = inputs.first().ok_or(BlasError::EmptyOperands)?;
+    for input in &inputs[1..] {
+        if input.dtype() != first.dtype() {
+            return Err(BlasError::TypeMismatch(first.dtype(), input.dtype()));
+        }
+        if input.ndims() != first.ndims() || key(&input.shape) != key(&first.shape) {
+            return Err(BlasError::ShapeMismatch(
+                first.shape.clone(),
+                input.shape.clone(),
+            ));
+        }
+    }
+    Ok(first)
+}
+
+fn check_axis(axis: usize, ndims: usize) -> BlasResult<()> {
+    if axis >= ndims {
+        return Err(BlasError::AxisOutOfRange { axis, ndims });
+    }
+    Ok(())
+}
+
+fn check_rank(ndims: usize) -> BlasResult<()> {
+    if ndims > 4 {
+        return Err(BlasError::UnsupportedRank(ndims));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blas_tensor::DType;
+
+    #[test]
+    fn test_concat_owned() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let b = BlasTensor::from_vec_shape(vec![5.0, 6.0], vec![2, 1]);
+        let c = exec.concat_owned(&[a.clone(), b], 1).unwrap();
+        let cref = BlasTensor::from_vec_shape(vec![1.0, 2.0, 5.0, 3.0, 4.0, 6.0], vec![2, 3]);
+        assert_eq!(c, cref);
+
+        let c = exec
+            .join_compute_owned(BlasOpCode::Concat, &[a.clone(), a], 0)
+            .unwrap();
+        assert_eq!(c.shape(), [4, 2]);
+        assert_eq!(c.view::<f32>()[[3, 1]], 4.0);
+    }
+
+    #[test]
+    fn test_stack_owned() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3], vec![3]);
+        let b = BlasTensor::from_vec_shape_i32(vec![4, 5, 6], vec![3]);
+        let c = exec.stack_owned(&[a.clone(), b.clone()], 0).unwrap();
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4, 5, 6], vec![2, 3])
+        );
+
+        let c = exec
+            .join_compute_owned(BlasOpCode::Stack, &[a, b], 1)
+            .unwrap();
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![1, 4, 2, 5, 3, 6], vec![3, 2])
+        );
+    }
+
+    #[test]
+    fn test_split_fused_qkv() {
+        let exec = BlasExecutor::new();
+        let qkv = BlasTensor::from_vec_shape((0..18).map(|x| x as f32).collect(), vec![1, 2, 9]);
+        let parts = exec
+            .split_compute_owned(BlasOpCode::Split, &qkv, 2, &[3, 3, 3])
+            .unwrap();
+        assert_eq!(parts.len(), 3);
+        let k = BlasTensor::from_vec_shape(vec![3.0, 4.0, 5.0, 12.0, 13.0, 14.0], vec![1, 2, 3]);
+        assert_eq!(parts[1], k);
+        assert_eq!(exec.concat_owned(&parts, 2).unwrap(), qkv);
+    }
+
+    #[test]
+    fn test_chunk_owned() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i8((0..5).collect(), vec![5]);
+        let parts = exec
+            .split_compute_owned(BlasOpCode::Chunk(2), &a, 0, &[])
+            .unwrap();
+        assert_eq!(
+            parts[0],
+            BlasTensor::from_vec_shape_i8(vec![0, 1, 2], vec![3])
+        );
+        assert_eq!(parts[1], BlasTensor::from_vec_shape_i8(vec![3, 4], vec![2]));
+
+        let parts = exec.chunk_owned(&a, 0, 4).unwrap();
+        assert_eq!(parts.len(), 3);
+    }
+
+    #[test]
+    fn test_shape_errors() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::zeros(vec![2, 3]);
+        let b = BlasTensor::zeros(vec![3, 3]);
+        assert_eq!(exec.concat_owned(&[], 0), Err(BlasError::EmptyOperands));
+        assert_eq!(
+            exec.concat_owned(&[a.clone(), b.clone()], 1),
+            Err(BlasError::ShapeMismatch(vec![2, 3], vec![3, 3]))
+        );
+        assert!(exec.concat_owned(&[a.clone(), b.clone()], 0).is_ok());
+        assert_eq!(
+            exec.stack_owned(&[a.clone(), b], 0),
+            Err(BlasError::ShapeMismatch(vec![2, 3], vec![3, 3]))
+        );
+        assert_eq!(
+            exec.concat_owned(&[a.clone(), BlasTensor::zeros_double(vec![2, 3])], 0),
+            Err(BlasError::TypeMismatch(DType::Float, DType::Double))
+        );
+        assert_eq!(
+            exec.concat_owned(&[a.clone(), a.clone()], 2),
+            Err(BlasError::AxisOutOfRange { axis: 2, ndims: 2 })
+        );
+        assert_eq!(
+            exec.split_owned(&a, 1, &[1, 1]),
+            Err(BlasError::InvalidSplit {
+                dim: 3,
+                sizes: vec![1, 1]
+            })
+        );
+        let d4 = BlasTensor::zeros(vec![1, 1, 1, 1]);
+        assert_eq!(
+            exec.stack_owned(&[d4], 0),
+            Err(BlasError::UnsupportedRank(5))
+        );
+    }
+}
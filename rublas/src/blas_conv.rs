@@ -0,0 +1,635 @@
+use ndarray::prelude::*;
+use ndarray::Slice;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{BlasTensor, DType, Layout, TensorElement};
+
+// Convolution hyper parameters as [h, w] pairs; conv1d only reads the first
+// entry of each pair. The input channels and the output channels are split
+// into groups, and each output group sees only its input group.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ConvParams {
+    pub stride: [usize; 2],
+    pub padding: [usize; 2],
+    pub dilation: [usize; 2],
+    pub groups: usize,
+}
+
+impl Default for ConvParams {
+    fn default() -> Self {
+        ConvParams {
+            stride: [1, 1],
+            padding: [0, 0],
+            dilation: [1, 1],
+            groups: 1,
+        }
+    }
+}
+
+// Activations are channel-last NWC/NHWC, like untagged rank-3/4 tensors are
+// taken to be, unless tagged ChannelsFirst for NCW/NCHW; the output keeps
+// the input's tag. Weights are [out, in / groups, (kh,) kw]
+// whatever the activation layout. Every group is lowered to one f32 gemm
+// over the im2col matrix of the whole batch.
+impl BlasExecutor {
+    pub fn conv_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: &BlasTensor,
+        weight: &BlasTensor,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::Conv1d(params) => self.conv1d_owned(input, weight, params),
+            BlasOpCode::Conv2d(params) => self.conv2d_owned(input, weight, params),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // shape is the forward input's shape for ConvBackwardData, or the
+    // weight's shape for ConvBackwardWeight; operand is the other forward
+    // operand, the rank of grad_out picks conv1d or conv2d
+    pub fn conv_backward_compute_owned(
+        &self,
+        op: BlasOpCode,
+        grad_out: &BlasTensor,
+        operand: &BlasTensor,
+        shape: &[usize],
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::ConvBackwardData(params) => {
+                self.conv_backward_data_owned(grad_out, operand, shape, params)
+            }
+            BlasOpCode::ConvBackwardWeight(params) => {
+                self.conv_backward_weight_owned(grad_out, operand, shape, params)
+            }
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    pub fn conv1d_owned(
+        &self,
+        input: &BlasTensor,
+        weight: &BlasTensor,
+        params: ConvParams,
+    ) -> BlasTensor {
+        check_rank(input, 3);
+        check_rank(weight, 3);
+        self.conv(input, weight, params)
+    }
+
+    pub fn conv2d_owned(
+        &self,
+        input: &BlasTensor,
+        weight: &BlasTensor,
+        params: ConvParams,
+    ) -> BlasTensor {
+        check_rank(input, 4);
+        check_rank(weight, 4);
+        self.conv(input, weight, params)
+    }
+
+    // gradient w.r.t. the forward input, returned in grad_out's layout
+    pub fn conv_backward_data_owned(
+        &self,
+        grad_out: &BlasTensor,
+        weight: &BlasTensor,
+        input_shape: &[usize],
+        params: ConvParams,
+    ) -> BlasTensor {
+        let ndims = grad_out.ndims();
+        check_rank(grad_out, ndims);
+        check_rank(weight, ndims);
+        check_shape_rank(input_shape, ndims);
+        let x_dims = nchw_dims(input_shape, grad_out.layout);
+        let w_dims = nchw_dims(&weight.shape(), Layout::ChannelsFirst);
+        let geom = Geometry::new(params, ndims, x_dims, w_dims);
+        let dy = nchw_view(grad_out);
+        geom.check_output(dy.shape(), x_dims[0], w_dims[0]);
+
+        let [n, _, h, w] = x_dims;
+        let (cg, og) = (w_dims[1], w_dims[0] / params.groups);
+        let w_matrix = weight_matrix(weight);
+        let mut dx = Array4::<f32>::zeros([n, x_dims[1], h, w]);
+        for g in 0..params.groups {
+            let wg = w_matrix.slice_axis(Axis(0), Slice::from(g * og..(g + 1) * og));
+            let wg_t = BlasTensor::from_array(wg.t().as_standard_layout().into_owned().into_dyn());
+            let dyg = group_matrix(dy.slice_axis(Axis(1), Slice::from(g * og..(g + 1) * og)));
+            let cols = self.gemm_owned(wg_t, dyg);
+            let cols_view = cols.view::<f32>().into_dimensionality::<Ix2>().unwrap();
+            let mut dxg = dx.slice_axis_mut(Axis(1), Slice::from(g * cg..(g + 1) * cg));
+            for_each_tap(n, cg, &geom, |[row, col], idx| {
+                dxg[idx] += cols_view[[row, col]]
+            });
+            self.recycle(cols);
+        }
+        from_nchw(dx, ndims, grad_out.layout)
+    }
+
+    // gradient w.r.t. the weight, a row-major tensor of weight_shape
+    pub fn conv_backward_weight_owned(
+        &self,
+        grad_out: &BlasTensor,
+        input: &BlasTensor,
+        weight_shape: &[usize],
+        params: ConvParams,
+    ) -> BlasTensor {
+        let ndims = grad_out.ndims();
+        check_rank(grad_out, ndims);
+        check_rank(input, ndims);
+        check_shape_rank(weight_shape, ndims);
+        let x_dims = nchw_dims(&input.shape(), input.layout);
+        let w_dims = nchw_dims(weight_shape, Layout::ChannelsFirst);
+        let geom = Geometry::new(params, ndims, x_dims, w_dims);
+        let dy = nchw_view(grad_out);
+        geom.check_output(dy.shape(), x_dims[0], w_dims[0]);
+
+        let x = nchw_view(input);
+        let (cg, og) = (w_dims[1], w_dims[0] / params.groups);
+        let mut dw = Array2::<f32>::zeros([w_dims[0], cg * geom.kernel[0] * geom.kernel[1]]);
+        for g in 0..params.groups {
+            let xg = x.slice_axis(Axis(1), Slice::from(g * cg..(g + 1) * cg));
+            let cols_t = im2col(xg, &geom)
+                .reversed_axes()
+                .as_standard_layout()
+                .into_owned();
+            let dyg = group_matrix(dy.slice_axis(Axis(1), Slice::from(g * og..(g + 1) * og)));
+            let dwg = self.gemm_owned(dyg, BlasTensor::from_array(cols_t.into_dyn()));
+            dw.slice_axis_mut(Axis(0), Slice::from(g * og..(g + 1) * og))
+                .assign(&dwg.view::<f32>().into_dimensionality::<Ix2>().unwrap());
+            self.recycle(dwg);
+        }
+        BlasTensor::from_array(dw.into_shape(IxDyn(weight_shape)).unwrap())
+    }
+
+    fn conv(&self, input: &BlasTensor, weight: &BlasTensor, params: ConvParams) -> BlasTensor {
+        let ndims = input.ndims();
+        let x_dims = nchw_dims(&input.shape(), input.layout);
+        let w_dims = nchw_dims(&weight.shape(), Layout::ChannelsFirst);
+        let geom = Geometry::new(params, ndims, x_dims, w_dims);
+
+        let x = nchw_view(input);
+        let n = x_dims[0];
+        let (cg, og) = (w_dims[1], w_dims[0] / params.groups);
+        let [oh, ow] = geom.output;
+        let w_matrix = weight_matrix(weight);
+        let mut out = Array4::<f32>::zeros([n, w_dims[0], oh, ow]);
+        for g in 0..params.groups {
+            let xg = x.slice_axis(Axis(1), Slice::from(g * cg..(g + 1) * cg));
+            let cols = BlasTensor::from_array(im2col(xg, &geom).into_dyn());
+            let wg = w_matrix.slice_axis(Axis(0), Slice::from(g * og..(g + 1) * og));
+            let yg = self.gemm_owned(BlasTensor::from_array(wg.to_owned().into_dyn()), cols);
+            // [og, n * oh * ow] back to the batch-major [n, og, oh, ow]
+            let yg_view = yg.view::<f32>().into_shape([og, n, oh, ow]).unwrap();
+            out.slice_axis_mut(Axis(1), Slice::from(g * og..(g + 1) * og))
+                .assign(&yg_view.permuted_axes([1, 0, 2, 3]));
+            self.recycle(yg);
+        }
+        from_nchw(out, ndims, input.layout)
+    }
+}
+
+// output size and taps of a 2-D convolution or pooling window, rank-3 ops
+// run with a unit height and no stride, padding or dilation along it
+pub(crate) struct Geometry {
+    pub(crate) kernel: [usize; 2],
+    stride: [usize; 2],
+    padding: [usize; 2],
+    dilation: [usize; 2],
+    pub(crate) input: [usize; 2],
+    pub(crate) output: [usize; 2],
+}
+
+impl Geometry {
+    fn new(params: ConvParams, ndims: usize, x_dims: [usize; 4], w_dims: [usize; 4]) -> Self {
+        let groups = params.groups;
+        if groups == 0 || !x_dims[1].is_multiple_of(groups) || !w_dims[0].is_multiple_of(groups) {
+            panic!(
+                "{} groups not valid for {} input and {} output channels",
+                groups, x_dims[1], w_dims[0]
+            );
+        }
+        if x_dims[1] / groups != w_dims[1] {
+            panic!(
+                "weight expects {} channels per group, input has {}",
+                w_dims[1],
+                x_dims[1] / groups
+            );
+        }
+        Geometry::spatial(
+            ndims,
+            [x_dims[2], x_dims[3]],
+            [w_dims[2], w_dims[3]],
+            params.stride,
+            params.padding,
+            params.dilation,
+        )
+    }
+
+    // from the [h, w] pairs of conv/pool params, where rank-3 ops only use
+    // the first entry; input and kernel are already in 2-D form
+    pub(crate) fn spatial(
+        ndims: usize,
+        input: [usize; 2],
+        kernel: [usize; 2],
+        stride: [usize; 2],
+        padding: [usize; 2],
+        dilation: [usize; 2],
+    ) -> Self {
+        let (stride, padding, dilation) = (
+            lift(ndims, stride, 1),
+            lift(ndims, padding, 0),
+            lift(ndims, dilation, 1),
+        );
+        if stride.contains(&0) || dilation.contains(&0) {
+            panic!(
+                "stride and dilation must be positive, got {:?} and {:?}",
+                stride, dilation
+            );
+        }
+        let mut output = [0; 2];
+        for axis in 0..2 {
+            let span = dilation[axis] * (kernel[axis].max(1) - 1) + 1;
+            let padded = input[axis] + 2 * padding[axis];
+            if padded < span {
+                panic!(
+                    "kernel of span {} larger than padded input of size {}",
+                    span, padded
+                );
+            }
+            output[axis] = (padded - span) / stride[axis] + 1;
+        }
+        Geometry {
+            kernel,
+            stride,
+            padding,
+            dilation,
+            input,
+            output,
+        }
+    }
+
+    // grad_out must have the [n, out_channels, oh, ow] dims of the output
+    fn check_output(&self, dims: &[usize], batch: usize, channels: usize) {
+        let expected = [batch, channels, self.output[0], self.output[1]];
+        if dims != expected {
+            panic!(
+                "gradient of NCHW dims {:?} not match conv output {:?}",
+                dims, expected
+            );
+        }
+    }
+
+    // input position read by output position pos through kernel tap k, None
+    // when it falls into the zero padding
+    pub(crate) fn source(&self, axis: usize, pos: usize, k: usize) -> Option<usize> {
+        let src =
+            (pos * self.stride[axis] + k * self.dilation[axis]).checked_sub(self.padding[axis])?;
+        (src < self.input[axis]).then_some(src)
+    }
+}
+
+// a rank-3 op's [h, w] pair as unit height and its first entry as width
+pub(crate) fn lift(ndims: usize, pair: [usize; 2], unit: usize) -> [usize; 2] {
+    if ndims == 3 {
+        [unit, pair[0]]
+    } else {
+        pair
+    }
+}
+
+// calls f([row, col], [n, c, h, w]) for every im2col entry that reads an
+// input element; rows are (c, kh, kw) and columns (n, oh, ow)
+fn for_each_tap<F: FnMut([usize; 2], [usize; 4])>(
+    batch: usize,
+    channels: usize,
+    geom: &Geometry,
+    mut f: F,
+) {
+    let [kh, kw] = geom.kernel;
+    let [oh, ow] = geom.output;
+    for n in 0..batch {
+        for c in 0..channels {
+            for i in 0..kh {
+                for j in 0..kw {
+                    let row = (c * kh + i) * kw + j;
+                    for y in 0..oh {
+                        let Some(h) = geom.source(0, y, i) else {
+                            continue;
+                        };
+                        for x in 0..ow {
+                            if let Some(w) = geom.source(1, x, j) {
+                                f([row, (n * oh + y) * ow + x], [n, c, h, w]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn im2col(x: ArrayView4<'_, f32>, geom: &Geometry) -> Array2<f32> {
+    let (n, c) = (x.shape()[0], x.shape()[1]);
+    let rows = c * geom.kernel[0] * geom.kernel[1];
+    let mut cols = Array2::<f32>::zeros([rows, n * geom.output[0] * geom.output[1]]);
+    for_each_tap(n, c, geom, |idx, src| cols[idx] = x[src]);
+    cols
+}
+
+// one group of grad_out as the [og, n * oh * ow] gemm operand
+fn group_matrix(dy: ArrayView4<'_, f32>) -> BlasTensor {
+    let rows = dy.shape()[1];
+    let batch_last = dy
+        .permuted_axes([1, 0, 2, 3])
+        .as_standard_layout()
+        .into_owned();
+    let cols = batch_last.len() / rows.max(1);
+    BlasTensor::from_array(batch_last.into_shape([rows, cols]).unwrap().into_dyn())
+}
+
+// weight as [out, in / groups * kh * kw]
+fn weight_matrix(weight: &BlasTensor) -> Array2<f32> {
+    let view = weight.view::<f32>().as_standard_layout().into_owned();
+    let rows = weight.shape()[0];
+    let cols = weight.numel() / rows.max(1);
+    view.into_shape([rows, cols]).unwrap()
+}
+
+fn check_rank(tensor: &BlasTensor, ndims: usize) {
+    if tensor.dtype() != DType::Float {
+        panic!("conv only supports Float tensors, got {:?}", tensor.dtype());
+    }
+    if tensor.ndims() != ndims || !(3..=4).contains(&ndims) {
+        panic!(
+            "conv expects tensors with {} dims, got shape {:?}",
+            ndims,
+            tensor.shape()
+        );
+    }
+}
+
+fn check_shape_rank(shape: &[usize], ndims: usize) {
+    if shape.len() != ndims {
+        panic!("conv expects a shape with {} dims, got {:?}", ndims, shape);
+    }
+}
+
+// every layout but ChannelsFirst keeps the channels in the last dim
+pub(crate) fn channels_last(layout: Layout) -> bool {
+    layout != Layout::ChannelsFirst
+}
+
+// [n, c, h, w] of a conv shape in the given layout, unit height for rank 3
+pub(crate) fn nchw_dims(shape: &[usize], layout: Layout) -> [usize; 4] {
+    let mut dims = shape.to_vec();
+    if channels_last(layout) {
+        let channels = dims.pop().unwrap();
+        dims.insert(1, channels);
+    }
+    if dims.len() == 3 {
+        dims.insert(2, 1);
+    }
+    [dims[0], dims[1], dims[2], dims[3]]
+}
+
+pub(crate) fn nchw_view<T: TensorElement>(tensor: &BlasTensor) -> ArrayView4<'_, T> {
+    let mut view = tensor.view::<T>();
+    if channels_last(tensor.layout) {
+        let axes: &[usize] = if tensor.ndims() == 4 {
+            &[0, 3, 1, 2]
+        } else {
+            &[0, 2, 1]
+        };
+        view = view.permuted_axes(axes);
+    }
+    if tensor.ndims() == 3 {
+        view.insert_axis_inplace(Axis(2));
+    }
+    view.into_dimensionality::<Ix4>().unwrap()
+}
+
+// back from [n, c, h, w] to the rank and layout of the activations
+pub(crate) fn from_nchw<T: TensorElement>(
+    data: Array4<T>,
+    ndims: usize,
+    layout: Layout,
+) -> BlasTensor {
+    let mut data = data.into_dyn();
+    if channels_last(layout) {
+        data = data.permuted_axes(IxDyn(&[0, 2, 3, 1]));
+    }
+    if ndims == 3 {
+        let height = if channels_last(layout) { 1 } else { 2 };
+        data = data.index_axis_move(Axis(height), 0);
+    }
+    let out = BlasTensor::from_array(data);
+    match layout {
+        Layout::ChannelsFirst | Layout::ChannelsLast => out.with_layout(layout),
+        _ => out,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sequence(shape: Vec<usize>) -> BlasTensor {
+        let numel = shape.iter().product();
+        BlasTensor::from_vec_shape((0..numel).map(|x| (x % 7) as f32 - 3.0).collect(), shape)
+    }
+
+    // direct nested-loop convolution over NCHW
+    fn reference_conv2d(x: &BlasTensor, w: &BlasTensor, p: ConvParams) -> Array4<f32> {
+        let x = x.view::<f32>().into_dimensionality::<Ix4>().unwrap();
+        let w = w.view::<f32>().into_dimensionality::<Ix4>().unwrap();
+        let (n, c, h, wd) = x.dim();
+        let (o, cg, kh, kw) = w.dim();
+        let og = o / p.groups;
+        let oh = (h + 2 * p.padding[0] - p.dilation[0] * (kh - 1) - 1) / p.stride[0] + 1;
+        let ow = (wd + 2 * p.padding[1] - p.dilation[1] * (kw - 1) - 1) / p.stride[1] + 1;
+        let mut out = Array4::<f32>::zeros([n, o, oh, ow]);
+        for ((b, oc, y, xo), value) in out.indexed_iter_mut() {
+            let g = oc / og;
+            for ic in 0..cg {
+                for i in 0..kh {
+                    for j in 0..kw {
+                        let sy =
+                            (y * p.stride[0] + i * p.dilation[0]) as isize - p.padding[0] as isize;
+                        let sx =
+                            (xo * p.stride[1] + j * p.dilation[1]) as isize - p.padding[1] as isize;
+                        if sy >= 0 && sx >= 0 && (sy as usize) < h && (sx as usize) < wd {
+                            *value +=
+                                x[[b, g * cg + ic, sy as usize, sx as usize]] * w[[oc, ic, i, j]];
+                        }
+                    }
+                }
+            }
+        }
+        assert_eq!(c, cg * p.groups);
+        out
+    }
+
+    fn dot(lhs: &BlasTensor, rhs: &BlasTensor) -> f32 {
+        lhs.view::<f32>()
+            .iter()
+            .zip(rhs.view::<f32>().iter())
+            .map(|(a, b)| a * b)
+            .sum()
+    }
+
+    #[test]
+    fn test_conv2d_box_filter() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::from_vec_shape((1..=9).map(|x| x as f32).collect(), vec![1, 3, 3, 1]);
+        let w = BlasTensor::ones(vec![1, 1, 2, 2]);
+        let c = exec.conv_compute_owned(BlasOpCode::Conv2d(ConvParams::default()), &x, &w);
+        let cref = BlasTensor::from_vec_shape(vec![12.0, 16.0, 24.0, 28.0], vec![1, 2, 2, 1]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_conv2d_against_reference() {
+        let exec = BlasExecutor::new();
+        let x = sequence(vec![2, 4, 7, 6]).with_layout(Layout::ChannelsFirst);
+        let w = sequence(vec![6, 2, 3, 2]);
+        let params = ConvParams {
+            stride: [2, 1],
+            padding: [1, 2],
+            dilation: [1, 2],
+            groups: 2,
+        };
+        let c = exec.conv2d_owned(&x, &w, params);
+        let cref = BlasTensor::from_array(reference_conv2d(&x, &w, params).into_dyn());
+        assert!(c.all_close(&cref, 1e-4));
+    }
+
+    #[test]
+    fn test_conv2d_nhwc() {
+        let exec = BlasExecutor::new();
+        let nchw = sequence(vec![2, 3, 5, 5]).with_layout(Layout::ChannelsFirst);
+        let w = sequence(vec![4, 3, 3, 3]);
+        let params = ConvParams {
+            padding: [1, 1],
+            stride: [2, 2],
+            ..ConvParams::default()
+        };
+        let nhwc = exec.to_layout_owned(nchw.clone(), Layout::ChannelsLast);
+        let c = exec.conv2d_owned(&nhwc, &w, params);
+        assert_eq!(c.layout, Layout::ChannelsLast);
+        assert_eq!(c.shape(), [2, 3, 3, 4]);
+        let cref = exec.conv2d_owned(&nchw, &w, params);
+        assert!(exec
+            .to_layout_owned(c.clone(), Layout::ChannelsFirst)
+            .all_close(&cref, 1e-4));
+
+        // untagged activations are NHWC as well
+        let untagged = exec.to_layout_owned(nhwc, Layout::RowMajor);
+        let c_untagged = exec.conv2d_owned(&untagged, &w, params);
+        assert_eq!(c_untagged.layout, Layout::RowMajor);
+        assert!(c_untagged.all_close(&c, 0.0));
+    }
+
+    #[test]
+    fn test_conv1d() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0, 5.0], vec![1, 5, 1]);
+        let w = BlasTensor::from_vec_shape(vec![1.0, 0.0, -1.0], vec![1, 1, 3]);
+        let params = ConvParams {
+            stride: [2, 7],
+            padding: [1, 7],
+            ..ConvParams::default()
+        };
+        let c = exec.conv_compute_owned(BlasOpCode::Conv1d(params), &x, &w);
+        let cref = BlasTensor::from_vec_shape(vec![-2.0, -2.0, 4.0], vec![1, 3, 1]);
+        assert_eq!(c, cref);
+
+        // NWC with two channels, depthwise
+        let x = sequence(vec![2, 6, 2]).with_layout(Layout::ChannelsLast);
+        let w = sequence(vec![2, 1, 2]);
+        let params = ConvParams {
+            groups: 2,
+            ..ConvParams::default()
+        };
+        let c = exec.conv1d_owned(&x, &w, params);
+        assert_eq!(c.shape(), [2, 5, 2]);
+        let xv = x.view::<f32>();
+        let wv = w.view::<f32>();
+        let expected = xv[[1, 3, 1]] * wv[[1, 0, 0]] + xv[[1, 4, 1]] * wv[[1, 0, 1]];
+        assert_eq!(c.view::<f32>()[[1, 3, 1]], expected);
+    }
+
+    #[test]
+    fn test_conv_backward_adjoint() {
+        // <conv(x, w), dy> = <x, dx> = <w, dw> since conv is bilinear
+        let exec = BlasExecutor::new();
+        let params = ConvParams {
+            stride: [2, 2],
+            padding: [1, 0],
+            dilation: [2, 1],
+            groups: 2,
+        };
+        let x = BlasTensor::uniform(vec![2, 4, 7, 6], -1.0, 1.0).with_layout(Layout::ChannelsFirst);
+        let w = BlasTensor::uniform(vec![4, 2, 2, 3], -1.0, 1.0);
+        let y = exec.conv2d_owned(&x, &w, params);
+        let dy =
+            BlasTensor::uniform(y.shape().to_vec(), -1.0, 1.0).with_layout(Layout::ChannelsFirst);
+
+        let op = BlasOpCode::ConvBackwardData(params);
+        let dx = exec.conv_backward_compute_owned(op, &dy, &w, &x.shape());
+        assert_eq!(dx.shape(), x.shape());
+        let op = BlasOpCode::ConvBackwardWeight(params);
+        let dw = exec.conv_backward_compute_owned(op, &dy, &x, &w.shape());
+        assert_eq!(dw.shape(), w.shape());
+
+        let forward = dot(&y, &dy);
+        assert!((forward - dot(&x, &dx)).abs() < 1e-3);
+        assert!((forward - dot(&w, &dw)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_conv1d_backward_nwc() {
+        let exec = BlasExecutor::new();
+        let params = ConvParams {
+            stride: [2, 1],
+            padding: [2, 0],
+            ..ConvParams::default()
+        };
+        let x = BlasTensor::uniform(vec![3, 9, 2], -1.0, 1.0).with_layout(Layout::ChannelsLast);
+        let w = BlasTensor::uniform(vec![3, 2, 4], -1.0, 1.0);
+        let y = exec.conv1d_owned(&x, &w, params);
+        let dy =
+            BlasTensor::uniform(y.shape().to_vec(), -1.0, 1.0).with_layout(Layout::ChannelsLast);
+        let dx = exec.conv_backward_data_owned(&dy, &w, &x.shape(), params);
+        assert_eq!(dx.layout, Layout::ChannelsLast);
+        let dw = exec.conv_backward_weight_owned(&dy, &x, &w.shape(), params);
+
+        let forward = dot(&y, &dy);
+        assert!((forward - dot(&x, &dx)).abs() < 1e-3);
+        assert!((forward - dot(&w, &dw)).abs() < 1e-3);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "gradient of NCHW dims [2, 4, 3, 3] not match conv output [1, 4, 3, 3]"
+    )]
+    fn test_conv_backward_grad_batch_mismatch() {
+        let exec = BlasExecutor::new();
+        let w = BlasTensor::zeros(vec![4, 2, 3, 3]);
+        let dy = BlasTensor::zeros(vec![2, 3, 3, 4]);
+        exec.conv_backward_data_owned(&dy, &w, &[1, 5, 5, 2], ConvParams::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "weight expects 3 channels per group, input has 2")]
+    fn test_conv2d_channel_mismatch() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::zeros(vec![1, 5, 5, 4]);
+        let w = BlasTensor::zeros(vec![2, 3, 3, 3]);
+        let params = ConvParams {
+            groups: 2,
+            ..ConvParams::default()
+        };
+        exec.conv2d_owned(&x, &w, params);
+    }
+}
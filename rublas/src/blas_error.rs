@@ -0,0 +1,46 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::blas_tensor::DType;
+
+// recoverable errors of ops whose operands are only known at runtime, e.g.
+// the list of tensors given to concat; misuse of the fixed-arity opcodes
+// still panics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlasError {
+    EmptyOperands,
+    TypeMismatch(DType, DType),
+    ShapeMismatch(Vec<usize>, Vec<usize>),
+    AxisOutOfRange { axis: usize, ndims: usize },
+    UnsupportedRank(usize),
+    InvalidSplit { dim: usize, sizes: Vec<usize> },
+}
+
+pub type BlasResult<T> = Result<T, BlasError>;
+
+impl fmt::Display for BlasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlasError::EmptyOperands => write!(f, "no operand tensors given"),
+            BlasError::TypeMismatch(lhs, rhs) => {
+                write!(f, "operands' types not match: {:?} vs {:?}", lhs, rhs)
+            }
+            BlasError::ShapeMismatch(lhs, rhs) => {
+                write!(f, "operands' shapes not match: {:?} vs {:?}", lhs, rhs)
+            }
+            BlasError::AxisOutOfRange { axis, ndims } => write!(
+                f,
+                "axis {} out of range for tensor with {} dims",
+                axis, ndims
+            ),
+            BlasError::UnsupportedRank(ndims) => {
+                write!(f, "not support tensor with {} dims", ndims)
+            }
+            BlasError::InvalidSplit { dim, sizes } => {
+                write!(f, "cannot split dim of size {} into {:?}", dim, sizes)
+            }
+        }
+    }
+}
+
+impl Error for BlasError {}
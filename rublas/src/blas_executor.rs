@@ -1,16 +1,31 @@
 use ndarray::linalg::general_mat_mul;
-use ndarray::linalg::general_mat_vec_mul;
+use ndarray::{Array, ArrayD, Dimension};
+use std::sync::Mutex;
 
+use crate::blas_buffer::BufferPool;
 use crate::blas_opcode::BlasOpCode;
-use crate::blas_tensor::{BlasTensor, TensorKind};
-use crate::prelude::Array2;
+use crate::blas_tensor::{
+    check_shapes, with_dtype, BlasTensor, CastMode, DType, Layout, TensorElement, TensorKind,
+};
+use crate::blas_view::BlasTensorView;
+use crate::prelude::{ArrayView2, Ix2};
 
 #[derive(Debug)]
-pub struct BlasExecutor {}
+pub struct BlasExecutor {
+    pub(crate) pool: Mutex<BufferPool>,
+}
+
+impl Default for BlasExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl BlasExecutor {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            pool: Mutex::new(BufferPool::default()),
+        }
     }
 
     pub fn binary_compute_owned(
@@ -20,6 +35,8 @@ impl BlasExecutor {
         rhs: BlasTensor,
     ) -> BlasTensor {
         match op {
+            // the typed opcodes take operands of their own type only, the
+            // untyped Add/Sub/Mul/Div below promote mixed types
             BlasOpCode::AddF => self.addf32_owned(lhs, rhs),
             BlasOpCode::SubF => self.subf32_owned(lhs, rhs),
             BlasOpCode::MulF => self.mulf32_owned(lhs, rhs),
@@ -31,10 +48,53 @@ impl BlasExecutor {
             BlasOpCode::MulI => self.muli32_owned(lhs, rhs),
             BlasOpCode::DivI => self.divi32_owned(lhs, rhs),
             BlasOpCode::GemmF => self.gemm_owned(lhs, rhs),
+            BlasOpCode::Add | BlasOpCode::Sub | BlasOpCode::Mul | BlasOpCode::Div => {
+                let mut out = lhs;
+                self.binary_compute_inplace(op, &mut out, &rhs);
+                out
+            }
             _ => panic!("not wired opcode"),
         }
     }
 
+    // lhs is overwritten with the result; operands of different types are
+    // first cast to DType::promote of both, so lhs may change its type
+    pub fn binary_compute_inplace(&self, op: BlasOpCode, lhs: &mut BlasTensor, rhs: &BlasTensor) {
+        self.binary_compute_inplace_view(op, lhs, &rhs.as_view())
+    }
+
+    // rhs is read through the view and only copied when it gets promoted
+    pub fn binary_compute_inplace_view(
+        &self,
+        op: BlasOpCode,
+        lhs: &mut BlasTensor,
+        rhs: &BlasTensorView,
+    ) {
+        check_shapes(&lhs.shape, rhs.shape());
+        let dtype = lhs.dtype().promote(rhs.dtype());
+        if lhs.dtype() != dtype {
+            *lhs = self.promote_to(lhs, dtype).into_owned();
+        }
+        let promoted;
+        let rhs = if rhs.dtype() == dtype {
+            rhs.clone()
+        } else {
+            promoted = self.cast_view(rhs, dtype, CastMode::Saturating);
+            promoted.as_view()
+        };
+        with_dtype!(dtype, T => {
+            let compute: fn(T, T) -> T = match op {
+                BlasOpCode::Add => |x, y| x + y,
+                BlasOpCode::Sub => |x, y| x - y,
+                BlasOpCode::Mul => |x, y| x * y,
+                BlasOpCode::Div => |x, y| x / y,
+                _ => panic!("not wired opcode"),
+            };
+            lhs.view_mut::<T>()
+                .zip_mut_with(&rhs.view::<T>(), |x, &y| *x = compute(*x, y))
+        })
+    }
+
     pub fn binary_compute_side_effect(
         &self,
         op: BlasOpCode,
@@ -54,26 +114,30 @@ impl BlasExecutor {
     }
 
     pub fn addi32_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_typed(&lhs, &rhs, DType::Int32);
+        if !same_storage(&lhs, &rhs) {
+            return self.zip_logical::<i32>(&lhs, &rhs, |x, y| x + y);
+        }
         match lhs.data {
             TensorKind::Int32Vector(ref _lhs) => match rhs.data {
                 TensorKind::Int32Vector(ref _rhs) => {
-                    let out_data = _lhs + _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x + y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
-                    };
-                    return out;
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
             TensorKind::Int32Matrix(ref _lhs) => match rhs.data {
                 TensorKind::Int32Matrix(ref _rhs) => {
-                    let out_data = _lhs + _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x + y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -82,26 +146,30 @@ impl BlasExecutor {
     }
 
     pub fn subi32_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_typed(&lhs, &rhs, DType::Int32);
+        if !same_storage(&lhs, &rhs) {
+            return self.zip_logical::<i32>(&lhs, &rhs, |x, y| x - y);
+        }
         match lhs.data {
             TensorKind::Int32Vector(ref _lhs) => match rhs.data {
                 TensorKind::Int32Vector(ref _rhs) => {
-                    let out_data = _lhs - _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x - y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
-                    };
-                    return out;
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
             TensorKind::Int32Matrix(ref _lhs) => match rhs.data {
                 TensorKind::Int32Matrix(ref _rhs) => {
-                    let out_data = _lhs - _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x - y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -110,26 +178,30 @@ impl BlasExecutor {
     }
 
     pub fn muli32_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_typed(&lhs, &rhs, DType::Int32);
+        if !same_storage(&lhs, &rhs) {
+            return self.zip_logical::<i32>(&lhs, &rhs, |x, y| x * y);
+        }
         match lhs.data {
             TensorKind::Int32Vector(ref _lhs) => match rhs.data {
                 TensorKind::Int32Vector(ref _rhs) => {
-                    let out_data = _lhs * _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x * y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
-                    };
-                    return out;
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
             TensorKind::Int32Matrix(ref _lhs) => match rhs.data {
                 TensorKind::Int32Matrix(ref _rhs) => {
-                    let out_data = _lhs * _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x * y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -138,26 +210,30 @@ impl BlasExecutor {
     }
 
     pub fn divi32_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_typed(&lhs, &rhs, DType::Int32);
+        if !same_storage(&lhs, &rhs) {
+            return self.zip_logical::<i32>(&lhs, &rhs, |x, y| x / y);
+        }
         match lhs.data {
             TensorKind::Int32Vector(ref _lhs) => match rhs.data {
                 TensorKind::Int32Vector(ref _rhs) => {
-                    let out_data = _lhs / _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x / y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
-                    };
-                    return out;
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
             TensorKind::Int32Matrix(ref _lhs) => match rhs.data {
                 TensorKind::Int32Matrix(ref _rhs) => {
-                    let out_data = _lhs / _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x / y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -166,26 +242,30 @@ impl BlasExecutor {
     }
 
     pub fn addf32_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_typed(&lhs, &rhs, DType::Float);
+        if !same_storage(&lhs, &rhs) {
+            return self.zip_logical::<f32>(&lhs, &rhs, |x, y| x + y);
+        }
         match lhs.data {
             TensorKind::FloatVector(ref _lhs) => match rhs.data {
                 TensorKind::FloatVector(ref _rhs) => {
-                    let out_data = _lhs + _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x + y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
-                    };
-                    return out;
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
             TensorKind::FloatMatrix(ref _lhs) => match rhs.data {
                 TensorKind::FloatMatrix(ref _rhs) => {
-                    let out_data = _lhs + _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x + y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -194,26 +274,30 @@ impl BlasExecutor {
     }
 
     pub fn subf32_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_typed(&lhs, &rhs, DType::Float);
+        if !same_storage(&lhs, &rhs) {
+            return self.zip_logical::<f32>(&lhs, &rhs, |x, y| x - y);
+        }
         match lhs.data {
             TensorKind::FloatVector(ref _lhs) => match rhs.data {
                 TensorKind::FloatVector(ref _rhs) => {
-                    let out_data = _lhs - _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x - y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
-                    };
-                    return out;
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
             TensorKind::FloatMatrix(ref _lhs) => match rhs.data {
                 TensorKind::FloatMatrix(ref _rhs) => {
-                    let out_data = _lhs - _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x - y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -222,26 +306,30 @@ impl BlasExecutor {
     }
 
     pub fn mulf32_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_typed(&lhs, &rhs, DType::Float);
+        if !same_storage(&lhs, &rhs) {
+            return self.zip_logical::<f32>(&lhs, &rhs, |x, y| x * y);
+        }
         match lhs.data {
             TensorKind::FloatVector(ref _lhs) => match rhs.data {
                 TensorKind::FloatVector(ref _rhs) => {
-                    let out_data = _lhs * _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x * y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
-                    };
-                    return out;
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
             TensorKind::FloatMatrix(ref _lhs) => match rhs.data {
                 TensorKind::FloatMatrix(ref _rhs) => {
-                    let out_data = _lhs * _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x * y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -250,26 +338,30 @@ impl BlasExecutor {
     }
 
     pub fn divf32_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
+        check_typed(&lhs, &rhs, DType::Float);
+        if !same_storage(&lhs, &rhs) {
+            return self.zip_logical::<f32>(&lhs, &rhs, |x, y| x / y);
+        }
         match lhs.data {
             TensorKind::FloatVector(ref _lhs) => match rhs.data {
                 TensorKind::FloatVector(ref _rhs) => {
-                    let out_data = _lhs / _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x / y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
-                    };
-                    return out;
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
             TensorKind::FloatMatrix(ref _lhs) => match rhs.data {
                 TensorKind::FloatMatrix(ref _rhs) => {
-                    let out_data = _lhs / _rhs;
-                    let mut out = BlasTensor {
+                    let out_data = self.zip_pooled(_lhs, _rhs, |x, y| x / y);
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -278,6 +370,11 @@ impl BlasExecutor {
     }
 
     pub fn addf32_side_effect(&self, lhs: &BlasTensor, rhs: &BlasTensor, out: &mut BlasTensor) {
+        check_typed(lhs, rhs, DType::Float);
+        if !same_storage(lhs, rhs) {
+            *out = self.zip_logical::<f32>(lhs, rhs, |x, y| x + y);
+            return;
+        }
         match lhs.data {
             TensorKind::FloatVector(ref _lhs) => match rhs.data {
                 TensorKind::FloatVector(ref _rhs) => {
@@ -285,6 +382,7 @@ impl BlasExecutor {
                     *out = BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
+                        layout: lhs.layout,
                     };
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
@@ -294,7 +392,8 @@ impl BlasExecutor {
                     let out_data = _lhs + _rhs;
                     *out = BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
                     };
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
@@ -304,6 +403,11 @@ impl BlasExecutor {
     }
 
     pub fn subf32_side_effect(&self, lhs: &BlasTensor, rhs: &BlasTensor, out: &mut BlasTensor) {
+        check_typed(lhs, rhs, DType::Float);
+        if !same_storage(lhs, rhs) {
+            *out = self.zip_logical::<f32>(lhs, rhs, |x, y| x - y);
+            return;
+        }
         match lhs.data {
             TensorKind::FloatVector(ref _lhs) => match rhs.data {
                 TensorKind::FloatVector(ref _rhs) => {
@@ -311,6 +415,7 @@ impl BlasExecutor {
                     *out = BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
+                        layout: lhs.layout,
                     };
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
@@ -320,7 +425,8 @@ impl BlasExecutor {
                     let out_data = _lhs - _rhs;
                     *out = BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
                     };
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
@@ -330,6 +436,11 @@ impl BlasExecutor {
     }
 
     pub fn mulf32_side_effect(&self, lhs: &BlasTensor, rhs: &BlasTensor, out: &mut BlasTensor) {
+        check_typed(lhs, rhs, DType::Float);
+        if !same_storage(lhs, rhs) {
+            *out = self.zip_logical::<f32>(lhs, rhs, |x, y| x * y);
+            return;
+        }
         match lhs.data {
             TensorKind::FloatVector(ref _lhs) => match rhs.data {
                 TensorKind::FloatVector(ref _rhs) => {
@@ -337,6 +448,7 @@ impl BlasExecutor {
                     *out = BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
+                        layout: lhs.layout,
                     };
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
@@ -346,7 +458,8 @@ impl BlasExecutor {
                     let out_data = _lhs * _rhs;
                     *out = BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
                     };
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
@@ -356,6 +469,11 @@ impl BlasExecutor {
     }
 
     pub fn divf32_side_effect(&self, lhs: &BlasTensor, rhs: &BlasTensor, out: &mut BlasTensor) {
+        check_typed(lhs, rhs, DType::Float);
+        if !same_storage(lhs, rhs) {
+            *out = self.zip_logical::<f32>(lhs, rhs, |x, y| x / y);
+            return;
+        }
         match lhs.data {
             TensorKind::FloatVector(ref _lhs) => match rhs.data {
                 TensorKind::FloatVector(ref _rhs) => {
@@ -363,6 +481,7 @@ impl BlasExecutor {
                     *out = BlasTensor {
                         data: TensorKind::from(out_data),
                         shape: vec![lhs.shape[0]],
+                        layout: lhs.layout,
                     };
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
@@ -372,7 +491,8 @@ impl BlasExecutor {
                     let out_data = _lhs / _rhs;
                     *out = BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
+                        shape: lhs.shape.clone(),
+                        layout: lhs.layout,
                     };
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
@@ -382,18 +502,31 @@ impl BlasExecutor {
     }
 
     // TODO add type check
-    // gemm with normal-layout mat * normal-layout mat; also consumes operands ownerships
+    // gemm over the logical matrices; ColMajor or padded operands are passed
+    // to BLAS as strided views instead of being copied; also consumes
+    // operands ownerships. The output is drawn from the executor's pool,
+    // callers hand it back through recycle when they are done with it
     pub fn gemm_owned(&self, lhs: BlasTensor, rhs: BlasTensor) -> BlasTensor {
         match lhs.data {
-            TensorKind::FloatMatrix(ref _lhs) => match rhs.data {
-                TensorKind::FloatMatrix(ref _rhs) => {
-                    let mut out_data = Array2::<f32>::zeros([lhs.shape[0], rhs.shape[1]]);
-                    general_mat_mul(1.0, &_lhs, &_rhs, 1.0, &mut out_data);
-                    let mut out = BlasTensor {
+            TensorKind::FloatMatrix(_) => match rhs.data {
+                TensorKind::FloatMatrix(_) => {
+                    let shape = [lhs.shape[0], rhs.shape[1]];
+                    let mut out_data = self
+                        .pooled_array::<f32>(&shape, 0.0)
+                        .into_dimensionality::<Ix2>()
+                        .unwrap();
+                    general_mat_mul(
+                        1.0,
+                        &matrix_view(&lhs),
+                        &matrix_view(&rhs),
+                        1.0,
+                        &mut out_data,
+                    );
+                    BlasTensor {
                         data: TensorKind::from(out_data),
-                        shape: vec![lhs.shape[0], rhs.shape[1]],
-                    };
-                    return out;
+                        shape: shape.to_vec(),
+                        layout: Layout::RowMajor,
+                    }
                 }
                 _ => panic!("rhs operand's type not compatible with return type"),
             },
@@ -401,13 +534,14 @@ impl BlasExecutor {
         }
     }
 
-    // gemm with normal-layout mat * normal-layout mat; also consumes operands ownerships
-    pub fn gemm_side_effect(&self, lhs: &BlasTensor, rhs: &BlasTensor, out: &mut BlasTensor) -> () {
+    // gemm over the logical matrices, accumulating into out in its own layout
+    pub fn gemm_side_effect(&self, lhs: &BlasTensor, rhs: &BlasTensor, out: &mut BlasTensor) {
         match out.data {
-            TensorKind::FloatMatrix(ref mut _out) => match lhs.data {
-                TensorKind::FloatMatrix(ref _lhs) => match rhs.data {
-                    TensorKind::FloatMatrix(ref _rhs) => {
-                        general_mat_mul(1.0, _lhs, _rhs, 1.0, _out);
+            TensorKind::FloatMatrix(_) => match lhs.data {
+                TensorKind::FloatMatrix(_) => match rhs.data {
+                    TensorKind::FloatMatrix(_) => {
+                        let mut _out = out.view_mut::<f32>().into_dimensionality::<Ix2>().unwrap();
+                        general_mat_mul(1.0, &matrix_view(lhs), &matrix_view(rhs), 1.0, &mut _out);
                     }
                     _ => panic!("rhs operand's type not compatible with return type"),
                 },
@@ -416,10 +550,63 @@ impl BlasExecutor {
             _ => panic!("return type not supported for this gemm"),
         }
     }
+
+    // elementwise over two equally shaped storages into a pooled buffer
+    fn zip_pooled<T: TensorElement, D: Dimension>(
+        &self,
+        lhs: &Array<T, D>,
+        rhs: &Array<T, D>,
+        f: fn(T, T) -> T,
+    ) -> Array<T, D> {
+        let mut buffer = self.take_buffer::<T>(lhs.len());
+        buffer.extend(lhs.iter().zip(rhs).map(|(&x, &y)| f(x, y)));
+        Array::from_shape_vec(lhs.raw_dim(), buffer).unwrap()
+    }
+
+    // elementwise over the logical views into a pooled buffer, for operands
+    // whose storages differ
+    fn zip_logical<T: TensorElement>(
+        &self,
+        lhs: &BlasTensor,
+        rhs: &BlasTensor,
+        f: fn(T, T) -> T,
+    ) -> BlasTensor {
+        check_shapes(&lhs.shape, &rhs.shape);
+        let mut buffer = self.take_buffer::<T>(lhs.numel());
+        let (lhs_view, rhs_view) = (lhs.view::<T>(), rhs.view::<T>());
+        buffer.extend(lhs_view.iter().zip(&rhs_view).map(|(&x, &y)| f(x, y)));
+        BlasTensor::from_array(ArrayD::from_shape_vec(lhs.shape.clone(), buffer).unwrap())
+    }
 }
 
-#[cfg(test)]
+// the typed elementwise kernels never cast, mixed types go through the
+// untyped opcodes
+fn check_typed(lhs: &BlasTensor, rhs: &BlasTensor, dtype: DType) {
+    if lhs.dtype() != dtype || rhs.dtype() != dtype {
+        panic!(
+            "typed kernel for {:?} got {:?} and {:?} operands, use Add/Sub/Mul/Div to promote",
+            dtype,
+            lhs.dtype(),
+            rhs.dtype()
+        );
+    }
+}
 
+// the typed elementwise kernels combine the raw storages, which only lines
+// up when both operands keep their elements in the same order and with the
+// same padding
+fn same_storage(lhs: &BlasTensor, rhs: &BlasTensor) -> bool {
+    (lhs.layout == Layout::ColMajor) == (rhs.layout == Layout::ColMajor)
+        && lhs.storage_shape() == rhs.storage_shape()
+}
+
+// logical matrix of a FloatMatrix tensor, strided when the storage is
+// ColMajor or padded
+fn matrix_view(tensor: &BlasTensor) -> ArrayView2<'_, f32> {
+    tensor.view::<f32>().into_dimensionality::<Ix2>().unwrap()
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -610,6 +797,76 @@ mod tests {
         assert_eq!(c, cref);
     }
 
+    #[test]
+    fn test_typed_kernels_mixed_layouts() {
+        let exec = BlasExecutor::new();
+        // [[1, 2], [3, 4]] in Fortran order
+        let a = BlasTensor::from_vec_fortran(vec![1.0f32, 3.0, 2.0, 4.0], vec![2, 2]);
+        let b = BlasTensor::from_vec_shape(vec![0.0, 1.0, 0.0, 0.0], vec![2, 2]);
+        let cref = BlasTensor::from_vec_shape(vec![1.0, 3.0, 3.0, 4.0], vec![2, 2]);
+        let c = exec.addf32_owned(a.clone(), b.clone());
+        assert!(c.all_close(&cref, 0.0));
+        let c = exec.addf32_owned(b.clone(), a.clone());
+        assert!(c.all_close(&cref, 0.0));
+
+        let mut c = BlasTensor::zeros(vec![2, 2]);
+        exec.mulf32_side_effect(&b, &a, &mut c);
+        let cref = BlasTensor::from_vec_shape(vec![0.0, 2.0, 0.0, 0.0], vec![2, 2]);
+        assert!(c.all_close(&cref, 0.0));
+
+        let a = BlasTensor::from_vec_fortran(vec![10i32, 30, 20, 40], vec![2, 2]);
+        let b = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4], vec![2, 2]);
+        let c = exec.binary_compute_owned(BlasOpCode::SubI, a, b);
+        let cref = BlasTensor::from_vec_shape_i32(vec![9, 18, 27, 36], vec![2, 2]);
+        assert!(c.all_close(&cref, 0.0));
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "typed kernel for Float got Float and Int32 operands, use Add/Sub/Mul/Div to promote"
+    )]
+    fn test_typed_kernels_reject_mixed_types() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::ones(vec![2, 2]);
+        let b = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4], vec![2, 2]);
+        // the untyped opcode promotes the same pair
+        let c = exec.binary_compute_owned(BlasOpCode::Add, a.clone(), b.clone());
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape(vec![2.0, 3.0, 4.0, 5.0], vec![2, 2])
+        );
+        exec.binary_compute_owned(BlasOpCode::AddF, a, b);
+    }
+
+    #[test]
+    fn test_typed_kernels_padded_operand() {
+        let exec = BlasExecutor::new();
+        let padded = BlasTensor::ones(vec![17, 23]).aligned(64);
+        let c = exec.binary_compute_owned(
+            BlasOpCode::AddF,
+            padded.clone(),
+            BlasTensor::ones(vec![17, 23]),
+        );
+        assert!(c.all_close(
+            &BlasTensor::from_vec_shape(vec![2.0; 17 * 23], vec![17, 23]),
+            0.0
+        ));
+
+        // equally padded operands keep combining their storages
+        let c = exec.subf32_owned(padded.clone(), padded.clone());
+        assert!(c.is_padded());
+        assert!(c.all_close(&BlasTensor::zeros(vec![17, 23]), 0.0));
+
+        let mut out = BlasTensor::zeros(vec![17, 23]);
+        exec.binary_compute_side_effect(
+            BlasOpCode::DivF,
+            &BlasTensor::ones(vec![17, 23]),
+            &padded,
+            &mut out,
+        );
+        assert!(out.all_close(&BlasTensor::ones(vec![17, 23]), 0.0));
+    }
+
     #[test]
     fn test_binary_compute_addf_owned() {
         let a = BlasTensor::from_vec_shape(vec![1.1, 2.2, 3.3, 4.4, 5.5, 6.6], vec![2, 3]);
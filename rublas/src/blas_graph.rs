@@ -0,0 +1,620 @@
+use ndarray::linalg::general_mat_mul;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+use num_traits::Float;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{check_shapes, with_float_dtype, BlasTensor, DType, TensorElement};
+use crate::blas_unary::float_unary_fn;
+
+// handle of a node in a LazyGraph
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct GraphVar(usize);
+
+#[derive(Debug, Clone)]
+enum GraphOp {
+    Input(BlasTensor),
+    Elementwise(BlasOpCode, Vec<GraphVar>),
+    Gemm(GraphVar, GraphVar),
+}
+
+#[derive(Debug, Clone)]
+struct GraphNode {
+    op: GraphOp,
+    shape: Vec<usize>,
+    dtype: DType,
+}
+
+// Deferred Float/Double computation: the builder methods only check types
+// and shapes and record BlasOpCode nodes, BlasExecutor::run_graph then
+// compiles the nodes the outputs need into fused kernels and runs those.
+// Elementwise operands must share type and shape like the eager ops.
+#[derive(Debug, Clone, Default)]
+pub struct LazyGraph {
+    nodes: Vec<GraphNode>,
+}
+
+impl LazyGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn shape(&self, var: GraphVar) -> &[usize] {
+        &self.nodes[var.0].shape
+    }
+
+    pub fn input(&mut self, value: BlasTensor) -> GraphVar {
+        let (shape, dtype) = (value.shape(), value.dtype());
+        if !matches!(dtype, DType::Float | DType::Double) {
+            panic!(
+                "lazy graphs only support Float and Double tensors, got {:?}",
+                dtype
+            );
+        }
+        self.push(GraphOp::Input(value), shape, dtype)
+    }
+
+    // Add/Sub/Mul/Div and their Float typed forms, GemmF/GemmD on 2-D operands
+    pub fn binary(&mut self, op: BlasOpCode, lhs: GraphVar, rhs: GraphVar) -> GraphVar {
+        let (a, b) = (&self.nodes[lhs.0], &self.nodes[rhs.0]);
+        if a.dtype != b.dtype {
+            panic!("operands' types not match: {:?} vs {:?}", a.dtype, b.dtype);
+        }
+        let dtype = a.dtype;
+        match op {
+            BlasOpCode::GemmF | BlasOpCode::GemmD => {
+                if a.shape.len() != 2 || b.shape.len() != 2 || a.shape[1] != b.shape[0] {
+                    panic!(
+                        "gemm operands' shapes not compatible: {:?} vs {:?}",
+                        a.shape, b.shape
+                    );
+                }
+                let shape = vec![a.shape[0], b.shape[1]];
+                self.push(GraphOp::Gemm(lhs, rhs), shape, dtype)
+            }
+            _ => {
+                let op = untyped(op);
+                check_shapes(&a.shape, &b.shape);
+                let shape = a.shape.clone();
+                self.push(GraphOp::Elementwise(op, vec![lhs, rhs]), shape, dtype)
+            }
+        }
+    }
+
+    // unary elementwise and tensor-scalar opcodes
+    pub fn unary(&mut self, op: BlasOpCode, input: GraphVar) -> GraphVar {
+        // rejects unsupported opcodes while building
+        let _ = float_map::<f64>(op);
+        let node = &self.nodes[input.0];
+        let (shape, dtype) = (node.shape.clone(), node.dtype);
+        self.push(GraphOp::Elementwise(op, vec![input]), shape, dtype)
+    }
+
+    // groups the nodes outputs depend on into kernels: an elementwise node
+    // whose only use is another elementwise node is evaluated inside it, and
+    // a gemm whose only use is a bias add accumulates onto the bias (beta 1)
+    pub fn compile(&self, outputs: &[GraphVar]) -> GraphPlan {
+        let mut uses = vec![0; self.nodes.len()];
+        let mut needed = vec![false; self.nodes.len()];
+        for &out in outputs {
+            uses[out.0] += 1;
+            needed[out.0] = true;
+        }
+        for idx in (0..self.nodes.len()).rev() {
+            if needed[idx] {
+                for operand in self.operands(idx) {
+                    uses[operand.0] += 1;
+                    needed[operand.0] = true;
+                }
+            }
+        }
+
+        // (lhs, rhs, bias) of gemms folded into the add consuming them,
+        // keyed by the add
+        let mut bias_gemm = vec![None; self.nodes.len()];
+        let mut absorbed = vec![false; self.nodes.len()];
+        for idx in (0..self.nodes.len()).filter(|&idx| needed[idx]) {
+            if let GraphOp::Elementwise(BlasOpCode::Add, operands) = &self.nodes[idx].op {
+                let (lhs, rhs) = (operands[0], operands[1]);
+                for (gemm, bias) in [(lhs, rhs), (rhs, lhs)] {
+                    if let GraphOp::Gemm(a, b) = self.nodes[gemm.0].op {
+                        if uses[gemm.0] == 1 {
+                            bias_gemm[idx] = Some((a, b, bias));
+                            absorbed[gemm.0] = true;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        let is_fusable = |idx: usize| {
+            matches!(self.nodes[idx].op, GraphOp::Elementwise(..)) && bias_gemm[idx].is_none()
+        };
+        let mut inlined = vec![false; self.nodes.len()];
+        for idx in (0..self.nodes.len()).filter(|&idx| needed[idx] && is_fusable(idx)) {
+            for operand in self.operands(idx) {
+                if uses[operand.0] == 1 && is_fusable(operand.0) {
+                    inlined[operand.0] = true;
+                }
+            }
+        }
+
+        let mut kernels = vec![];
+        for idx in 0..self.nodes.len() {
+            if !needed[idx] || inlined[idx] || absorbed[idx] {
+                continue;
+            }
+            let out = GraphVar(idx);
+            let kernel = match (&self.nodes[idx].op, bias_gemm[idx]) {
+                (GraphOp::Input(_), _) => continue,
+                (_, Some((lhs, rhs, bias))) => Kernel::Gemm {
+                    out,
+                    lhs,
+                    rhs,
+                    bias: Some(bias),
+                },
+                (GraphOp::Gemm(lhs, rhs), None) => Kernel::Gemm {
+                    out,
+                    lhs: *lhs,
+                    rhs: *rhs,
+                    bias: None,
+                },
+                (GraphOp::Elementwise(..), None) => {
+                    let mut leaves = vec![];
+                    let mut steps = vec![];
+                    self.flatten(out, &inlined, &mut leaves, &mut steps);
+                    Kernel::Elementwise { out, leaves, steps }
+                }
+            };
+            kernels.push(kernel);
+        }
+
+        // reads of every node by the kernels and the caller, for freeing
+        // intermediates while running
+        let mut reads = vec![0; self.nodes.len()];
+        for var in kernels
+            .iter()
+            .flat_map(Kernel::operands)
+            .chain(outputs.iter().cloned())
+        {
+            reads[var.0] += 1;
+        }
+        GraphPlan {
+            kernels,
+            reads,
+            outputs: outputs.to_vec(),
+        }
+    }
+
+    fn push(&mut self, op: GraphOp, shape: Vec<usize>, dtype: DType) -> GraphVar {
+        self.nodes.push(GraphNode { op, shape, dtype });
+        GraphVar(self.nodes.len() - 1)
+    }
+
+    fn operands(&self, idx: usize) -> Vec<GraphVar> {
+        match &self.nodes[idx].op {
+            GraphOp::Input(_) => vec![],
+            GraphOp::Elementwise(_, operands) => operands.clone(),
+            GraphOp::Gemm(lhs, rhs) => vec![*lhs, *rhs],
+        }
+    }
+
+    // postfix steps of the expression rooted at var, loading every operand
+    // that is not inlined from the leaves
+    fn flatten(
+        &self,
+        var: GraphVar,
+        inlined: &[bool],
+        leaves: &mut Vec<GraphVar>,
+        steps: &mut Vec<Step>,
+    ) {
+        let (op, operands) = match &self.nodes[var.0].op {
+            GraphOp::Elementwise(op, operands) => (*op, operands),
+            _ => unreachable!(),
+        };
+        for &operand in operands {
+            if inlined[operand.0] {
+                self.flatten(operand, inlined, leaves, steps);
+                continue;
+            }
+            let slot = match leaves.iter().position(|&leaf| leaf == operand) {
+                Some(slot) => slot,
+                None => {
+                    leaves.push(operand);
+                    leaves.len() - 1
+                }
+            };
+            steps.push(Step::Load(slot));
+        }
+        steps.push(Step::Op(op));
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Load(usize),
+    Op(BlasOpCode),
+}
+
+#[derive(Debug, Clone)]
+enum Kernel {
+    // one pass over the leaves evaluating the postfix steps per element
+    Elementwise {
+        out: GraphVar,
+        leaves: Vec<GraphVar>,
+        steps: Vec<Step>,
+    },
+    Gemm {
+        out: GraphVar,
+        lhs: GraphVar,
+        rhs: GraphVar,
+        bias: Option<GraphVar>,
+    },
+}
+
+impl Kernel {
+    fn out(&self) -> GraphVar {
+        match self {
+            Kernel::Elementwise { out, .. } | Kernel::Gemm { out, .. } => *out,
+        }
+    }
+
+    fn operands(&self) -> Vec<GraphVar> {
+        match self {
+            Kernel::Elementwise { leaves, .. } => leaves.clone(),
+            Kernel::Gemm { lhs, rhs, bias, .. } => [*lhs, *rhs].into_iter().chain(*bias).collect(),
+        }
+    }
+}
+
+// kernels of a compiled LazyGraph in execution order
+#[derive(Debug, Clone)]
+pub struct GraphPlan {
+    kernels: Vec<Kernel>,
+    reads: Vec<usize>,
+    outputs: Vec<GraphVar>,
+}
+
+impl GraphPlan {
+    pub fn num_kernels(&self) -> usize {
+        self.kernels.len()
+    }
+}
+
+impl BlasExecutor {
+    pub fn run_graph(&self, graph: &LazyGraph, outputs: &[GraphVar]) -> Vec<BlasTensor> {
+        self.run_plan(graph, &graph.compile(outputs))
+    }
+
+    // output buffers come from the executor's pool and intermediates go
+    // back to it after their last use; a bias only read by its gemm becomes
+    // the gemm's output buffer
+    pub fn run_plan(&self, graph: &LazyGraph, plan: &GraphPlan) -> Vec<BlasTensor> {
+        let mut values: Vec<Option<BlasTensor>> = vec![None; graph.len()];
+        let mut remaining = plan.reads.clone();
+        for kernel in &plan.kernels {
+            let out = kernel.out();
+            let node = &graph.nodes[out.0];
+            let value = with_float_dtype!(node.dtype, T => match kernel {
+                Kernel::Elementwise { leaves, steps, .. } => {
+                    let leaves: Vec<&BlasTensor> =
+                        leaves.iter().map(|&leaf| fetch(graph, &values, leaf)).collect();
+                    let buffer = self.take_buffer::<T>(node.shape.iter().product());
+                    elementwise::<T>(&leaves, steps, &node.shape, buffer)
+                }
+                Kernel::Gemm { lhs, rhs, bias, .. } => {
+                    let mut acc = match bias {
+                        Some(bias) if remaining[bias.0] == 1 && values[bias.0].is_some() => {
+                            values[bias.0].take().unwrap()
+                        }
+                        Some(bias) => self.copy_pooled(fetch(graph, &values, *bias)),
+                        None => BlasTensor::from_array(self.pooled_array::<T>(&node.shape, 0.0)),
+                    };
+                    let (a, b) = (fetch(graph, &values, *lhs), fetch(graph, &values, *rhs));
+                    gemm_accumulate::<T>(a, b, &mut acc);
+                    acc
+                }
+            });
+            for operand in kernel.operands() {
+                remaining[operand.0] -= 1;
+                if remaining[operand.0] == 0 {
+                    if let Some(value) = values[operand.0].take() {
+                        self.recycle(value);
+                    }
+                }
+            }
+            values[out.0] = Some(value);
+        }
+        // computed outputs are moved out, inputs and repeated outputs copied
+        let outputs = &plan.outputs;
+        (0..outputs.len())
+            .map(|idx| {
+                let out = outputs[idx];
+                if values[out.0].is_none() || outputs[idx + 1..].contains(&out) {
+                    self.copy_pooled(fetch(graph, &values, out))
+                } else {
+                    values[out.0].take().unwrap()
+                }
+            })
+            .collect()
+    }
+}
+
+fn fetch<'a>(
+    graph: &'a LazyGraph,
+    values: &'a [Option<BlasTensor>],
+    var: GraphVar,
+) -> &'a BlasTensor {
+    match (&graph.nodes[var.0].op, &values[var.0]) {
+        (GraphOp::Input(value), _) => value,
+        (_, Some(value)) => value,
+        _ => panic!("graph node {} used before it was computed", var.0),
+    }
+}
+
+// walks all leaves in logical order at once, so the only allocation is the
+// output
+fn elementwise<T: TensorElement + Float>(
+    leaves: &[&BlasTensor],
+    steps: &[Step],
+    shape: &[usize],
+    mut out: Vec<T>,
+) -> BlasTensor {
+    let views: Vec<ArrayViewD<'_, T>> = leaves.iter().map(|leaf| leaf.view::<T>()).collect();
+    let mut iters: Vec<_> = views.iter().map(|view| view.iter()).collect();
+    let ops: Vec<TypedStep<T>> = steps
+        .iter()
+        .map(|step| match *step {
+            Step::Load(slot) => TypedStep::Load(slot),
+            Step::Op(op) if is_binary(op) => TypedStep::Binary(float_binary_fn(op)),
+            Step::Op(op) => TypedStep::Map(float_map(op)),
+        })
+        .collect();
+    let numel: usize = shape.iter().product();
+    let mut loaded = vec![T::zero(); iters.len()];
+    let mut stack = Vec::with_capacity(steps.len());
+    for _ in 0..numel {
+        for (value, iter) in loaded.iter_mut().zip(iters.iter_mut()) {
+            *value = *iter.next().unwrap();
+        }
+        for op in &ops {
+            match op {
+                TypedStep::Load(slot) => stack.push(loaded[*slot]),
+                TypedStep::Binary(f) => {
+                    let y = stack.pop().unwrap();
+                    let x = stack.pop().unwrap();
+                    stack.push(f(x, y));
+                }
+                TypedStep::Map(f) => {
+                    let x = stack.pop().unwrap();
+                    stack.push(f(x));
+                }
+            }
+        }
+        out.push(stack.pop().unwrap());
+    }
+    BlasTensor::from_array(ArrayD::from_shape_vec(IxDyn(shape), out).unwrap())
+}
+
+enum TypedStep<T> {
+    Load(usize),
+    Binary(fn(T, T) -> T),
+    Map(Box<dyn Fn(T) -> T>),
+}
+
+// acc += a b over the logical matrices
+fn gemm_accumulate<T: TensorElement + LinalgScalar>(
+    a: &BlasTensor,
+    b: &BlasTensor,
+    acc: &mut BlasTensor,
+) {
+    let a = a.view::<T>().into_dimensionality::<Ix2>().unwrap();
+    let b = b.view::<T>().into_dimensionality::<Ix2>().unwrap();
+    let mut acc = acc.view_mut::<T>().into_dimensionality::<Ix2>().unwrap();
+    general_mat_mul(T::one(), &a, &b, T::one(), &mut acc);
+}
+
+pub(crate) fn untyped(op: BlasOpCode) -> BlasOpCode {
+    match op {
+        BlasOpCode::Add | BlasOpCode::AddF => BlasOpCode::Add,
+        BlasOpCode::Sub | BlasOpCode::SubF => BlasOpCode::Sub,
+        BlasOpCode::Mul | BlasOpCode::MulF => BlasOpCode::Mul,
+        BlasOpCode::Div | BlasOpCode::DivF => BlasOpCode::Div,
+        _ => panic!("not wired opcode"),
+    }
+}
+
+pub(crate) fn is_binary(op: BlasOpCode) -> bool {
+    matches!(
+        op,
+        BlasOpCode::Add | BlasOpCode::Sub | BlasOpCode::Mul | BlasOpCode::Div
+    )
+}
+
+pub(crate) fn float_binary_fn<T: Float>(op: BlasOpCode) -> fn(T, T) -> T {
+    match op {
+        BlasOpCode::Add => |x, y| x + y,
+        BlasOpCode::Sub => |x, y| x - y,
+        BlasOpCode::Mul => |x, y| x * y,
+        BlasOpCode::Div => |x, y| x / y,
+        _ => panic!("not wired opcode"),
+    }
+}
+
+// unary opcodes and tensor-scalar opcodes with their immediate bound
+pub(crate) fn float_map<T: Float + 'static>(op: BlasOpCode) -> Box<dyn Fn(T) -> T> {
+    let cast = |imm: f64| T::from(imm).unwrap();
+    match op {
+        BlasOpCode::AddScalar(imm) => Box::new(move |x| x + cast(imm)),
+        BlasOpCode::SubScalar(imm) => Box::new(move |x| x - cast(imm)),
+        BlasOpCode::MulScalar(imm) => Box::new(move |x| x * cast(imm)),
+        BlasOpCode::DivScalar(imm) => Box::new(move |x| x / cast(imm)),
+        BlasOpCode::PowScalar(imm) => Box::new(move |x| x.powf(cast(imm))),
+        BlasOpCode::MinScalar(imm) => Box::new(move |x| if cast(imm) < x { cast(imm) } else { x }),
+        BlasOpCode::MaxScalar(imm) => Box::new(move |x| if cast(imm) > x { cast(imm) } else { x }),
+        BlasOpCode::RSubScalar(imm) => Box::new(move |x| cast(imm) - x),
+        BlasOpCode::RDivScalar(imm) => Box::new(move |x| cast(imm) / x),
+        op => Box::new(float_unary_fn::<T>(op)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuses_elementwise_chain() {
+        // ((a + b) * c) / d in one pass
+        let exec = BlasExecutor::new();
+        let shape = vec![2, 3, 4];
+        let inputs: Vec<BlasTensor> = (0..4)
+            .map(|_| BlasTensor::uniform(shape.clone(), 1.0, 2.0))
+            .collect();
+        let mut graph = LazyGraph::new();
+        let x: Vec<GraphVar> = inputs.iter().map(|t| graph.input(t.clone())).collect();
+        let sum = graph.binary(BlasOpCode::Add, x[0], x[1]);
+        let prod = graph.binary(BlasOpCode::MulF, sum, x[2]);
+        let out = graph.binary(BlasOpCode::Div, prod, x[3]);
+        assert_eq!(graph.compile(&[out]).num_kernels(), 1);
+
+        let c = exec.run_graph(&graph, &[out]).remove(0);
+        let sum = exec.binary_compute_owned(BlasOpCode::Add, inputs[0].clone(), inputs[1].clone());
+        let prod = exec.binary_compute_owned(BlasOpCode::Mul, sum, inputs[2].clone());
+        let cref = exec.binary_compute_owned(BlasOpCode::Div, prod, inputs[3].clone());
+        assert!(c.all_close(&cref, 1e-6));
+        assert_eq!(c.shape(), shape);
+    }
+
+    #[test]
+    fn test_fuses_unary_and_scalar_ops() {
+        // relu(2 * exp(a) - b) + a, with a read twice in the same kernel
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::normal_double(vec![3, 5], 0.0, 1.0);
+        let b = BlasTensor::normal_double(vec![3, 5], 0.0, 1.0);
+        let mut graph = LazyGraph::new();
+        let (ga, gb) = (graph.input(a.clone()), graph.input(b.clone()));
+        let e = graph.unary(BlasOpCode::Exp, ga);
+        let e = graph.unary(BlasOpCode::MulScalar(2.0), e);
+        let d = graph.binary(BlasOpCode::Sub, e, gb);
+        let r = graph.unary(BlasOpCode::Relu, d);
+        let out = graph.binary(BlasOpCode::Add, r, ga);
+        assert_eq!(graph.compile(&[out]).num_kernels(), 1);
+
+        let e = exec.unary_compute_owned(BlasOpCode::Exp, a.clone());
+        let e = exec.scalar_compute_owned(BlasOpCode::MulScalar(2.0), e);
+        let d = exec.binary_compute_owned(BlasOpCode::Sub, e, b);
+        let r = exec.unary_compute_owned(BlasOpCode::Relu, d);
+        let cref = exec.binary_compute_owned(BlasOpCode::Add, r, a);
+        assert!(exec.run_graph(&graph, &[out])[0].all_close(&cref, 1e-12));
+    }
+
+    #[test]
+    fn test_shared_intermediate_materialised() {
+        // s = a + b feeds two consumers and is an output itself
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec(vec![1.0, 2.0]);
+        let b = BlasTensor::from_vec(vec![3.0, 4.0]);
+        let mut graph = LazyGraph::new();
+        let (ga, gb) = (graph.input(a), graph.input(b));
+        let s = graph.binary(BlasOpCode::Add, ga, gb);
+        let sq = graph.binary(BlasOpCode::Mul, s, s);
+        let neg = graph.unary(BlasOpCode::Neg, s);
+        let out = graph.binary(BlasOpCode::Add, sq, neg);
+        // unused nodes are not run
+        graph.unary(BlasOpCode::Exp, ga);
+        assert_eq!(graph.compile(&[out, s]).num_kernels(), 2);
+
+        let c = exec.run_graph(&graph, &[out, s]);
+        assert_eq!(c[0], BlasTensor::from_vec(vec![12.0, 30.0]));
+        assert_eq!(c[1], BlasTensor::from_vec(vec![4.0, 6.0]));
+    }
+
+    #[test]
+    fn test_folds_bias_add_into_gemm() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::uniform(vec![4, 3], -1.0, 1.0);
+        let w = BlasTensor::uniform(vec![3, 5], -1.0, 1.0);
+        let bias = BlasTensor::uniform(vec![4, 5], -1.0, 1.0);
+        let mut graph = LazyGraph::new();
+        let (ga, gw, gbias) = (
+            graph.input(a.clone()),
+            graph.input(w.clone()),
+            graph.input(bias.clone()),
+        );
+        let mm = graph.binary(BlasOpCode::GemmF, ga, gw);
+        let out = graph.binary(BlasOpCode::Add, gbias, mm);
+        let plan = graph.compile(&[out]);
+        assert_eq!(plan.num_kernels(), 1);
+
+        let cref = exec.binary_compute_owned(BlasOpCode::Add, exec.gemm_owned(a, w), bias);
+        // the input bias is copied, so the plan can run again
+        for _ in 0..2 {
+            assert!(exec.run_plan(&graph, &plan)[0].all_close(&cref, 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_bias_from_fused_kernel() {
+        // relu(a w + (b * 2)) in f64: the bias kernel's buffer becomes the
+        // gemm output and the relu runs as its own kernel
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::normal_double(vec![2, 3], 0.0, 1.0);
+        let w = BlasTensor::normal_double(vec![3, 2], 0.0, 1.0);
+        let b = BlasTensor::normal_double(vec![2, 2], 0.0, 1.0);
+        let mut graph = LazyGraph::new();
+        let (ga, gw, gb) = (
+            graph.input(a.clone()),
+            graph.input(w.clone()),
+            graph.input(b.clone()),
+        );
+        let mm = graph.binary(BlasOpCode::GemmD, ga, gw);
+        let bias = graph.unary(BlasOpCode::MulScalar(2.0), gb);
+        let sum = graph.binary(BlasOpCode::Add, mm, bias);
+        let out = graph.unary(BlasOpCode::Relu, sum);
+        assert_eq!(graph.compile(&[out]).num_kernels(), 3);
+
+        let bias = exec.scalar_compute_owned(BlasOpCode::MulScalar(2.0), b);
+        let sum = exec.binary_compute_owned(BlasOpCode::Add, a.matmul(&w), bias);
+        let cref = exec.unary_compute_owned(BlasOpCode::Relu, sum);
+        assert!(exec.run_graph(&graph, &[out])[0].all_close(&cref, 1e-12));
+    }
+
+    #[test]
+    fn test_run_plan_reuses_pooled_buffers() {
+        // exp(a) * b is shared, so two elementwise kernels and a temporary
+        let exec = BlasExecutor::new();
+        let mut graph = LazyGraph::new();
+        let a = graph.input(BlasTensor::uniform(vec![4, 4], -1.0, 1.0));
+        let b = graph.input(BlasTensor::uniform(vec![4, 4], -1.0, 1.0));
+        let e = graph.unary(BlasOpCode::Exp, a);
+        let t = graph.binary(BlasOpCode::Mul, e, b);
+        let out = graph.binary(BlasOpCode::Add, t, t);
+        let plan = graph.compile(&[out]);
+        let mut allocations = vec![];
+        for _ in 0..3 {
+            let c = exec.run_plan(&graph, &plan).remove(0);
+            exec.recycle(c);
+            allocations.push(exec.pool_stats().allocations);
+        }
+        assert_eq!(allocations, [2, 2, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "operands' shapes not match: [2] vs [3]")]
+    fn test_graph_shape_mismatch() {
+        let mut graph = LazyGraph::new();
+        let a = graph.input(BlasTensor::zeros(vec![2]));
+        let b = graph.input(BlasTensor::zeros(vec![3]));
+        graph.binary(BlasOpCode::Add, a, b);
+    }
+}
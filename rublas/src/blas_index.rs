@@ -0,0 +1,276 @@
+use ndarray::prelude::*;
+use num_traits::Num;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_any_dtype, with_dtype, BlasTensor, DType, TensorElement};
+
+// Index driven data movement. Index tensors are Int32 (Int32Vector or
+// Int32Matrix storage) and every index must be within [0, dim) of the axis it
+// addresses; axis is a logical axis of the data tensor.
+impl BlasExecutor {
+    pub fn index_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: &BlasTensor,
+        axis: usize,
+        index: &BlasTensor,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::IndexSelect => self.index_select_owned(input, axis, index),
+            BlasOpCode::Gather => self.gather_owned(input, axis, index),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // consumes input and writes src into it at the indexed positions
+    pub fn scatter_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: BlasTensor,
+        axis: usize,
+        index: &BlasTensor,
+        src: &BlasTensor,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::Scatter => self.scatter_owned(input, axis, index, src),
+            BlasOpCode::ScatterAdd => self.scatter_add_owned(input, axis, index, src),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // picks whole slices along axis by a 1-D index, e.g. embedding rows
+    pub fn index_select_owned(
+        &self,
+        input: &BlasTensor,
+        axis: usize,
+        index: &BlasTensor,
+    ) -> BlasTensor {
+        check_index(input, axis, index);
+        if index.ndims() != 1 {
+            panic!(
+                "index_select needs a 1-D index, got shape {:?}",
+                index.shape
+            );
+        }
+        let dim = input.shape[axis];
+        let indices: Vec<usize> = index
+            .view::<i32>()
+            .iter()
+            .map(|&idx| resolve(idx, axis, dim))
+            .collect();
+        with_any_dtype!(input.dtype(), T => {
+            BlasTensor::from_array(input.view::<T>().select(Axis(axis), &indices))
+        })
+    }
+
+    // out[i][j] = input[index[i][j]][j] for axis 0, the output takes the
+    // shape of index which may be smaller than input on the other axes
+    pub fn gather_owned(&self, input: &BlasTensor, axis: usize, index: &BlasTensor) -> BlasTensor {
+        check_index(input, axis, index);
+        check_extent(index, input, Some(axis));
+        let dim = input.shape[axis];
+        let index = index.view::<i32>();
+        with_any_dtype!(input.dtype(), T => {
+            let view = input.view::<T>();
+            let out_data = ArrayD::from_shape_fn(index.raw_dim(), |idx| {
+                let mut src = idx.clone();
+                src[axis] = resolve(index[&idx], axis, dim);
+                view[src]
+            });
+            BlasTensor::from_array(out_data)
+        })
+    }
+
+    // input[index[i][j]][j] = src[i][j] for axis 0; with repeated indices
+    // the last write wins
+    pub fn scatter_owned(
+        &self,
+        input: BlasTensor,
+        axis: usize,
+        index: &BlasTensor,
+        src: &BlasTensor,
+    ) -> BlasTensor {
+        with_any_dtype!(input.dtype(), T => scatter::<T, _>(input, axis, index, src, |dst, x| *dst = x))
+    }
+
+    // input[index[i][j]][j] += src[i][j] for axis 0, repeated indices add up
+    pub fn scatter_add_owned(
+        &self,
+        input: BlasTensor,
+        axis: usize,
+        index: &BlasTensor,
+        src: &BlasTensor,
+    ) -> BlasTensor {
+        with_dtype!(input.dtype(), T => scatter::<T, _>(input, axis, index, src, add_to::<T>))
+    }
+}
+
+fn add_to<T: Num + Copy>(dst: &mut T, x: T) {
+    *dst = *dst + x;
+}
+
+fn scatter<T, F>(
+    mut input: BlasTensor,
+    axis: usize,
+    index: &BlasTensor,
+    src: &BlasTensor,
+    write: F,
+) -> BlasTensor
+where
+    T: TensorElement,
+    F: Fn(&mut T, T),
+{
+    check_index(&input, axis, index);
+    if src.dtype() != input.dtype() {
+        panic!(
+            "operands' types not match: {:?} vs {:?}",
+            input.dtype(),
+            src.dtype()
+        );
+    }
+    check_extent(index, src, None);
+    check_extent(index, &input, Some(axis));
+    let dim = input.shape[axis];
+    let src = src.view::<T>();
+    let mut out = input.view_mut::<T>();
+    for (idx, &position) in index.view::<i32>().indexed_iter() {
+        let mut dst = idx.clone();
+        dst[axis] = resolve(position, axis, dim);
+        write(&mut out[dst], src[idx]);
+    }
+    input
+}
+
+fn check_index(input: &BlasTensor, axis: usize, index: &BlasTensor) {
+    if index.dtype() != DType::Int32 {
+        panic!("index tensor must be Int32, got {:?}", index.dtype());
+    }
+    if axis >= input.ndims() {
+        panic!(
+            "axis {} out of range for tensor with {} dims",
+            axis,
+            input.ndims()
+        );
+    }
+}
+
+// gather/scatter walk index elementwise, so it needs the rank of other and
+// may not be larger on any axis but skip
+fn check_extent(index: &BlasTensor, other: &BlasTensor, skip: Option<usize>) {
+    let fits = index.ndims() == other.ndims()
+        && (0..index.ndims())
+            .all(|axis| Some(axis) == skip || index.shape[axis] <= other.shape[axis]);
+    if !fits {
+        panic!(
+            "index shape {:?} not compatible with shape {:?}",
+            index.shape, other.shape
+        );
+    }
+}
+
+fn resolve(idx: i32, axis: usize, dim: usize) -> usize {
+    if idx < 0 || idx as usize >= dim {
+        panic!(
+            "index {} out of range for axis {} of size {}",
+            idx, axis, dim
+        );
+    }
+    idx as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_select_embedding() {
+        let exec = BlasExecutor::new();
+        let table = BlasTensor::from_vec_shape((0..12).map(|x| x as f32).collect(), vec![4, 3]);
+        let ids = BlasTensor::from_vec_shape_i32(vec![3, 0, 3], vec![3]);
+        let c = exec.index_compute_owned(BlasOpCode::IndexSelect, &table, 0, &ids);
+        let cref = BlasTensor::from_vec_shape(
+            vec![9.0, 10.0, 11.0, 0.0, 1.0, 2.0, 9.0, 10.0, 11.0],
+            vec![3, 3],
+        );
+        assert_eq!(c, cref);
+
+        let ids = BlasTensor::from_vec_shape_i32(vec![2], vec![1]);
+        let c = exec.index_select_owned(&table, 1, &ids);
+        let cref = BlasTensor::from_vec_shape(vec![2.0, 5.0, 8.0, 11.0], vec![4, 1]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_gather() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4, 5, 6], vec![2, 3]);
+        let index = BlasTensor::from_vec_shape_i32(vec![2, 0, 1, 1], vec![2, 2]);
+        let c = exec.index_compute_owned(BlasOpCode::Gather, &a, 1, &index);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![3, 1, 5, 5], vec![2, 2])
+        );
+
+        let index = BlasTensor::from_vec_shape_i32(vec![1, 0, 1], vec![1, 3]);
+        let c = exec.gather_owned(&a, 0, &index);
+        assert_eq!(c, BlasTensor::from_vec_shape_i32(vec![4, 2, 6], vec![1, 3]));
+    }
+
+    #[test]
+    fn test_scatter_and_scatter_add() {
+        let exec = BlasExecutor::new();
+        let index = BlasTensor::from_vec_shape_i32(vec![0, 2, 0, 1], vec![2, 2]);
+        let src = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+
+        let c = exec.scatter_compute_owned(
+            BlasOpCode::Scatter,
+            BlasTensor::zeros(vec![3, 2]),
+            0,
+            &index,
+            &src,
+        );
+        let cref = BlasTensor::from_vec_shape(vec![3.0, 0.0, 0.0, 4.0, 0.0, 2.0], vec![3, 2]);
+        assert_eq!(c, cref);
+
+        let c = exec.scatter_compute_owned(
+            BlasOpCode::ScatterAdd,
+            BlasTensor::ones(vec![3, 2]),
+            0,
+            &index,
+            &src,
+        );
+        let cref = BlasTensor::from_vec_shape(vec![5.0, 1.0, 1.0, 5.0, 1.0, 3.0], vec![3, 2]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_scatter_add_embedding_grad() {
+        // gradient of an embedding lookup accumulates rows by token id
+        let exec = BlasExecutor::new();
+        let ids = BlasTensor::from_vec_shape_i32(vec![1, 1, 0, 1, 1, 0], vec![3, 2]);
+        let grad = BlasTensor::from_vec_shape_f64(vec![0.5; 6], vec![3, 2]);
+        let c = exec.scatter_add_owned(BlasTensor::zeros_double(vec![2, 2]), 0, &ids, &grad);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_f64(vec![0.5, 0.5, 1.0, 1.0], vec![2, 2])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "index 4 out of range for axis 0 of size 4")]
+    fn test_index_select_out_of_range() {
+        let exec = BlasExecutor::new();
+        let table = BlasTensor::zeros(vec![4, 3]);
+        let ids = BlasTensor::from_vec_shape_i32(vec![4], vec![1]);
+        exec.index_select_owned(&table, 0, &ids);
+    }
+
+    #[test]
+    #[should_panic(expected = "index tensor must be Int32")]
+    fn test_gather_float_index() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::zeros(vec![2, 2]);
+        exec.gather_owned(&a, 0, &BlasTensor::zeros(vec![2, 2]));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_any_dtype, BlasTensor, Layout};
+
+impl BlasExecutor {
+    pub fn layout_compute_owned(&self, op: BlasOpCode, input: BlasTensor) -> BlasTensor {
+        match op {
+            BlasOpCode::ToLayout(layout) => self.to_layout_owned(input, layout),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // ColMajor <-> row-major moves the elements and keeps the logical shape,
+    // ChannelsFirst <-> ChannelsLast permutes NCHW <-> NHWC (NCW <-> NWC);
+    // between RowMajor and a channel tag only the tag changes
+    pub fn to_layout_owned(&self, input: BlasTensor, layout: Layout) -> BlasTensor {
+        match (input.layout, layout) {
+            (from, to) if from == to => input,
+            (Layout::ChannelsFirst, Layout::ChannelsLast) => {
+                let axes: &[usize] = if input.ndims() == 4 {
+                    &[0, 2, 3, 1]
+                } else {
+                    &[0, 2, 1]
+                };
+                self.permute_owned(input, axes).with_layout(layout)
+            }
+            (Layout::ChannelsLast, Layout::ChannelsFirst) => {
+                let axes: &[usize] = if input.ndims() == 4 {
+                    &[0, 3, 1, 2]
+                } else {
+                    &[0, 2, 1]
+                };
+                self.permute_owned(input, axes).with_layout(layout)
+            }
+            (_, Layout::ColMajor) => with_any_dtype!(input.dtype(), T => {
+                // the row-major copy of the reversed view is the Fortran
+                // ordered data, from_array_fortran then takes it as is
+                let reversed = input.view::<T>().reversed_axes();
+                let fortran = reversed.as_standard_layout().into_owned().reversed_axes();
+                BlasTensor::from_array_fortran(fortran)
+            }),
+            (Layout::ColMajor, to) => {
+                let row_major = with_any_dtype!(input.dtype(), T => {
+                    BlasTensor::from_array(input.view::<T>().as_standard_layout().into_owned())
+                });
+                row_major.with_layout(to)
+            }
+            (_, to) => input.with_layout(to),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fortran_constructor() {
+        let a = BlasTensor::from_vec_fortran(vec![1.0f32, 4.0, 2.0, 5.0, 3.0, 6.0], vec![2, 3]);
+        assert_eq!(a.layout, Layout::ColMajor);
+        assert_eq!(a.shape(), [2, 3]);
+        assert_eq!(a.view::<f32>()[[0, 2]], 3.0);
+        assert_eq!(a.view::<f32>()[[1, 0]], 4.0);
+
+        let row_major = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        assert!(a.all_close(&row_major, 0.0));
+        assert_ne!(a, row_major);
+    }
+
+    #[test]
+    fn test_row_col_major_roundtrip() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32((0..24).collect(), vec![2, 3, 4]);
+        let c = exec.layout_compute_owned(BlasOpCode::ToLayout(Layout::ColMajor), a.clone());
+        assert_eq!(c.layout, Layout::ColMajor);
+        assert_eq!(c.view::<i32>(), a.view::<i32>());
+        assert_eq!(c.view::<i32>()[[1, 2, 3]], 23);
+
+        let c = exec.to_layout_owned(c, Layout::RowMajor);
+        assert_eq!(c, a);
+    }
+
+    #[test]
+    fn test_nchw_nhwc() {
+        let exec = BlasExecutor::new();
+        let nchw =
+            BlasTensor::from_vec_shape((0..24).map(|x| x as f32).collect(), vec![1, 2, 3, 4])
+                .with_layout(Layout::ChannelsFirst);
+        let nhwc = exec.to_layout_owned(nchw.clone(), Layout::ChannelsLast);
+        assert_eq!(nhwc.layout, Layout::ChannelsLast);
+        assert_eq!(nhwc.shape(), [1, 3, 4, 2]);
+        assert_eq!(
+            nhwc.view::<f32>()[[0, 2, 1, 1]],
+            nchw.view::<f32>()[[0, 1, 2, 1]]
+        );
+
+        let back = exec.to_layout_owned(nhwc, Layout::ChannelsFirst);
+        assert_eq!(back, nchw);
+    }
+
+    #[test]
+    fn test_layout_aware_ops() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_fortran(vec![1.0f64, 3.0, 2.0, 4.0], vec![2, 2]);
+        let b = BlasTensor::from_vec_shape_f64(vec![1.0, 1.0, 1.0, 1.0], vec![2, 2]);
+        let c = exec.binary_compute_owned(BlasOpCode::Sub, a.clone(), b);
+        let cref = BlasTensor::from_vec_shape_f64(vec![0.0, 1.0, 2.0, 3.0], vec![2, 2]);
+        assert!(c.all_close(&cref, 0.0));
+
+        let c = a.reshape(&[4]);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_f64(vec![1.0, 2.0, 3.0, 4.0], vec![4])
+        );
+    }
+
+    #[test]
+    fn test_gemm_col_major_operands() {
+        let exec = BlasExecutor::new();
+        // [[1, 2, 3], [4, 5, 6]] in Fortran order
+        let a = BlasTensor::from_vec_fortran(vec![1.0f32, 4.0, 2.0, 5.0, 3.0, 6.0], vec![2, 3]);
+        let b = BlasTensor::from_vec_shape(vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0], vec![3, 2]);
+        let c = exec.gemm_owned(a.clone(), b.clone());
+        let cref = BlasTensor::from_vec_shape(vec![4.0, 5.0, 10.0, 11.0], vec![2, 2]);
+        assert_eq!(c, cref);
+
+        let b = exec.to_layout_owned(b, Layout::ColMajor);
+        let mut out = BlasTensor::zeros(vec![2, 2]);
+        out = exec.to_layout_owned(out, Layout::ColMajor);
+        exec.gemm_side_effect(&a, &b, &mut out);
+        assert!(out.all_close(&cref, 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "ChannelsLast needs a tensor with 3 or 4 dims")]
+    fn test_channel_tag_rank() {
+        BlasTensor::zeros(vec![2, 3]).with_layout(Layout::ChannelsLast);
+    }
+}
@@ -0,0 +1,370 @@
+use ndarray::prelude::*;
+use ndarray::Zip;
+use num_traits::Float;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_float_dtype, BlasTensor, DType, Layout, TensorElement};
+
+// how per-element (per-sample for cross entropy) losses are combined; Mean
+// and Sum give a single element vector
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Reduction {
+    None,
+    #[default]
+    Mean,
+    Sum,
+}
+
+// Losses of Float/Double predictions. The gradient is w.r.t. input, has
+// its shape and already includes the 1 / count factor of Mean; it is only
+// computed when asked for.
+impl BlasExecutor {
+    // target has the type and shape of input, except for CrossEntropyLoss
+    // where it holds the Int32 class of every lane along the last axis
+    pub fn loss_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: &BlasTensor,
+        target: &BlasTensor,
+        with_grad: bool,
+    ) -> (BlasTensor, Option<BlasTensor>) {
+        match op {
+            BlasOpCode::MseLoss(reduction) => {
+                self.mse_loss_owned(input, target, reduction, with_grad)
+            }
+            BlasOpCode::L1Loss(reduction) => {
+                self.l1_loss_owned(input, target, reduction, with_grad)
+            }
+            BlasOpCode::HuberLoss(reduction, delta) => {
+                self.huber_loss_owned(input, target, reduction, delta, with_grad)
+            }
+            BlasOpCode::BceWithLogitsLoss(reduction) => {
+                self.bce_with_logits_loss_owned(input, target, reduction, with_grad)
+            }
+            BlasOpCode::CrossEntropyLoss(reduction) => {
+                self.cross_entropy_loss_owned(input, target, reduction, with_grad)
+            }
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    pub fn mse_loss_owned(
+        &self,
+        input: &BlasTensor,
+        target: &BlasTensor,
+        reduction: Reduction,
+        with_grad: bool,
+    ) -> (BlasTensor, Option<BlasTensor>) {
+        with_float_dtype!(input.dtype(), T => pointwise::<T, _>(input, target, reduction, with_grad, |x, y| {
+            let diff = x - y;
+            (diff * diff, diff + diff)
+        }))
+    }
+
+    // the gradient at x == y is taken as 0
+    pub fn l1_loss_owned(
+        &self,
+        input: &BlasTensor,
+        target: &BlasTensor,
+        reduction: Reduction,
+        with_grad: bool,
+    ) -> (BlasTensor, Option<BlasTensor>) {
+        with_float_dtype!(input.dtype(), T => pointwise::<T, _>(input, target, reduction, with_grad, |x, y| {
+            let diff = x - y;
+            (diff.abs(), sign(diff))
+        }))
+    }
+
+    // quadratic within delta of the target, linear outside
+    pub fn huber_loss_owned(
+        &self,
+        input: &BlasTensor,
+        target: &BlasTensor,
+        reduction: Reduction,
+        delta: f64,
+        with_grad: bool,
+    ) -> (BlasTensor, Option<BlasTensor>) {
+        if delta <= 0.0 {
+            panic!("huber delta must be positive, got {}", delta);
+        }
+        with_float_dtype!(input.dtype(), T => {
+            let delta = delta as T;
+            let half = 0.5 as T;
+            pointwise::<T, _>(input, target, reduction, with_grad, |x, y| {
+                let diff = x - y;
+                if diff.abs() <= delta {
+                    (half * diff * diff, diff)
+                } else {
+                    (delta * (diff.abs() - half * delta), delta * sign(diff))
+                }
+            })
+        })
+    }
+
+    // input holds logits and target probabilities in [0, 1]; computed as
+    // max(x, 0) - x * y + ln(1 + exp(-|x|)) so large logits don't overflow
+    pub fn bce_with_logits_loss_owned(
+        &self,
+        input: &BlasTensor,
+        target: &BlasTensor,
+        reduction: Reduction,
+        with_grad: bool,
+    ) -> (BlasTensor, Option<BlasTensor>) {
+        with_float_dtype!(input.dtype(), T => pointwise::<T, _>(input, target, reduction, with_grad, |x, y| {
+            let loss = x.max(0.0) - x * y + (-x.abs()).exp().ln_1p();
+            let sigmoid = (1.0 + (-x).exp()).recip();
+            (loss, sigmoid - y)
+        }))
+    }
+
+    // input holds logits with the classes along the last axis, labels the
+    // Int32 class of every lane, i.e. the shape of input without its last
+    // dim ([1] for a vector input)
+    pub fn cross_entropy_loss_owned(
+        &self,
+        input: &BlasTensor,
+        labels: &BlasTensor,
+        reduction: Reduction,
+        with_grad: bool,
+    ) -> (BlasTensor, Option<BlasTensor>) {
+        with_float_dtype!(input.dtype(), T => cross_entropy::<T>(input, labels, reduction, with_grad))
+    }
+}
+
+// f gives the loss of one element and its derivative w.r.t. the input
+fn pointwise<T, F>(
+    input: &BlasTensor,
+    target: &BlasTensor,
+    reduction: Reduction,
+    with_grad: bool,
+    f: F,
+) -> (BlasTensor, Option<BlasTensor>)
+where
+    T: TensorElement + Float,
+    F: Fn(T, T) -> (T, T),
+{
+    if target.dtype() != input.dtype() || target.shape != input.shape {
+        panic!(
+            "target of type {:?} and shape {:?} not match input of type {:?} and shape {:?}",
+            target.dtype(),
+            target.shape,
+            input.dtype(),
+            input.shape
+        );
+    }
+    let pairs = Zip::from(&input.view::<T>())
+        .and(&target.view::<T>())
+        .apply_collect(|&x, &y| f(x, y));
+    let loss = pairs.map(|pair| pair.0);
+    let grad = with_grad.then(|| {
+        let scale = grad_scale::<T>(reduction, loss.len());
+        like_input(input, pairs.map(|pair| pair.1 * scale))
+    });
+    (reduce(loss, reduction), grad)
+}
+
+fn cross_entropy<T: TensorElement + Float>(
+    input: &BlasTensor,
+    labels: &BlasTensor,
+    reduction: Reduction,
+    with_grad: bool,
+) -> (BlasTensor, Option<BlasTensor>) {
+    let ndims = input.ndims();
+    let mut lanes_shape = input.shape[..ndims - 1].to_vec();
+    if lanes_shape.is_empty() {
+        lanes_shape.push(1);
+    }
+    if labels.dtype() != DType::Int32 || labels.shape != lanes_shape {
+        panic!(
+            "labels must be Int32 of shape {:?}, got {:?} of shape {:?}",
+            lanes_shape,
+            labels.dtype(),
+            labels.shape
+        );
+    }
+    let axis = Axis(ndims - 1);
+    let classes = input.shape[ndims - 1];
+    let labels = labels.view::<i32>();
+    let x = input.view::<T>();
+    let mut losses = Vec::with_capacity(labels.len());
+    let mut lses = Vec::with_capacity(labels.len());
+    for (lane, &label) in x.lanes(axis).into_iter().zip(labels.iter()) {
+        if label < 0 || label as usize >= classes {
+            panic!("label {} out of range for {} classes", label, classes);
+        }
+        let max = lane.fold(T::neg_infinity(), |max, &x| if x > max { x } else { max });
+        let lse = max + lane.fold(T::zero(), |sum, &x| sum + (x - max).exp()).ln();
+        losses.push(lse - lane[label as usize]);
+        lses.push(lse);
+    }
+    let grad = with_grad.then(|| {
+        // softmax minus the one-hot label
+        let scale = grad_scale::<T>(reduction, labels.len());
+        let mut grad = x.to_owned();
+        let lanes = grad.lanes_mut(axis).into_iter();
+        for ((mut lane, &label), &lse) in lanes.zip(labels.iter()).zip(lses.iter()) {
+            lane.mapv_inplace(|x| (x - lse).exp() * scale);
+            lane[label as usize] = lane[label as usize] - scale;
+        }
+        like_input(input, grad)
+    });
+    let loss = Array::from(losses).into_shape(IxDyn(&lanes_shape)).unwrap();
+    (reduce(loss, reduction), grad)
+}
+
+fn reduce<T: TensorElement + Float>(loss: ArrayD<T>, reduction: Reduction) -> BlasTensor {
+    let total = loss.iter().fold(T::zero(), |sum, &x| sum + x);
+    match reduction {
+        Reduction::None => BlasTensor::from_array(loss),
+        Reduction::Sum => BlasTensor::from_array(arr1(&[total]).into_dyn()),
+        Reduction::Mean => {
+            let mean = total / T::from(loss.len().max(1)).unwrap();
+            BlasTensor::from_array(arr1(&[mean]).into_dyn())
+        }
+    }
+}
+
+fn grad_scale<T: Float>(reduction: Reduction, count: usize) -> T {
+    match reduction {
+        Reduction::Mean => T::from(count.max(1)).unwrap().recip(),
+        _ => T::one(),
+    }
+}
+
+// gradients keep the channel tag of the input
+fn like_input<T: TensorElement>(input: &BlasTensor, grad: ArrayD<T>) -> BlasTensor {
+    let grad = BlasTensor::from_array(grad);
+    match input.layout {
+        Layout::ChannelsFirst | Layout::ChannelsLast => grad.with_layout(input.layout),
+        _ => grad,
+    }
+}
+
+fn sign<T: Float>(x: T) -> T {
+    if x > T::zero() {
+        T::one()
+    } else if x < T::zero() {
+        -T::one()
+    } else {
+        T::zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mse_loss() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let y = BlasTensor::from_vec_shape(vec![1.0, 0.0, 4.0, 2.0], vec![2, 2]);
+        let (loss, grad) =
+            exec.loss_compute_owned(BlasOpCode::MseLoss(Reduction::Mean), &x, &y, true);
+        assert_eq!(loss, BlasTensor::from_vec(vec![2.25]));
+        let gref = BlasTensor::from_vec_shape(vec![0.0, 1.0, -0.5, 1.0], vec![2, 2]);
+        assert_eq!(grad.unwrap(), gref);
+
+        let (loss, grad) = exec.mse_loss_owned(&x, &y, Reduction::None, false);
+        assert_eq!(
+            loss,
+            BlasTensor::from_vec_shape(vec![0.0, 4.0, 1.0, 4.0], vec![2, 2])
+        );
+        assert!(grad.is_none());
+    }
+
+    #[test]
+    fn test_l1_and_huber_loss() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::from_vec_shape_f64(vec![0.5, -3.0, 2.0], vec![3]);
+        let y = BlasTensor::from_vec_shape_f64(vec![0.0, 0.0, 2.0], vec![3]);
+        let (loss, grad) = exec.l1_loss_owned(&x, &y, Reduction::Sum, true);
+        assert_eq!(loss, BlasTensor::from_vec_shape_f64(vec![3.5], vec![1]));
+        assert_eq!(
+            grad.unwrap(),
+            BlasTensor::from_vec_shape_f64(vec![1.0, -1.0, 0.0], vec![3])
+        );
+
+        let op = BlasOpCode::HuberLoss(Reduction::None, 1.0);
+        let (loss, grad) = exec.loss_compute_owned(op, &x, &y, true);
+        assert_eq!(
+            loss,
+            BlasTensor::from_vec_shape_f64(vec![0.125, 2.5, 0.0], vec![3])
+        );
+        assert_eq!(
+            grad.unwrap(),
+            BlasTensor::from_vec_shape_f64(vec![0.5, -1.0, 0.0], vec![3])
+        );
+    }
+
+    #[test]
+    fn test_bce_with_logits_stable() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::from_vec_shape_f64(vec![100.0, -100.0, 0.0], vec![3]);
+        let y = BlasTensor::from_vec_shape_f64(vec![1.0, 1.0, 0.5], vec![3]);
+        let op = BlasOpCode::BceWithLogitsLoss(Reduction::None);
+        let (loss, grad) = exec.loss_compute_owned(op, &x, &y, true);
+        let loss = loss.view::<f64>();
+        assert!(loss[[0]].abs() < 1e-40);
+        assert!((loss[[1]] - 100.0).abs() < 1e-12);
+        assert!((loss[[2]] - std::f64::consts::LN_2).abs() < 1e-12);
+        let gref = BlasTensor::from_vec_shape_f64(vec![0.0, -1.0, 0.0], vec![3]);
+        assert!(grad.unwrap().all_close(&gref, 1e-12));
+    }
+
+    #[test]
+    fn test_cross_entropy_loss() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::normal_double(vec![2, 3, 5], 0.0, 2.0);
+        let labels = BlasTensor::from_vec_shape_i32(vec![0, 4, 2, 1, 1, 3], vec![2, 3]);
+        let (loss, grad) = exec.cross_entropy_loss_owned(&x, &labels, Reduction::None, true);
+        assert_eq!(loss.shape(), [2, 3]);
+
+        let log_p = exec.log_softmax_owned(x.clone(), None);
+        let log_p = log_p.view::<f64>();
+        let loss = loss.view::<f64>();
+        for ((i, j), &label) in labels
+            .view::<i32>()
+            .into_dimensionality::<Ix2>()
+            .unwrap()
+            .indexed_iter()
+        {
+            assert!((loss[[i, j]] + log_p[[i, j, label as usize]]).abs() < 1e-12);
+        }
+        // every lane of softmax - one_hot sums to 0
+        let sums = exec.sum_owned(grad.unwrap(), Some(2), false);
+        assert!(sums.all_close(&BlasTensor::zeros_double(vec![2, 3]), 1e-12));
+    }
+
+    #[test]
+    fn test_cross_entropy_mean_grad_fd() {
+        // central differences of the mean loss against the analytic gradient
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::normal_double(vec![4, 3], 0.0, 1.0);
+        let labels = BlasTensor::from_vec_shape_i32(vec![2, 0, 1, 2], vec![4]);
+        let op = BlasOpCode::CrossEntropyLoss(Reduction::Mean);
+        let (_, grad) = exec.loss_compute_owned(op, &x, &labels, true);
+        let grad = grad.unwrap();
+        let h = 1e-6;
+        for idx in [[0, 0], [1, 0], [3, 2], [2, 1]] {
+            let mut plus = x.clone();
+            plus.view_mut::<f64>()[&idx[..]] += h;
+            let mut minus = x.clone();
+            minus.view_mut::<f64>()[&idx[..]] -= h;
+            let lp = exec.loss_compute_owned(op, &plus, &labels, false).0;
+            let lm = exec.loss_compute_owned(op, &minus, &labels, false).0;
+            let fd = (lp.view::<f64>()[[0]] - lm.view::<f64>()[[0]]) / (2.0 * h);
+            assert!((fd - grad.view::<f64>()[&idx[..]]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "label 3 out of range for 3 classes")]
+    fn test_cross_entropy_label_range() {
+        let exec = BlasExecutor::new();
+        let x = BlasTensor::zeros(vec![2, 3]);
+        let labels = BlasTensor::from_vec_shape_i32(vec![0, 3], vec![2]);
+        exec.cross_entropy_loss_owned(&x, &labels, Reduction::Mean, false);
+    }
+}
@@ -0,0 +1,367 @@
+use ndarray::prelude::*;
+use num_traits::Float;
+
+use crate::blas_conv::channels_last;
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_float_dtype, BlasTensor, TensorElement};
+
+// Normalisation of Float/Double tensors, computed in place on the consumed
+// input. gamma and beta are optional per-feature scale and shift vectors of
+// the normalised dim, missing ones act as 1 and 0.
+impl BlasExecutor {
+    // LayerNorm and RmsNorm over the last logical axis
+    pub fn norm_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: BlasTensor,
+        gamma: Option<&BlasTensor>,
+        beta: Option<&BlasTensor>,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::LayerNorm(eps) => self.layer_norm_owned(input, gamma, beta, eps),
+            BlasOpCode::RmsNorm(eps) => self.rms_norm_owned(input, gamma, beta, eps),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // BatchNorm normalises with the running stats, BatchNormTraining with
+    // the batch stats and then moves the running stats towards them by
+    // momentum, the variance unbiased like the usual frameworks do
+    pub fn batch_norm_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: BlasTensor,
+        running_mean: &mut BlasTensor,
+        running_var: &mut BlasTensor,
+        gamma: Option<&BlasTensor>,
+        beta: Option<&BlasTensor>,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::BatchNorm(eps) => {
+                self.batch_norm_owned(input, running_mean, running_var, gamma, beta, eps)
+            }
+            BlasOpCode::BatchNormTraining { eps, momentum } => {
+                let count = input.numel() / input.shape[channel_axis(&input)].max(1);
+                let (out, mean, var) = self.batch_norm_training_owned(input, gamma, beta, eps);
+                let unbias = count as f64 / (count.max(2) - 1) as f64;
+                with_float_dtype!(out.dtype(), T => {
+                    update_running::<T>(running_mean, &mean, momentum, 1.0);
+                    update_running::<T>(running_var, &var, momentum, unbias);
+                });
+                out
+            }
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    pub fn layer_norm_owned(
+        &self,
+        input: BlasTensor,
+        gamma: Option<&BlasTensor>,
+        beta: Option<&BlasTensor>,
+        eps: f64,
+    ) -> BlasTensor {
+        with_float_dtype!(input.dtype(), T => trailing_norm::<T>(input, gamma, beta, eps, true))
+    }
+
+    // scales by the root mean square only, nothing is subtracted
+    pub fn rms_norm_owned(
+        &self,
+        input: BlasTensor,
+        gamma: Option<&BlasTensor>,
+        beta: Option<&BlasTensor>,
+        eps: f64,
+    ) -> BlasTensor {
+        with_float_dtype!(input.dtype(), T => trailing_norm::<T>(input, gamma, beta, eps, false))
+    }
+
+    // inference mode, the channel dim is the last one like for conv, or 1
+    // when the tensor is tagged ChannelsFirst; the stats are vectors of the
+    // channel dim
+    pub fn batch_norm_owned(
+        &self,
+        input: BlasTensor,
+        mean: &BlasTensor,
+        var: &BlasTensor,
+        gamma: Option<&BlasTensor>,
+        beta: Option<&BlasTensor>,
+        eps: f64,
+    ) -> BlasTensor {
+        with_float_dtype!(input.dtype(), T => {
+            let channels = input.shape[channel_axis(&input)];
+            let mean = param_vector::<T>(Some(mean), channels, "mean").unwrap();
+            let var = param_vector::<T>(Some(var), channels, "var").unwrap();
+            channel_norm::<T>(input, &mean.to_vec(), &var.to_vec(), gamma, beta, eps)
+        })
+    }
+
+    // training mode, returns the output with the batch mean and the biased
+    // batch variance it was normalised with, for the backward pass
+    pub fn batch_norm_training_owned(
+        &self,
+        input: BlasTensor,
+        gamma: Option<&BlasTensor>,
+        beta: Option<&BlasTensor>,
+        eps: f64,
+    ) -> (BlasTensor, BlasTensor, BlasTensor) {
+        with_float_dtype!(input.dtype(), T => {
+            let axis = Axis(channel_axis(&input));
+            let view = input.view::<T>();
+            let (mean, var): (Vec<T>, Vec<T>) = view
+                .axis_iter(axis)
+                .map(|channel| moments(channel.iter().cloned()))
+                .unzip();
+            let out = channel_norm::<T>(input, &mean, &var, gamma, beta, eps);
+            (
+                out,
+                BlasTensor::from_array(Array1::from(mean).into_dyn()),
+                BlasTensor::from_array(Array1::from(var).into_dyn()),
+            )
+        })
+    }
+}
+
+fn trailing_norm<T: TensorElement + Float>(
+    mut input: BlasTensor,
+    gamma: Option<&BlasTensor>,
+    beta: Option<&BlasTensor>,
+    eps: f64,
+    center: bool,
+) -> BlasTensor {
+    let axis = Axis(input.ndims() - 1);
+    let dim = input.shape[axis.index()];
+    let gamma = param_vector::<T>(gamma, dim, "gamma");
+    let beta = param_vector::<T>(beta, dim, "beta");
+    let eps = T::from(eps).unwrap();
+    let n = T::from(dim).unwrap();
+    for mut lane in input.view_mut::<T>().lanes_mut(axis) {
+        let (mean, scale) = if center {
+            let (mean, var) = moments(lane.iter().cloned());
+            (mean, (var + eps).sqrt().recip())
+        } else {
+            let square = lane.fold(T::zero(), |sum, &x| sum + x * x) / n;
+            (T::zero(), (square + eps).sqrt().recip())
+        };
+        for (idx, x) in lane.iter_mut().enumerate() {
+            *x = affine(&gamma, &beta, idx, (*x - mean) * scale);
+        }
+    }
+    input
+}
+
+fn channel_norm<T: TensorElement + Float>(
+    mut input: BlasTensor,
+    mean: &[T],
+    var: &[T],
+    gamma: Option<&BlasTensor>,
+    beta: Option<&BlasTensor>,
+    eps: f64,
+) -> BlasTensor {
+    let axis = Axis(channel_axis(&input));
+    let channels = input.shape[axis.index()];
+    let gamma = param_vector::<T>(gamma, channels, "gamma");
+    let beta = param_vector::<T>(beta, channels, "beta");
+    let eps = T::from(eps).unwrap();
+    for (c, mut channel) in input.view_mut::<T>().axis_iter_mut(axis).enumerate() {
+        let scale = (var[c] + eps).sqrt().recip();
+        channel.mapv_inplace(|x| affine(&gamma, &beta, c, (x - mean[c]) * scale));
+    }
+    input
+}
+
+fn affine<T: Float>(
+    gamma: &Option<ArrayView1<'_, T>>,
+    beta: &Option<ArrayView1<'_, T>>,
+    idx: usize,
+    x: T,
+) -> T {
+    let x = gamma.as_ref().map_or(x, |gamma| x * gamma[idx]);
+    beta.as_ref().map_or(x, |beta| x + beta[idx])
+}
+
+// mean and biased variance
+fn moments<T: Float>(values: impl Iterator<Item = T> + Clone) -> (T, T) {
+    let (sum, count) = values
+        .clone()
+        .fold((T::zero(), 0), |(sum, count), x| (sum + x, count + 1));
+    let n = T::from(count.max(1)).unwrap();
+    let mean = sum / n;
+    let var = values.fold(T::zero(), |acc, x| acc + (x - mean) * (x - mean)) / n;
+    (mean, var)
+}
+
+fn update_running<T: TensorElement + Float>(
+    running: &mut BlasTensor,
+    batch: &BlasTensor,
+    momentum: f64,
+    factor: f64,
+) {
+    if running.shape != batch.shape {
+        panic!(
+            "running stats of shape {:?} not match batch stats {:?}",
+            running.shape, batch.shape
+        );
+    }
+    let momentum = T::from(momentum).unwrap();
+    let factor = T::from(factor).unwrap();
+    let batch = batch.view::<T>();
+    for (stat, &x) in running.view_mut::<T>().iter_mut().zip(batch.iter()) {
+        *stat = (T::one() - momentum) * *stat + momentum * x * factor;
+    }
+}
+
+fn param_vector<'a, T: TensorElement>(
+    param: Option<&'a BlasTensor>,
+    dim: usize,
+    name: &str,
+) -> Option<ArrayView1<'a, T>> {
+    let param = param?;
+    if param.shape != [dim] || param.dtype() != T::DTYPE {
+        panic!(
+            "{} of type {:?} and shape {:?} not match a {:?} dim of size {}",
+            name,
+            param.dtype(),
+            param.shape,
+            T::DTYPE,
+            dim
+        );
+    }
+    Some(param.view::<T>().into_dimensionality::<Ix1>().unwrap())
+}
+
+fn channel_axis(input: &BlasTensor) -> usize {
+    if input.ndims() < 2 {
+        panic!("batch norm needs a tensor with 2 dims or more");
+    }
+    if channels_last(input.layout) {
+        input.ndims() - 1
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blas_tensor::Layout;
+
+    #[test]
+    fn test_layer_norm() {
+        let exec = BlasExecutor::new();
+        let a =
+            BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0, -2.0, 0.0, 2.0, 4.0], vec![2, 4]);
+        let c = exec.norm_compute_owned(BlasOpCode::LayerNorm(0.0), a.clone(), None, None);
+        // (x - mean) / std with std = sqrt(1.25) and sqrt(5)
+        let k = 1.0 / 1.25f32.sqrt();
+        let row = vec![-1.5 * k, -0.5 * k, 0.5 * k, 1.5 * k];
+        let cref = BlasTensor::from_vec_shape([row.clone(), row].concat(), vec![2, 4]);
+        assert!(c.all_close(&cref, 1e-6));
+
+        let gamma = BlasTensor::from_vec(vec![2.0, 2.0, 1.0, 1.0]);
+        let beta = BlasTensor::from_vec(vec![0.0, 0.0, 0.0, 10.0]);
+        let c = exec.layer_norm_owned(a, Some(&gamma), Some(&beta), 1e-5);
+        assert!((c.view::<f32>()[[1, 0]] + 3.0 * k).abs() < 1e-4);
+        assert!((c.view::<f32>()[[1, 3]] - 10.0 - 1.5 * k).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_rms_norm_rank3() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_f64(vec![3.0, 4.0, -6.0, 8.0], vec![2, 1, 2]);
+        let gamma = BlasTensor::from_vec_shape_f64(vec![1.0, 0.5], vec![2]);
+        let c = exec.norm_compute_owned(BlasOpCode::RmsNorm(0.0), a, Some(&gamma), None);
+        // rms = sqrt(12.5) and sqrt(50)
+        let (r0, r1) = (12.5f64.sqrt(), 50f64.sqrt());
+        let cref = BlasTensor::from_vec_shape_f64(
+            vec![3.0 / r0, 2.0 / r0, -6.0 / r1, 4.0 / r1],
+            vec![2, 1, 2],
+        );
+        assert!(c.all_close(&cref, 1e-12));
+    }
+
+    #[test]
+    fn test_batch_norm_inference() {
+        let exec = BlasExecutor::new();
+        // NCHW with 2 channels
+        let a = BlasTensor::from_vec_shape(
+            vec![1.0, 3.0, 5.0, 7.0, 2.0, 2.0, 4.0, 4.0],
+            vec![2, 2, 1, 2],
+        )
+        .with_layout(Layout::ChannelsFirst);
+        let mut mean = BlasTensor::from_vec(vec![1.0, 4.0]);
+        let mut var = BlasTensor::from_vec(vec![4.0, 1.0]);
+        let beta = BlasTensor::from_vec(vec![0.0, 1.0]);
+        let c = exec.batch_norm_compute_owned(
+            BlasOpCode::BatchNorm(0.0),
+            a,
+            &mut mean,
+            &mut var,
+            None,
+            Some(&beta),
+        );
+        let cref = BlasTensor::from_vec_shape(
+            vec![0.0, 1.0, 2.0, 4.0, 0.5, 0.5, 1.0, 1.0],
+            vec![2, 2, 1, 2],
+        )
+        .with_layout(Layout::ChannelsFirst);
+        assert_eq!(c, cref);
+        assert_eq!(mean, BlasTensor::from_vec(vec![1.0, 4.0]));
+    }
+
+    #[test]
+    fn test_batch_norm_training_stats() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(vec![1.0, 10.0, 3.0, 20.0, 5.0, 30.0], vec![3, 2]);
+        let (c, mean, var) = exec.batch_norm_training_owned(a.clone(), None, None, 0.0);
+        assert_eq!(mean, BlasTensor::from_vec(vec![3.0, 20.0]));
+        assert!(var.all_close(&BlasTensor::from_vec(vec![8.0 / 3.0, 200.0 / 3.0]), 1e-4));
+        let k = 1.5f32.sqrt();
+        let cref = BlasTensor::from_vec_shape(vec![-k, -k, 0.0, 0.0, k, k], vec![3, 2]);
+        assert!(c.all_close(&cref, 1e-6));
+
+        let mut running_mean = BlasTensor::zeros(vec![2]);
+        let mut running_var = BlasTensor::ones(vec![2]);
+        exec.batch_norm_compute_owned(
+            BlasOpCode::BatchNormTraining {
+                eps: 0.0,
+                momentum: 0.1,
+            },
+            a,
+            &mut running_mean,
+            &mut running_var,
+            None,
+            None,
+        );
+        assert!(running_mean.all_close(&BlasTensor::from_vec(vec![0.3, 2.0]), 1e-6));
+        assert!(running_var.all_close(&BlasTensor::from_vec(vec![1.3, 10.9]), 1e-5));
+    }
+
+    #[test]
+    fn test_batch_norm_nhwc() {
+        let exec = BlasExecutor::new();
+        let nchw =
+            BlasTensor::uniform(vec![2, 3, 2, 2], -1.0, 1.0).with_layout(Layout::ChannelsFirst);
+        let nhwc = exec.to_layout_owned(nchw.clone(), Layout::ChannelsLast);
+        let gamma = BlasTensor::from_vec(vec![1.0, 2.0, 3.0]);
+        let (c, mean, _) = exec.batch_norm_training_owned(nhwc.clone(), Some(&gamma), None, 1e-5);
+        let (cref, mean_ref, _) = exec.batch_norm_training_owned(nchw, Some(&gamma), None, 1e-5);
+        assert!(mean.all_close(&mean_ref, 1e-6));
+        assert!(exec
+            .to_layout_owned(c.clone(), Layout::ChannelsFirst)
+            .all_close(&cref, 1e-5));
+
+        // untagged tensors are NHWC as well
+        let untagged = exec.to_layout_owned(nhwc, Layout::RowMajor);
+        let (c_untagged, _, _) = exec.batch_norm_training_owned(untagged, Some(&gamma), None, 1e-5);
+        assert!(c_untagged.all_close(&c, 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "gamma of type Float and shape [3] not match a Float dim of size 4")]
+    fn test_layer_norm_gamma_shape() {
+        let exec = BlasExecutor::new();
+        let gamma = BlasTensor::ones(vec![3]);
+        exec.layer_norm_owned(BlasTensor::zeros(vec![2, 4]), Some(&gamma), None, 1e-5);
+    }
+}
@@ -19,6 +19,13 @@
 //     }
 // }
 
+use crate::blas_attention::AttentionParams;
+use crate::blas_conv::ConvParams;
+use crate::blas_loss::Reduction;
+use crate::blas_optim::{AdamParams, SgdParams};
+use crate::blas_pool::PoolParams;
+use crate::blas_tensor::{CastMode, DType, Layout, PadMode};
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BlasOpCode {
     AddF,
@@ -37,4 +44,118 @@ pub enum BlasOpCode {
     GemmD,
     GemvF,
     GemvD,
+    // untyped elementwise, mixed operand types are promoted by DType::promote
+    Add,
+    Sub,
+    Mul,
+    Div,
+    // element type conversion to the target type
+    Cast(DType, CastMode),
+    // reductions, element type follows the input tensor
+    ReduceSum,
+    ReduceMean,
+    ReduceMax,
+    ReduceMin,
+    ReduceProd,
+    ArgMax,
+    ArgMin,
+    // unary elementwise, integer tensors only accept Neg/Abs/Relu/Sign and
+    // the no-op Floor/Ceil/Round
+    Neg,
+    Abs,
+    Exp,
+    Log,
+    Sqrt,
+    Rsqrt,
+    Sin,
+    Cos,
+    Tanh,
+    Sigmoid,
+    Relu,
+    Gelu,
+    Erf,
+    Floor,
+    Ceil,
+    Round,
+    Sign,
+    Reciprocal,
+    // tensor-scalar, the immediate is cast to the element type of the tensor
+    // and must be integral for integer tensors; RSub/RDiv compute
+    // `imm - tensor` and `imm / tensor`
+    AddScalar(f64),
+    SubScalar(f64),
+    MulScalar(f64),
+    DivScalar(f64),
+    PowScalar(f64),
+    MinScalar(f64),
+    MaxScalar(f64),
+    RSubScalar(f64),
+    RDivScalar(f64),
+    // comparisons yield Bool tensors, logical ops take Bool tensors
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicalAnd,
+    LogicalOr,
+    LogicalNot,
+    // where(cond, lhs, rhs) picks lhs where cond holds, rhs elsewhere
+    Where,
+    // layout changes with contiguous output; Transpose swaps the last two
+    // dims, Permute reorders all dims by the axes given to the executor
+    Transpose,
+    Permute,
+    // join N tensors along an axis, or split one into N along an axis;
+    // Chunk(n) splits into n near-equal parts
+    Concat,
+    Stack,
+    Split,
+    Chunk(usize),
+    // converts the storage or the channel order to the target layout
+    ToLayout(Layout),
+    // widens every axis by the (before, after) counts given to the executor
+    Pad(PadMode),
+    // index driven moves along the axis given to the executor, the index
+    // tensor is Int32; ScatterAdd accumulates repeated indices
+    IndexSelect,
+    Gather,
+    Scatter,
+    ScatterAdd,
+    // f32 convolutions lowered to im2col + gemm; the backward ops give the
+    // gradients w.r.t. the forward input and weight
+    Conv1d(ConvParams),
+    Conv2d(ConvParams),
+    ConvBackwardData(ConvParams),
+    ConvBackwardWeight(ConvParams),
+    // pooling over the spatial dims of rank-3/4 activations
+    MaxPool1d(PoolParams),
+    MaxPool2d(PoolParams),
+    AvgPool1d(PoolParams),
+    AvgPool2d(PoolParams),
+    GlobalAvgPool,
+    // along the last axis, with an optional additive mask
+    Softmax,
+    LogSoftmax,
+    LogSumExp,
+    // LayerNorm/RmsNorm over the last axis and BatchNorm per channel, all
+    // with their epsilon; BatchNormTraining also updates running stats
+    LayerNorm(f64),
+    RmsNorm(f64),
+    BatchNorm(f64),
+    BatchNormTraining { eps: f64, momentum: f64 },
+    // fused softmax(q k^T * scale) v over [batch, heads, seq, dim]
+    Attention(AttentionParams),
+    // losses against a target, HuberLoss with its delta; CrossEntropyLoss
+    // takes logits and Int32 class labels
+    MseLoss(Reduction),
+    L1Loss(Reduction),
+    HuberLoss(Reduction, f64),
+    BceWithLogitsLoss(Reduction),
+    CrossEntropyLoss(Reduction),
+    // fused in-place parameter updates
+    Sgd(SgdParams),
+    Adam(AdamParams),
+    AdamW(AdamParams),
 }
@@ -0,0 +1,316 @@
+use ndarray::linalg::general_mat_mul;
+use ndarray::prelude::*;
+use ndarray::LinalgScalar;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_dtype, BlasTensor, TensorElement};
+
+// Arithmetic operators over BlasTensor, mainly for tests and prototyping
+// outside of the CRT. Tensor operands must share shape and mixed element
+// types are promoted like the untyped Add/Sub/Mul/Div opcodes; an f64
+// operand is applied through the tensor-scalar opcodes. Owned lhs operands
+// are updated in place, borrowed ones are cloned first.
+macro_rules! impl_binary_op {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident, $opcode:ident, $scalar_op:ident) => {
+        impl $assign_trait<&BlasTensor> for BlasTensor {
+            fn $assign_method(&mut self, rhs: &BlasTensor) {
+                BlasExecutor::new().binary_compute_inplace(BlasOpCode::$opcode, self, rhs);
+            }
+        }
+
+        impl $assign_trait<BlasTensor> for BlasTensor {
+            fn $assign_method(&mut self, rhs: BlasTensor) {
+                self.$assign_method(&rhs);
+            }
+        }
+
+        impl $assign_trait<f64> for BlasTensor {
+            fn $assign_method(&mut self, rhs: f64) {
+                BlasExecutor::new().scalar_compute_inplace(BlasOpCode::$scalar_op(rhs), self);
+            }
+        }
+
+        impl $trait<&BlasTensor> for BlasTensor {
+            type Output = BlasTensor;
+
+            fn $method(mut self, rhs: &BlasTensor) -> BlasTensor {
+                self.$assign_method(rhs);
+                self
+            }
+        }
+
+        impl $trait<BlasTensor> for BlasTensor {
+            type Output = BlasTensor;
+
+            fn $method(self, rhs: BlasTensor) -> BlasTensor {
+                self.$method(&rhs)
+            }
+        }
+
+        impl $trait<&BlasTensor> for &BlasTensor {
+            type Output = BlasTensor;
+
+            fn $method(self, rhs: &BlasTensor) -> BlasTensor {
+                self.clone().$method(rhs)
+            }
+        }
+
+        impl $trait<BlasTensor> for &BlasTensor {
+            type Output = BlasTensor;
+
+            fn $method(self, rhs: BlasTensor) -> BlasTensor {
+                self.clone().$method(&rhs)
+            }
+        }
+
+        impl $trait<f64> for BlasTensor {
+            type Output = BlasTensor;
+
+            fn $method(mut self, rhs: f64) -> BlasTensor {
+                self.$assign_method(rhs);
+                self
+            }
+        }
+
+        impl $trait<f64> for &BlasTensor {
+            type Output = BlasTensor;
+
+            fn $method(self, rhs: f64) -> BlasTensor {
+                self.clone().$method(rhs)
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, AddAssign, add_assign, Add, AddScalar);
+impl_binary_op!(Sub, sub, SubAssign, sub_assign, Sub, SubScalar);
+impl_binary_op!(Mul, mul, MulAssign, mul_assign, Mul, MulScalar);
+impl_binary_op!(Div, div, DivAssign, div_assign, Div, DivScalar);
+
+impl Neg for BlasTensor {
+    type Output = BlasTensor;
+
+    fn neg(self) -> BlasTensor {
+        BlasExecutor::new().unary_compute_owned(BlasOpCode::Neg, self)
+    }
+}
+
+impl Neg for &BlasTensor {
+    type Output = BlasTensor;
+
+    fn neg(self) -> BlasTensor {
+        -self.clone()
+    }
+}
+
+impl BlasTensor {
+    // matrix product over the last two dims. D3/D4 operands are batches of
+    // matrices sharing their leading dims; a D1 operand acts as a row (lhs)
+    // or column (rhs) vector and its dim is dropped from the result
+    pub fn matmul(&self, rhs: &BlasTensor) -> BlasTensor {
+        if self.dtype() != rhs.dtype() {
+            panic!(
+                "operands' types not match: {:?} vs {:?}",
+                self.dtype(),
+                rhs.dtype()
+            );
+        }
+        with_dtype!(self.dtype(), T => matmul::<T>(self, rhs))
+    }
+}
+
+fn matmul<T: TensorElement + LinalgScalar>(lhs: &BlasTensor, rhs: &BlasTensor) -> BlasTensor {
+    let (l, r) = (lhs.ndims(), rhs.ndims());
+    let inner_l = lhs.shape[l - 1];
+    let inner_r = if r == 1 {
+        rhs.shape[0]
+    } else {
+        rhs.shape[r - 2]
+    };
+    if inner_l != inner_r {
+        panic!(
+            "matmul inner dims not match: {:?} vs {:?}",
+            lhs.shape, rhs.shape
+        );
+    }
+    let a = lhs.view::<T>();
+    let b = rhs.view::<T>();
+    match (l, r) {
+        (1, 1) => {
+            let a = a.into_dimensionality::<Ix1>().unwrap();
+            let b = b.into_dimensionality::<Ix1>().unwrap();
+            BlasTensor::from_array(arr1(&[a.dot(&b)]).into_dyn())
+        }
+        (1, 2) => {
+            let a = a.into_dimensionality::<Ix1>().unwrap();
+            let b = b.into_dimensionality::<Ix2>().unwrap();
+            BlasTensor::from_array(a.dot(&b).into_dyn())
+        }
+        (2, 1) => {
+            let a = a.into_dimensionality::<Ix2>().unwrap();
+            let b = b.into_dimensionality::<Ix1>().unwrap();
+            BlasTensor::from_array(a.dot(&b).into_dyn())
+        }
+        (2, 2) => {
+            let a = a.into_dimensionality::<Ix2>().unwrap();
+            let b = b.into_dimensionality::<Ix2>().unwrap();
+            BlasTensor::from_array(a.dot(&b).into_dyn())
+        }
+        (l, r) if l == r && lhs.shape[..l - 2] == rhs.shape[..r - 2] => {
+            let batch: usize = lhs.shape[..l - 2].iter().product();
+            let (m, k, n) = (lhs.shape[l - 2], inner_l, rhs.shape[r - 1]);
+            // padded and column-major views are strided, flatten a row-major copy
+            let (a, b) = (a.as_standard_layout(), b.as_standard_layout());
+            let a = a.view().into_shape([batch, m, k]).unwrap();
+            let b = b.view().into_shape([batch, k, n]).unwrap();
+            let mut out = Array3::<T>::zeros([batch, m, n]);
+            for idx in 0..batch {
+                general_mat_mul(
+                    T::one(),
+                    &a.index_axis(Axis(0), idx),
+                    &b.index_axis(Axis(0), idx),
+                    T::zero(),
+                    &mut out.index_axis_mut(Axis(0), idx),
+                );
+            }
+            let mut shape = lhs.shape[..l - 2].to_vec();
+            shape.extend_from_slice(&[m, n]);
+            BlasTensor::from_array(out.into_shape(shape).unwrap())
+        }
+        _ => panic!(
+            "matmul not support operands with shapes {:?} and {:?}",
+            lhs.shape, rhs.shape
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_sub_operators() {
+        let a = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        let b = BlasTensor::from_vec_shape(vec![6.0, 5.0, 4.0, 3.0, 2.0, 1.0], vec![2, 3]);
+        let c = &a + &b;
+        assert_eq!(c, BlasTensor::from_vec_shape(vec![7.0; 6], vec![2, 3]));
+
+        let c = c - a;
+        assert_eq!(c, b);
+    }
+
+    #[test]
+    fn test_mul_div_operators_i32() {
+        let a = BlasTensor::from_vec_shape_i32(vec![2, 4, 6, 8], vec![2, 1, 2]);
+        let b = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4], vec![2, 1, 2]);
+        let c = &a * &b;
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![2, 8, 18, 32], vec![2, 1, 2])
+        );
+
+        let c = a / b;
+        assert_eq!(c, BlasTensor::from_vec_shape_i32(vec![2; 4], vec![2, 1, 2]));
+    }
+
+    #[test]
+    fn test_assign_and_scalar_operators() {
+        let mut a = BlasTensor::from_vec_shape_f64(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let b = BlasTensor::from_vec_shape_f64(vec![1.0; 4], vec![2, 2]);
+        a += &b;
+        a *= 0.5;
+        a -= b;
+        assert_eq!(
+            a,
+            BlasTensor::from_vec_shape_f64(vec![0.0, 0.5, 1.0, 1.5], vec![2, 2])
+        );
+
+        let c = -(&a * 2.0 + 1.0);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_f64(vec![-1.0, -2.0, -3.0, -4.0], vec![2, 2])
+        );
+    }
+
+    #[test]
+    fn test_matmul_2d() {
+        let a = BlasTensor::ones(vec![17, 23]);
+        let b = BlasTensor::ones(vec![23, 18]);
+        let c = a.matmul(&b);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape([23.0; 17 * 18].to_vec(), vec![17, 18])
+        );
+    }
+
+    #[test]
+    fn test_matmul_vector() {
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4, 5, 6], vec![2, 3]);
+        let x = BlasTensor::from_vec_shape_i32(vec![1, 0, -1], vec![3]);
+        assert_eq!(
+            a.matmul(&x),
+            BlasTensor::from_vec_shape_i32(vec![-2, -2], vec![2])
+        );
+
+        let y = BlasTensor::from_vec_shape_i32(vec![1, 1], vec![2]);
+        assert_eq!(
+            y.matmul(&a),
+            BlasTensor::from_vec_shape_i32(vec![5, 7, 9], vec![3])
+        );
+        assert_eq!(
+            x.matmul(&x),
+            BlasTensor::from_vec_shape_i32(vec![2], vec![1])
+        );
+    }
+
+    #[test]
+    fn test_matmul_batched() {
+        let a = BlasTensor::from_vec_shape((0..12).map(|x| x as f32).collect(), vec![2, 2, 3]);
+        let b = BlasTensor::from_vec_shape(
+            vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0, 2.0, 0.0, 0.0, 2.0, 0.0, 0.0],
+            vec![2, 3, 2],
+        );
+        let c = a.matmul(&b);
+        let cref = BlasTensor::from_vec_shape(
+            vec![2.0, 3.0, 8.0, 9.0, 12.0, 14.0, 18.0, 20.0],
+            vec![2, 2, 2],
+        );
+        assert_eq!(c.shape(), [2, 2, 2]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_matmul_batched_strided() {
+        let a = BlasTensor::uniform(vec![2, 3, 5], -1.0, 1.0);
+        let b = BlasTensor::uniform(vec![2, 5, 4], -1.0, 1.0);
+        let cref = a.matmul(&b);
+        assert!(a.clone().aligned(64).matmul(&b).all_close(&cref, 1e-6));
+        assert!(a.matmul(&b.clone().aligned(64)).all_close(&cref, 1e-6));
+        let fortran = |t: &BlasTensor| {
+            let raw = t.view::<f32>().t().iter().cloned().collect();
+            BlasTensor::from_vec_fortran(raw, t.shape().to_vec())
+        };
+        assert!(fortran(&a).matmul(&fortran(&b)).all_close(&cref, 1e-6));
+
+        let a = BlasTensor::uniform(vec![2, 2, 3, 5], -1.0, 1.0);
+        let b = BlasTensor::uniform(vec![2, 2, 5, 4], -1.0, 1.0);
+        let cref = a.matmul(&b);
+        let c = fortran(&a).aligned(64).matmul(&b.clone().aligned(64));
+        assert_eq!(c.shape(), [2, 2, 3, 4]);
+        assert!(c.all_close(&cref, 1e-6));
+    }
+
+    #[test]
+    #[should_panic(expected = "operands' shapes not match")]
+    fn test_add_shape_mismatch() {
+        let _ = BlasTensor::zeros(vec![2, 3]) + BlasTensor::zeros(vec![3, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "matmul inner dims not match")]
+    fn test_matmul_inner_mismatch() {
+        BlasTensor::zeros(vec![2, 3]).matmul(&BlasTensor::zeros(vec![2, 3]));
+    }
+}
@@ -0,0 +1,301 @@
+use ndarray::Zip;
+use num_traits::Float;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{check_operands, with_float_dtype, BlasTensor, TensorElement};
+
+// weight_decay adds weight_decay * param to the gradient; nesterov looks
+// ahead along the momentum buffer
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SgdParams {
+    pub lr: f64,
+    pub momentum: f64,
+    pub weight_decay: f64,
+    pub nesterov: bool,
+}
+
+impl SgdParams {
+    pub fn new(lr: f64) -> Self {
+        SgdParams {
+            lr,
+            momentum: 0.0,
+            weight_decay: 0.0,
+            nesterov: false,
+        }
+    }
+}
+
+// shared by Adam, where weight_decay is added to the gradient, and AdamW,
+// where it shrinks the parameter directly
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AdamParams {
+    pub lr: f64,
+    pub betas: (f64, f64),
+    pub eps: f64,
+    pub weight_decay: f64,
+}
+
+impl AdamParams {
+    pub fn new(lr: f64) -> Self {
+        AdamParams {
+            lr,
+            betas: (0.9, 0.999),
+            eps: 1e-8,
+            weight_decay: 0.0,
+        }
+    }
+}
+
+// One fused pass over a Float/Double parameter, its gradient and its state
+// tensors, all of the same type and shape, updating param and the state in
+// place without temporaries.
+impl BlasExecutor {
+    // state is [momentum buffer] for Sgd (empty when momentum is 0) and
+    // [first moment, second moment] for Adam/AdamW, zero initialised by the
+    // caller; step counts the updates from 1 and is ignored by Sgd
+    pub fn optimizer_compute_side_effect(
+        &self,
+        op: BlasOpCode,
+        param: &mut BlasTensor,
+        grad: &BlasTensor,
+        state: &mut [BlasTensor],
+        step: usize,
+    ) {
+        match (op, state) {
+            (BlasOpCode::Sgd(params), []) => self.sgd_side_effect(param, grad, None, params),
+            (BlasOpCode::Sgd(params), [buf]) => {
+                self.sgd_side_effect(param, grad, Some(buf), params)
+            }
+            (BlasOpCode::Adam(params), [m, v]) => {
+                self.adam_side_effect(param, grad, m, v, step, params, false)
+            }
+            (BlasOpCode::AdamW(params), [m, v]) => {
+                self.adam_side_effect(param, grad, m, v, step, params, true)
+            }
+            (BlasOpCode::Sgd(_) | BlasOpCode::Adam(_) | BlasOpCode::AdamW(_), state) => {
+                panic!("{} state tensors not valid for {:?}", state.len(), op)
+            }
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    pub fn sgd_side_effect(
+        &self,
+        param: &mut BlasTensor,
+        grad: &BlasTensor,
+        momentum_buf: Option<&mut BlasTensor>,
+        params: SgdParams,
+    ) {
+        check_operands(param, grad);
+        match momentum_buf.as_deref() {
+            Some(buf) => check_operands(grad, buf),
+            None if params.momentum != 0.0 => panic!("sgd with momentum needs a momentum buffer"),
+            None => {}
+        }
+        with_float_dtype!(param.dtype(), T => sgd::<T>(param, grad, momentum_buf, params))
+    }
+
+    // decoupled selects AdamW
+    #[allow(clippy::too_many_arguments)]
+    pub fn adam_side_effect(
+        &self,
+        param: &mut BlasTensor,
+        grad: &BlasTensor,
+        m: &mut BlasTensor,
+        v: &mut BlasTensor,
+        step: usize,
+        params: AdamParams,
+        decoupled: bool,
+    ) {
+        check_operands(param, grad);
+        check_operands(grad, m);
+        check_operands(grad, v);
+        if step == 0 {
+            panic!("adam steps are counted from 1");
+        }
+        with_float_dtype!(param.dtype(), T => adam::<T>(param, grad, m, v, step, params, decoupled))
+    }
+}
+
+fn sgd<T: TensorElement + Float>(
+    param: &mut BlasTensor,
+    grad: &BlasTensor,
+    momentum_buf: Option<&mut BlasTensor>,
+    params: SgdParams,
+) {
+    let cast = |x: f64| T::from(x).unwrap();
+    let (lr, momentum, decay) = (
+        cast(params.lr),
+        cast(params.momentum),
+        cast(params.weight_decay),
+    );
+    let mut param = param.view_mut::<T>();
+    let grad = grad.view::<T>();
+    match momentum_buf {
+        Some(buf) => Zip::from(&mut param)
+            .and(&grad)
+            .and(&mut buf.view_mut::<T>())
+            .apply(|p, &g, b| {
+                let g = g + decay * *p;
+                *b = momentum * *b + g;
+                let step = if params.nesterov {
+                    g + momentum * *b
+                } else {
+                    *b
+                };
+                *p = *p - lr * step;
+            }),
+        None => Zip::from(&mut param)
+            .and(&grad)
+            .apply(|p, &g| *p = *p - lr * (g + decay * *p)),
+    }
+}
+
+fn adam<T: TensorElement + Float>(
+    param: &mut BlasTensor,
+    grad: &BlasTensor,
+    m: &mut BlasTensor,
+    v: &mut BlasTensor,
+    step: usize,
+    params: AdamParams,
+    decoupled: bool,
+) {
+    let cast = |x: f64| T::from(x).unwrap();
+    let (beta1, beta2) = params.betas;
+    // bias corrections of the zero initialised moments
+    let correction1 = cast(1.0 - beta1.powi(step as i32));
+    let correction2 = cast(1.0 - beta2.powi(step as i32)).sqrt();
+    let (lr, eps, decay) = (cast(params.lr), cast(params.eps), cast(params.weight_decay));
+    let (beta1, beta2) = (cast(beta1), cast(beta2));
+    let one = T::one();
+    Zip::from(&mut param.view_mut::<T>())
+        .and(&grad.view::<T>())
+        .and(&mut m.view_mut::<T>())
+        .and(&mut v.view_mut::<T>())
+        .apply(|p, &g, m, v| {
+            let g = if decoupled {
+                *p = *p * (one - lr * decay);
+                g
+            } else {
+                g + decay * *p
+            };
+            *m = beta1 * *m + (one - beta1) * g;
+            *v = beta2 * *v + (one - beta2) * g * g;
+            *p = *p - lr * (*m / correction1) / ((*v).sqrt() / correction2 + eps);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sgd_plain_and_decay() {
+        let exec = BlasExecutor::new();
+        let mut p = BlasTensor::from_vec(vec![1.0, 2.0]);
+        let g = BlasTensor::from_vec(vec![0.5, -1.0]);
+        exec.optimizer_compute_side_effect(
+            BlasOpCode::Sgd(SgdParams::new(0.1)),
+            &mut p,
+            &g,
+            &mut [],
+            1,
+        );
+        assert!(p.all_close(&BlasTensor::from_vec(vec![0.95, 2.1]), 1e-6));
+
+        let params = SgdParams {
+            weight_decay: 0.5,
+            ..SgdParams::new(0.1)
+        };
+        exec.sgd_side_effect(&mut p, &g, None, params);
+        assert!(p.all_close(&BlasTensor::from_vec(vec![0.8525, 2.095]), 1e-6));
+    }
+
+    #[test]
+    fn test_sgd_momentum() {
+        let exec = BlasExecutor::new();
+        let g = BlasTensor::from_vec_shape_f64(vec![1.0], vec![1]);
+        for (nesterov, expected) in [(false, [-1.0, -2.9]), (true, [-1.9, -4.61])] {
+            let params = SgdParams {
+                momentum: 0.9,
+                nesterov,
+                ..SgdParams::new(1.0)
+            };
+            let op = BlasOpCode::Sgd(params);
+            let mut p = BlasTensor::zeros_double(vec![1]);
+            let mut state = [BlasTensor::zeros_double(vec![1])];
+            for expected in expected {
+                exec.optimizer_compute_side_effect(op, &mut p, &g, &mut state, 1);
+                assert!((p.view::<f64>()[[0]] - expected).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adam_against_scalar_reference() {
+        let exec = BlasExecutor::new();
+        let params = AdamParams::new(0.01);
+        let mut p = BlasTensor::from_vec_shape_f64(vec![1.0, -2.0, 0.5, 3.0], vec![2, 2]);
+        let mut state = [
+            BlasTensor::zeros_double(vec![2, 2]),
+            BlasTensor::zeros_double(vec![2, 2]),
+        ];
+        let (mut x, mut m, mut v) = (1.0f64, 0.0, 0.0);
+        for step in 1..=5 {
+            // gradient of sum(p^2)
+            let g = exec.scalar_compute_owned(BlasOpCode::MulScalar(2.0), p.clone());
+            exec.optimizer_compute_side_effect(
+                BlasOpCode::Adam(params),
+                &mut p,
+                &g,
+                &mut state,
+                step,
+            );
+
+            let gx = 2.0 * x;
+            m = 0.9 * m + 0.1 * gx;
+            v = 0.999 * v + 0.001 * gx * gx;
+            let m_hat = m / (1.0 - 0.9f64.powi(step as i32));
+            let v_hat = v / (1.0 - 0.999f64.powi(step as i32));
+            x -= 0.01 * m_hat / (v_hat.sqrt() + 1e-8);
+            assert!((p.view::<f64>()[[0, 0]] - x).abs() < 1e-12);
+        }
+        // the first step moves every coordinate by about lr
+        assert!((x - (1.0 - 0.05)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_adamw_decouples_decay() {
+        let exec = BlasExecutor::new();
+        let params = AdamParams {
+            weight_decay: 0.1,
+            ..AdamParams::new(0.5)
+        };
+        let zeros = || BlasTensor::zeros(vec![2]);
+        let g = zeros();
+
+        // zero gradient: AdamW only shrinks the weights, Adam sees the
+        // decay as gradient and takes a full normalised step
+        let mut p = BlasTensor::from_vec(vec![2.0, -4.0]);
+        let mut state = [zeros(), zeros()];
+        exec.optimizer_compute_side_effect(BlasOpCode::AdamW(params), &mut p, &g, &mut state, 1);
+        assert!(p.all_close(&BlasTensor::from_vec(vec![1.9, -3.8]), 1e-6));
+        assert_eq!(state[0], zeros());
+
+        let mut p = BlasTensor::from_vec(vec![2.0, -4.0]);
+        let mut state = [zeros(), zeros()];
+        exec.optimizer_compute_side_effect(BlasOpCode::Adam(params), &mut p, &g, &mut state, 1);
+        assert!(p.all_close(&BlasTensor::from_vec(vec![1.5, -3.5]), 1e-5));
+    }
+
+    #[test]
+    #[should_panic(expected = "1 state tensors not valid for Adam")]
+    fn test_adam_missing_state() {
+        let exec = BlasExecutor::new();
+        let mut p = BlasTensor::zeros(vec![2]);
+        let g = BlasTensor::zeros(vec![2]);
+        let op = BlasOpCode::Adam(AdamParams::new(0.1));
+        exec.optimizer_compute_side_effect(op, &mut p, &g, &mut [BlasTensor::zeros(vec![2])], 1);
+    }
+}
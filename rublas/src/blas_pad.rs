@@ -0,0 +1,283 @@
+use ndarray::prelude::*;
+use ndarray::Slice;
+use std::mem::size_of;
+
+use crate::blas_cast::CastFrom;
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{
+    with_any_dtype, BlasTensor, CastMode, DType, Layout, PadMode, TensorElement, TensorKind,
+};
+
+// pads holds one (before, after) pair per logical axis
+impl BlasExecutor {
+    pub fn pad_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: BlasTensor,
+        pads: &[(usize, usize)],
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::Pad(mode) => self.pad_owned(input, pads, mode),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    pub fn pad_owned(
+        &self,
+        input: BlasTensor,
+        pads: &[(usize, usize)],
+        mode: PadMode,
+    ) -> BlasTensor {
+        if pads.len() != input.ndims() {
+            panic!(
+                "{} pads given for tensor with shape {:?}",
+                pads.len(),
+                input.shape
+            );
+        }
+        for (axis, &(before, after)) in pads.iter().enumerate() {
+            let dim = input.shape[axis];
+            let valid = match mode {
+                PadMode::Constant(_) => true,
+                PadMode::Reflect => before < dim && after < dim,
+                PadMode::Replicate => dim > 0 || before + after == 0,
+            };
+            if !valid {
+                panic!(
+                    "cannot pad axis {} of size {} by {:?} in {:?} mode",
+                    axis,
+                    dim,
+                    (before, after),
+                    mode
+                );
+            }
+        }
+        with_any_dtype!(input.dtype(), T => pad::<T>(&input, pads, mode))
+    }
+}
+
+fn pad<T: TensorElement + CastFrom>(
+    input: &BlasTensor,
+    pads: &[(usize, usize)],
+    mode: PadMode,
+) -> BlasTensor {
+    let view = input.view::<T>();
+    let shape: Vec<usize> = view
+        .shape()
+        .iter()
+        .zip(pads)
+        .map(|(&dim, &(before, after))| before + dim + after)
+        .collect();
+    let out_data = match mode {
+        PadMode::Constant(imm) => {
+            let mut out_data = ArrayD::from_elem(IxDyn(&shape), pad_value::<T>(imm));
+            let mut inner = out_data.view_mut();
+            for (axis, &(before, _)) in pads.iter().enumerate() {
+                let dim = view.shape()[axis];
+                inner.slice_axis_inplace(Axis(axis), Slice::from(before..before + dim));
+            }
+            inner.assign(&view);
+            out_data
+        }
+        _ => ArrayD::from_shape_fn(IxDyn(&shape), |idx| {
+            let src: Vec<usize> = (0..shape.len())
+                .map(|axis| {
+                    let pos = idx[axis] as isize - pads[axis].0 as isize;
+                    source_index(pos, view.shape()[axis] as isize, mode)
+                })
+                .collect();
+            view[IxDyn(&src)]
+        }),
+    };
+    let out = BlasTensor::from_array(out_data);
+    // the dims keep their meaning, so do channel tags
+    match input.layout {
+        Layout::ColMajor => out,
+        layout => out.with_layout(layout),
+    }
+}
+
+// index of the input element an output position at pos (relative to the
+// first input element) copies in Reflect/Replicate mode
+fn source_index(pos: isize, dim: isize, mode: PadMode) -> usize {
+    let index = match mode {
+        PadMode::Reflect if pos < 0 => -pos,
+        PadMode::Reflect if pos >= dim => 2 * (dim - 1) - pos,
+        _ => pos.clamp(0, dim - 1),
+    };
+    index as usize
+}
+
+// like the tensor-scalar immediates, integer tensors need an exact value
+fn pad_value<T: TensorElement + CastFrom>(imm: f64) -> T {
+    let value = T::cast_from(imm, CastMode::Saturating);
+    match T::DTYPE {
+        DType::Float | DType::Double => value,
+        _ if value.as_f64() == imm => value,
+        _ => panic!("immediate {} not representable as {:?}", imm, T::DTYPE),
+    }
+}
+
+impl BlasTensor {
+    // copy whose storage rows start at align_bytes boundaries: the buffer is
+    // aligned and the leading dim is rounded up to a multiple of the lane
+    // count, padding elements stay at their default; clones keep the leading
+    // dim but not necessarily the buffer alignment
+    pub fn aligned(&self, align_bytes: usize) -> BlasTensor {
+        with_any_dtype!(self.dtype(), T => BlasTensor {
+            data: aligned_storage::<T>(self, align_bytes),
+            shape: self.shape.clone(),
+            layout: self.layout,
+        })
+    }
+
+    // true when the storage begins at an align_bytes boundary and every row
+    // keeps that alignment
+    pub fn is_aligned(&self, align_bytes: usize) -> bool {
+        with_any_dtype!(self.dtype(), T => {
+            let ptr = T::storage(&self.data).unwrap().as_ptr() as usize;
+            ptr.is_multiple_of(align_bytes)
+                && (self.leading_dim() * size_of::<T>()).is_multiple_of(align_bytes)
+        })
+    }
+}
+
+fn aligned_storage<T: TensorElement + Default>(
+    tensor: &BlasTensor,
+    align_bytes: usize,
+) -> TensorKind {
+    let elem = size_of::<T>();
+    if !align_bytes.is_power_of_two() || align_bytes < elem {
+        panic!(
+            "alignment {} not valid for {:?} tensors",
+            align_bytes,
+            T::DTYPE
+        );
+    }
+    let lanes = align_bytes / elem;
+    // logical view in storage order, so its last axis is the contiguous one
+    let ordered = if tensor.layout == Layout::ColMajor {
+        tensor.view::<T>().reversed_axes()
+    } else {
+        tensor.view::<T>()
+    };
+    let dims = BlasTensor::storage_dims(ordered.shape());
+    let cols = *dims.last().unwrap();
+    let rows = tensor.numel() / cols.max(1);
+    let ld = cols.div_ceil(lanes) * lanes;
+
+    let mut buffer = Array1::<T>::default(rows * ld + lanes);
+    let misalignment = (buffer.as_ptr() as usize % align_bytes) / elem;
+    let offset = (lanes - misalignment) % lanes;
+    buffer.slice_axis_inplace(Axis(0), Slice::from(offset..offset + rows * ld));
+    let mut storage = buffer.into_shape([rows, ld]).unwrap();
+    let ordered = ordered.as_standard_layout();
+    storage
+        .slice_axis_mut(Axis(1), Slice::from(..cols))
+        .assign(&ordered.into_shape([rows, cols]).unwrap());
+    if dims.len() == 1 {
+        T::wrap(storage.into_shape(ld).unwrap().into_dyn())
+    } else {
+        T::wrap(storage.into_dyn())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pad_constant() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let c = exec.pad_compute_owned(
+            BlasOpCode::Pad(PadMode::Constant(-1.0)),
+            a,
+            &[(1, 0), (0, 1)],
+        );
+        let cref = BlasTensor::from_vec_shape(
+            vec![-1.0, -1.0, -1.0, 1.0, 2.0, -1.0, 3.0, 4.0, -1.0],
+            vec![3, 3],
+        );
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_pad_reflect_replicate() {
+        let exec = BlasExecutor::new();
+        let a = || BlasTensor::from_vec_shape_i32(vec![1, 2, 3], vec![3]);
+        let c = exec.pad_owned(a(), &[(2, 1)], PadMode::Reflect);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![3, 2, 1, 2, 3, 2], vec![6])
+        );
+        let c = exec.pad_owned(a(), &[(2, 1)], PadMode::Replicate);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![1, 1, 1, 2, 3, 3], vec![6])
+        );
+    }
+
+    #[test]
+    fn test_pad_nhwc_spatial() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape((0..8).map(|x| x as f32).collect(), vec![1, 2, 2, 2]);
+        let c = exec.pad_owned(
+            a.clone(),
+            &[(0, 0), (1, 1), (1, 1), (0, 0)],
+            PadMode::Replicate,
+        );
+        assert_eq!(c.shape(), [1, 4, 4, 2]);
+        assert_eq!(c.view::<f32>()[[0, 0, 0, 1]], 1.0);
+        assert_eq!(c.view::<f32>()[[0, 3, 3, 0]], 6.0);
+        assert_eq!(c.view::<f32>()[[0, 2, 1, 1]], a.view::<f32>()[[0, 1, 0, 1]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot pad axis 0 of size 3")]
+    fn test_pad_reflect_too_wide() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3], vec![3]);
+        exec.pad_owned(a, &[(3, 0)], PadMode::Reflect);
+    }
+
+    #[test]
+    fn test_aligned_storage_gemm() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::uniform(vec![17, 23], -1.0, 1.0);
+        let b = BlasTensor::uniform(vec![23, 18], -1.0, 1.0);
+        let a_aligned = a.aligned(64);
+        let b_aligned = b.aligned(64);
+        assert!(a_aligned.is_aligned(64));
+        assert!(a_aligned.is_padded());
+        assert_eq!(a_aligned.leading_dim(), 32);
+        assert_eq!(a_aligned.view::<f32>(), a.view::<f32>());
+
+        let c = exec.gemm_owned(a.clone(), b.clone());
+        let c_aligned = exec.gemm_owned(a_aligned, b_aligned);
+        assert!(c.all_close(&c_aligned, 1e-5));
+    }
+
+    #[test]
+    fn test_aligned_layouts_and_ops() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_fortran((0..6).map(|x| x as f64).collect(), vec![3, 2]);
+        let c = a.aligned(32);
+        assert_eq!(c.leading_dim(), 4);
+        assert_eq!(c.view::<f64>(), a.view::<f64>());
+
+        let c = exec.unary_compute_owned(BlasOpCode::Neg, c);
+        assert_eq!(c.view::<f64>()[[2, 1]], -5.0);
+        let c = c.reshape(&[6]);
+        assert!(!c.is_padded());
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_f64(vec![0.0, -3.0, -1.0, -4.0, -2.0, -5.0], vec![6])
+        );
+
+        let v = BlasTensor::from_vec_shape_i8(vec![1, 2, 3], vec![3]).aligned(16);
+        assert_eq!(v.leading_dim(), 16);
+        assert_eq!(v.view::<i8>().len(), 3);
+    }
+}
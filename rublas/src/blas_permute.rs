@@ -0,0 +1,143 @@
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_any_dtype, BlasTensor, Layout};
+
+// axes[i] names the input dim that becomes dim i of the output, like
+// numpy.transpose; results are copied into the last-dim-contiguous layout
+impl BlasExecutor {
+    // axes are ignored by Transpose
+    pub fn permute_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: BlasTensor,
+        axes: &[usize],
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::Transpose => self.transpose_owned(input),
+            BlasOpCode::Permute => self.permute_owned(input, axes),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // swaps the last two dims, leading dims act as a batch
+    pub fn transpose_owned(&self, input: BlasTensor) -> BlasTensor {
+        let ndims = input.ndims();
+        if ndims < 2 {
+            panic!("transpose needs a tensor with 2 dims or more");
+        }
+        let mut axes: Vec<usize> = (0..ndims).collect();
+        axes.swap(ndims - 2, ndims - 1);
+        self.permute_owned(input, &axes)
+    }
+
+    pub fn permute_owned(&self, input: BlasTensor, axes: &[usize]) -> BlasTensor {
+        let mut seen = vec![false; input.ndims()];
+        let valid = axes.len() == input.ndims()
+            && axes
+                .iter()
+                .all(|&axis| axis < seen.len() && !std::mem::replace(&mut seen[axis], true));
+        if !valid {
+            panic!(
+                "axes {:?} not a permutation of tensor with shape {:?}",
+                axes, input.shape
+            );
+        }
+        // only plain row-major storage already is the promised result
+        let identity = axes.iter().enumerate().all(|(idx, &axis)| idx == axis);
+        if identity && input.layout == Layout::RowMajor && !input.is_padded() {
+            return input;
+        }
+        with_any_dtype!(input.dtype(), T => {
+            let permuted = input.view::<T>().permuted_axes(axes);
+            BlasTensor::from_array(permuted.as_standard_layout().into_owned())
+        })
+    }
+}
+
+impl BlasTensor {
+    pub fn transpose(&self) -> BlasTensor {
+        BlasExecutor::new().transpose_owned(self.clone())
+    }
+
+    pub fn permute(&self, axes: &[usize]) -> BlasTensor {
+        BlasExecutor::new().permute_owned(self.clone(), axes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transpose_2d() {
+        let a = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        let c = a.transpose();
+        let cref = BlasTensor::from_vec_shape(vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0], vec![3, 2]);
+        assert_eq!(c, cref);
+        assert_eq!(c.transpose(), a);
+    }
+
+    #[test]
+    fn test_transpose_batched_i32() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32((0..12).collect(), vec![2, 2, 3]);
+        let c = exec.permute_compute_owned(BlasOpCode::Transpose, a, &[]);
+        let cref = BlasTensor::from_vec_shape_i32(
+            vec![0, 3, 1, 4, 2, 5, 6, 9, 7, 10, 8, 11],
+            vec![2, 3, 2],
+        );
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_permute_nchw_nhwc() {
+        let exec = BlasExecutor::new();
+        let nchw =
+            BlasTensor::from_vec_shape((0..24).map(|x| x as f32).collect(), vec![1, 2, 3, 4]);
+        let nhwc = exec.permute_compute_owned(BlasOpCode::Permute, nchw.clone(), &[0, 2, 3, 1]);
+        assert_eq!(nhwc.shape(), [1, 3, 4, 2]);
+        let view = nhwc.view::<f32>();
+        assert!(view.is_standard_layout());
+        assert_eq!(view[[0, 1, 2, 1]], nchw.view::<f32>()[[0, 1, 1, 2]]);
+
+        let back = nhwc.permute(&[0, 3, 1, 2]);
+        assert_eq!(back, nchw);
+    }
+
+    #[test]
+    fn test_permute_attention_heads() {
+        // [b, s, h, d] -> [b, h, s, d]
+        let a = BlasTensor::from_vec_shape((0..24).map(|x| x as f32).collect(), vec![1, 3, 2, 4]);
+        let c = a.permute(&[0, 2, 1, 3]);
+        assert_eq!(c.shape(), [1, 2, 3, 4]);
+        assert_eq!(c.view::<f32>()[[0, 1, 2, 3]], a.view::<f32>()[[0, 2, 1, 3]]);
+    }
+
+    #[test]
+    fn test_identity_permute_contiguous() {
+        let exec = BlasExecutor::new();
+        let fortran =
+            BlasTensor::from_vec_fortran(vec![1.0f32, 4.0, 2.0, 5.0, 3.0, 6.0], vec![2, 3]);
+        let c = exec.permute_owned(fortran, &[0, 1]);
+        let cref = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        assert_eq!(c, cref);
+
+        let padded = BlasTensor::ones(vec![3, 5]).aligned(64);
+        assert!(padded.is_padded());
+        let c = exec.permute_owned(padded, &[0, 1]);
+        assert!(!c.is_padded());
+        assert_eq!(c, BlasTensor::ones(vec![3, 5]));
+    }
+
+    #[test]
+    #[should_panic(expected = "not a permutation")]
+    fn test_permute_repeated_axis() {
+        BlasTensor::zeros(vec![2, 3, 4]).permute(&[0, 1, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "transpose needs a tensor with 2 dims or more")]
+    fn test_transpose_vector() {
+        BlasTensor::zeros(vec![4]).transpose();
+    }
+}
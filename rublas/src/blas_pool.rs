@@ -0,0 +1,382 @@
+use ndarray::prelude::*;
+use ndarray::Zip;
+use num_traits::Float;
+
+use crate::blas_conv::{from_nchw, lift, nchw_dims, nchw_view, Geometry};
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{BlasTensor, DType, TensorElement};
+
+// Pooling windows as [h, w] pairs; pool1d only reads the first entry of
+// each pair. count_pad makes average pooling divide by the full window
+// instead of by the elements inside the input, max pooling ignores it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PoolParams {
+    pub kernel: [usize; 2],
+    pub stride: [usize; 2],
+    pub padding: [usize; 2],
+    pub count_pad: bool,
+}
+
+impl PoolParams {
+    // non-overlapping windows, the stride is the kernel size
+    pub fn new(kernel: [usize; 2]) -> Self {
+        PoolParams {
+            kernel,
+            stride: kernel,
+            padding: [0, 0],
+            count_pad: false,
+        }
+    }
+}
+
+// Float/Double activations in the same convention as conv, channel-last
+// unless tagged ChannelsFirst; outputs keep the input's rank and layout tag
+impl BlasExecutor {
+    // max pooling only returns the values here, see max_pool2d_owned for
+    // the indices
+    pub fn pool_compute_owned(&self, op: BlasOpCode, input: &BlasTensor) -> BlasTensor {
+        match op {
+            BlasOpCode::MaxPool1d(params) => self.max_pool1d_owned(input, params).0,
+            BlasOpCode::MaxPool2d(params) => self.max_pool2d_owned(input, params).0,
+            BlasOpCode::AvgPool1d(params) => self.avg_pool1d_owned(input, params),
+            BlasOpCode::AvgPool2d(params) => self.avg_pool2d_owned(input, params),
+            BlasOpCode::GlobalAvgPool => self.global_avg_pool_owned(input),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    pub fn max_pool1d_owned(
+        &self,
+        input: &BlasTensor,
+        params: PoolParams,
+    ) -> (BlasTensor, BlasTensor) {
+        check_pool_input(input, Some(3));
+        max_pool_dispatch(input, params)
+    }
+
+    // values and Int32 indices of the maxima, an index is h * width + w
+    // within the input plane of its channel
+    pub fn max_pool2d_owned(
+        &self,
+        input: &BlasTensor,
+        params: PoolParams,
+    ) -> (BlasTensor, BlasTensor) {
+        check_pool_input(input, Some(4));
+        max_pool_dispatch(input, params)
+    }
+
+    pub fn avg_pool1d_owned(&self, input: &BlasTensor, params: PoolParams) -> BlasTensor {
+        check_pool_input(input, Some(3));
+        avg_pool_dispatch(input, params)
+    }
+
+    pub fn avg_pool2d_owned(&self, input: &BlasTensor, params: PoolParams) -> BlasTensor {
+        check_pool_input(input, Some(4));
+        avg_pool_dispatch(input, params)
+    }
+
+    // mean over every spatial position, spatial dims are kept with size 1
+    pub fn global_avg_pool_owned(&self, input: &BlasTensor) -> BlasTensor {
+        check_pool_input(input, None);
+        match input.dtype() {
+            DType::Float => global_avg_pool::<f32>(input),
+            _ => global_avg_pool::<f64>(input),
+        }
+    }
+}
+
+fn max_pool_dispatch(input: &BlasTensor, params: PoolParams) -> (BlasTensor, BlasTensor) {
+    let geom = pool_geometry(input, params);
+    match input.dtype() {
+        DType::Float => max_pool::<f32>(input, &geom),
+        _ => max_pool::<f64>(input, &geom),
+    }
+}
+
+fn avg_pool_dispatch(input: &BlasTensor, params: PoolParams) -> BlasTensor {
+    let geom = pool_geometry(input, params);
+    match input.dtype() {
+        DType::Float => avg_pool::<f32>(input, &geom, params.count_pad),
+        _ => avg_pool::<f64>(input, &geom, params.count_pad),
+    }
+}
+
+fn max_pool<T: TensorElement + Float>(
+    input: &BlasTensor,
+    geom: &Geometry,
+) -> (BlasTensor, BlasTensor) {
+    let x = nchw_view::<T>(input);
+    let (n, c) = (x.shape()[0], x.shape()[1]);
+    let [oh, ow] = geom.output;
+    let mut values = Array4::<T>::zeros([n, c, oh, ow]);
+    let mut indices = Array4::<i32>::zeros([n, c, oh, ow]);
+    Zip::indexed(&mut values)
+        .and(&mut indices)
+        .apply(|(b, ch, y, xo), value, index| {
+            let mut best = None;
+            for_each_window(geom, y, xo, |h, w| {
+                let candidate = x[[b, ch, h, w]];
+                // NaN wins so it propagates like in the elementwise ops
+                let better = match best {
+                    None => true,
+                    Some((max, _)) => candidate > max || candidate.is_nan() && !max.is_nan(),
+                };
+                if better {
+                    best = Some((candidate, h * geom.input[1] + w));
+                }
+            });
+            let (max, argmax) = best.unwrap();
+            *value = max;
+            *index = argmax as i32;
+        });
+    let ndims = input.ndims();
+    (
+        from_nchw(values, ndims, input.layout),
+        from_nchw(indices, ndims, input.layout),
+    )
+}
+
+fn avg_pool<T: TensorElement + Float>(
+    input: &BlasTensor,
+    geom: &Geometry,
+    count_pad: bool,
+) -> BlasTensor {
+    let x = nchw_view::<T>(input);
+    let (n, c) = (x.shape()[0], x.shape()[1]);
+    let [oh, ow] = geom.output;
+    let window = geom.kernel[0] * geom.kernel[1];
+    let out = Array4::from_shape_fn([n, c, oh, ow], |(b, ch, y, xo)| {
+        let (mut sum, mut count) = (T::zero(), 0);
+        for_each_window(geom, y, xo, |h, w| {
+            sum = sum + x[[b, ch, h, w]];
+            count += 1;
+        });
+        let count = if count_pad { window } else { count };
+        sum / T::from(count).unwrap()
+    });
+    from_nchw(out, input.ndims(), input.layout)
+}
+
+fn global_avg_pool<T: TensorElement + Float>(input: &BlasTensor) -> BlasTensor {
+    let x = nchw_view::<T>(input);
+    let (n, c, h, w) = x.dim();
+    let area = T::from(h * w).unwrap();
+    let out = Array4::from_shape_fn([n, c, 1, 1], |(b, ch, _, _)| {
+        x.slice(s![b, ch, .., ..]).sum() / area
+    });
+    from_nchw(out, input.ndims(), input.layout)
+}
+
+// calls f(h, w) for every input position of the window at output (y, x)
+fn for_each_window<F: FnMut(usize, usize)>(geom: &Geometry, y: usize, x: usize, mut f: F) {
+    for i in 0..geom.kernel[0] {
+        let Some(h) = geom.source(0, y, i) else {
+            continue;
+        };
+        for j in 0..geom.kernel[1] {
+            if let Some(w) = geom.source(1, x, j) {
+                f(h, w);
+            }
+        }
+    }
+}
+
+// every window has to overlap the input, so padding is at most half of the
+// kernel
+fn pool_geometry(input: &BlasTensor, params: PoolParams) -> Geometry {
+    let ndims = input.ndims();
+    let kernel = lift(ndims, params.kernel, 1);
+    let padding = lift(ndims, params.padding, 0);
+    if kernel.contains(&0) {
+        panic!("pooling kernel must be positive, got {:?}", params.kernel);
+    }
+    if (0..2).any(|axis| 2 * padding[axis] > kernel[axis]) {
+        panic!(
+            "padding {:?} exceeds half of kernel {:?}",
+            params.padding, params.kernel
+        );
+    }
+    let dims = nchw_dims(&input.shape(), input.layout);
+    Geometry::spatial(
+        ndims,
+        [dims[2], dims[3]],
+        kernel,
+        params.stride,
+        params.padding,
+        [1, 1],
+    )
+}
+
+fn check_pool_input(input: &BlasTensor, ndims: Option<usize>) {
+    if !matches!(input.dtype(), DType::Float | DType::Double) {
+        panic!(
+            "pooling only supports Float and Double tensors, got {:?}",
+            input.dtype()
+        );
+    }
+    let valid = match ndims {
+        Some(ndims) => input.ndims() == ndims,
+        None => (3..=4).contains(&input.ndims()),
+    };
+    if !valid {
+        panic!("pooling not support tensor with shape {:?}", input.shape());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blas_tensor::Layout;
+
+    #[test]
+    fn test_max_pool2d_with_indices() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(
+            vec![
+                1.0, 5.0, 2.0, 0.0, //
+                3.0, 4.0, 8.0, 6.0, //
+                0.0, 9.0, 1.0, 1.0, //
+                2.0, 2.0, 7.0, 3.0,
+            ],
+            vec![1, 4, 4, 1],
+        );
+        let (values, indices) = exec.max_pool2d_owned(&a, PoolParams::new([2, 2]));
+        assert_eq!(
+            values,
+            BlasTensor::from_vec_shape(vec![5.0, 8.0, 9.0, 7.0], vec![1, 2, 2, 1])
+        );
+        assert_eq!(
+            indices,
+            BlasTensor::from_vec_shape_i32(vec![1, 6, 9, 14], vec![1, 2, 2, 1])
+        );
+
+        let params = PoolParams {
+            stride: [1, 1],
+            padding: [1, 1],
+            ..PoolParams::new([3, 3])
+        };
+        let c = exec.pool_compute_owned(BlasOpCode::MaxPool2d(params), &a);
+        assert_eq!(c.shape(), [1, 4, 4, 1]);
+        assert_eq!(c.view::<f32>()[[0, 0, 0, 0]], 5.0);
+        assert_eq!(c.view::<f32>()[[0, 3, 0, 0]], 9.0);
+    }
+
+    #[test]
+    fn test_avg_pool2d_count_pad() {
+        let exec = BlasExecutor::new();
+        let a =
+            BlasTensor::from_vec_shape_f64((1..=4).map(|x| x as f64).collect(), vec![1, 2, 2, 1]);
+        let params = PoolParams {
+            stride: [1, 1],
+            padding: [1, 1],
+            ..PoolParams::new([2, 2])
+        };
+        let c = exec.avg_pool2d_owned(&a, params);
+        assert_eq!(c.shape(), [1, 3, 3, 1]);
+        assert_eq!(c.view::<f64>()[[0, 0, 0, 0]], 1.0);
+        assert_eq!(c.view::<f64>()[[0, 1, 1, 0]], 2.5);
+        assert_eq!(c.view::<f64>()[[0, 0, 1, 0]], 1.5);
+
+        let params = PoolParams {
+            count_pad: true,
+            ..params
+        };
+        let c = exec.pool_compute_owned(BlasOpCode::AvgPool2d(params), &a);
+        assert_eq!(c.view::<f64>()[[0, 0, 0, 0]], 0.25);
+        assert_eq!(c.view::<f64>()[[0, 1, 1, 0]], 2.5);
+        assert_eq!(c.view::<f64>()[[0, 0, 1, 0]], 0.75);
+    }
+
+    #[test]
+    fn test_pool1d() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(vec![1.0, 3.0, 2.0, 6.0, 4.0, 5.0], vec![1, 6, 1]);
+        let params = PoolParams {
+            stride: [2, 9],
+            ..PoolParams::new([3, 9])
+        };
+        let (values, indices) = exec.max_pool1d_owned(&a, params);
+        assert_eq!(
+            values,
+            BlasTensor::from_vec_shape(vec![3.0, 6.0], vec![1, 2, 1])
+        );
+        assert_eq!(
+            indices,
+            BlasTensor::from_vec_shape_i32(vec![1, 3], vec![1, 2, 1])
+        );
+
+        let c = exec.pool_compute_owned(BlasOpCode::AvgPool1d(PoolParams::new([2, 9])), &a);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape(vec![2.0, 4.0, 4.5], vec![1, 3, 1])
+        );
+    }
+
+    #[test]
+    fn test_pool_nhwc() {
+        let exec = BlasExecutor::new();
+        let nchw =
+            BlasTensor::uniform(vec![2, 3, 6, 4], -1.0, 1.0).with_layout(Layout::ChannelsFirst);
+        let nhwc = exec.to_layout_owned(nchw.clone(), Layout::ChannelsLast);
+        let params = PoolParams {
+            padding: [1, 0],
+            ..PoolParams::new([3, 2])
+        };
+        let (values, indices) = exec.max_pool2d_owned(&nhwc, params);
+        assert_eq!(values.layout, Layout::ChannelsLast);
+        assert_eq!(values.shape(), [2, 2, 2, 3]);
+        let (values_ref, indices_ref) = exec.max_pool2d_owned(&nchw, params);
+        assert_eq!(
+            exec.to_layout_owned(values, Layout::ChannelsFirst),
+            values_ref
+        );
+        assert_eq!(
+            exec.to_layout_owned(indices, Layout::ChannelsFirst),
+            indices_ref
+        );
+
+        let c = exec.avg_pool2d_owned(&nhwc, params);
+        let cref = exec.avg_pool2d_owned(&nchw, params);
+        assert!(exec
+            .to_layout_owned(c.clone(), Layout::ChannelsFirst)
+            .all_close(&cref, 1e-6));
+
+        // untagged activations are NHWC as well
+        let untagged = exec.to_layout_owned(nhwc, Layout::RowMajor);
+        assert!(exec.avg_pool2d_owned(&untagged, params).all_close(&c, 0.0));
+    }
+
+    #[test]
+    fn test_global_avg_pool() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape((0..12).map(|x| x as f32).collect(), vec![1, 3, 2, 2])
+            .with_layout(Layout::ChannelsLast);
+        let c = exec.pool_compute_owned(BlasOpCode::GlobalAvgPool, &a);
+        assert_eq!(c.shape(), [1, 1, 1, 2]);
+        assert_eq!(c.layout, Layout::ChannelsLast);
+        assert_eq!(
+            c.view::<f32>().iter().cloned().collect::<Vec<_>>(),
+            [5.0, 6.0]
+        );
+
+        let a = BlasTensor::from_vec_shape_f64(vec![1.0, 2.0, 3.0, 5.0], vec![2, 2, 1]);
+        let c = exec.global_avg_pool_owned(&a);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_f64(vec![1.5, 4.0], vec![2, 1, 1])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "padding [2, 0] exceeds half of kernel [3, 3]")]
+    fn test_pool_padding_too_wide() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::zeros(vec![1, 4, 4, 1]);
+        let params = PoolParams {
+            padding: [2, 0],
+            ..PoolParams::new([3, 3])
+        };
+        exec.avg_pool2d_owned(&a, params);
+    }
+}
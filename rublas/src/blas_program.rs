@@ -0,0 +1,440 @@
+use ndarray::linalg::{general_mat_mul, general_mat_vec_mul};
+use ndarray::prelude::*;
+use ndarray::{LinalgScalar, Zip};
+use num_traits::Float;
+use std::ops::Range;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_graph::{float_binary_fn, float_map, is_binary, untyped};
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{check_shapes, with_float_dtype, BlasTensor, DType, TensorElement};
+
+// arena offsets are multiples of this many elements, a 64 byte cache line
+// for Float
+const ALIGN: usize = 16;
+
+// handle of a tensor declared in a BlasProgram
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct TensorId(usize);
+
+#[derive(Debug, Clone)]
+struct Instruction {
+    op: BlasOpCode,
+    inputs: Vec<TensorId>,
+    output: TensorId,
+}
+
+// Straight-line BLAS program over tensors of one element type with shapes
+// known up front: elementwise Add/Sub/Mul/Div, unary and tensor-scalar
+// opcodes, GemmF/GemmD and GemvF/GemvD. Being single typed, the whole
+// program fits one arena of plain elements.
+#[derive(Debug, Clone)]
+pub struct BlasProgram {
+    dtype: DType,
+    shapes: Vec<Vec<usize>>,
+    inputs: Vec<TensorId>,
+    outputs: Vec<TensorId>,
+    instructions: Vec<Instruction>,
+}
+
+impl BlasProgram {
+    pub fn new(dtype: DType) -> Self {
+        if !matches!(dtype, DType::Float | DType::Double) {
+            panic!(
+                "programs only support Float and Double tensors, got {:?}",
+                dtype
+            );
+        }
+        BlasProgram {
+            dtype,
+            shapes: vec![],
+            inputs: vec![],
+            outputs: vec![],
+            instructions: vec![],
+        }
+    }
+
+    pub fn shape(&self, id: TensorId) -> &[usize] {
+        &self.shapes[id.0]
+    }
+
+    // tensors handed to BlasExecutor::run_program, in declaration order
+    pub fn input(&mut self, shape: Vec<usize>) -> TensorId {
+        let id = self.declare(shape);
+        self.inputs.push(id);
+        id
+    }
+
+    // tensors read back after the run, live until the end of the program
+    pub fn output(&mut self, id: TensorId) {
+        self.outputs.push(id);
+    }
+
+    pub fn push(&mut self, op: BlasOpCode, inputs: &[TensorId]) -> TensorId {
+        let shapes: Vec<&[usize]> = inputs.iter().map(|&id| self.shape(id)).collect();
+        let (op, shape) = match (op, &shapes[..]) {
+            (BlasOpCode::GemmF | BlasOpCode::GemmD, [a, b]) => {
+                if a.len() != 2 || b.len() != 2 || a[1] != b[0] {
+                    panic!("gemm operands' shapes not compatible: {:?} vs {:?}", a, b);
+                }
+                (op, vec![a[0], b[1]])
+            }
+            (BlasOpCode::GemvF | BlasOpCode::GemvD, [a, x]) => {
+                if a.len() != 2 || x.len() != 1 || a[1] != x[0] {
+                    panic!("gemv operands' shapes not compatible: {:?} vs {:?}", a, x);
+                }
+                (op, vec![a[0]])
+            }
+            (_, [a, b]) => {
+                check_shapes(a, b);
+                (untyped(op), a.to_vec())
+            }
+            (_, [a]) => {
+                // rejects unsupported opcodes while building
+                let _ = float_map::<f64>(op);
+                (op, a.to_vec())
+            }
+            _ => panic!("{} operands not valid for {:?}", inputs.len(), op),
+        };
+        let output = self.declare(shape);
+        self.instructions.push(Instruction {
+            op,
+            inputs: inputs.to_vec(),
+            output,
+        });
+        output
+    }
+
+    // Lifetimes run from the step writing a tensor (0 for inputs) to the
+    // last step reading it (the end for outputs). Tensors are placed largest
+    // first at the lowest aligned offset not used by any placed tensor whose
+    // lifetime overlaps, so an instruction never shares memory between its
+    // operands and its output.
+    pub fn plan(&self) -> MemoryPlan {
+        let count = self.shapes.len();
+        let end = self.instructions.len();
+        let mut lifetimes: Vec<(usize, usize)> = (0..count).map(|_| (0, 0)).collect();
+        for (step, inst) in self.instructions.iter().enumerate() {
+            lifetimes[inst.output.0] = (step, step);
+        }
+        for (step, inst) in self.instructions.iter().enumerate() {
+            for id in &inst.inputs {
+                lifetimes[id.0].1 = lifetimes[id.0].1.max(step);
+            }
+        }
+        for id in &self.outputs {
+            lifetimes[id.0].1 = end;
+        }
+
+        let sizes: Vec<usize> = self
+            .shapes
+            .iter()
+            .map(|shape| shape.iter().product())
+            .collect();
+        let mut order: Vec<usize> = (0..count).collect();
+        order.sort_by_key(|&id| (std::cmp::Reverse(sizes[id]), lifetimes[id].0));
+        let mut offsets = vec![0; count];
+        let mut placed: Vec<usize> = vec![];
+        for id in order {
+            let (first, last) = lifetimes[id];
+            let mut busy: Vec<Range<usize>> = placed
+                .iter()
+                .filter(|&&other| lifetimes[other].0 <= last && first <= lifetimes[other].1)
+                .map(|&other| offsets[other]..offsets[other] + sizes[other])
+                .collect();
+            busy.sort_by_key(|range| range.start);
+            let mut offset = 0;
+            for range in busy {
+                if offset + sizes[id] <= range.start {
+                    break;
+                }
+                offset = offset.max(range.end.div_ceil(ALIGN) * ALIGN);
+            }
+            offsets[id] = offset;
+            placed.push(id);
+        }
+        let arena_len = (0..count)
+            .map(|id| offsets[id] + sizes[id])
+            .max()
+            .unwrap_or(0);
+        MemoryPlan {
+            offsets,
+            sizes,
+            lifetimes,
+            arena_len,
+        }
+    }
+
+    fn declare(&mut self, shape: Vec<usize>) -> TensorId {
+        self.shapes.push(shape);
+        TensorId(self.shapes.len() - 1)
+    }
+}
+
+// arena placement of every tensor of a BlasProgram, in elements
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryPlan {
+    offsets: Vec<usize>,
+    sizes: Vec<usize>,
+    lifetimes: Vec<(usize, usize)>,
+    arena_len: usize,
+}
+
+impl MemoryPlan {
+    pub fn arena_len(&self) -> usize {
+        self.arena_len
+    }
+
+    // elements needed without any reuse, for comparison
+    pub fn unplanned_len(&self) -> usize {
+        self.sizes.iter().sum()
+    }
+
+    pub fn range(&self, id: TensorId) -> Range<usize> {
+        self.offsets[id.0]..self.offsets[id.0] + self.sizes[id.0]
+    }
+
+    // first and last instruction the tensor is live for
+    pub fn lifetime(&self, id: TensorId) -> (usize, usize) {
+        self.lifetimes[id.0]
+    }
+}
+
+// The arena is a plain 1-D tensor of the program's type, allocated once
+// and reused by every run.
+impl BlasExecutor {
+    pub fn alloc_arena(&self, program: &BlasProgram, plan: &MemoryPlan) -> BlasTensor {
+        with_float_dtype!(program.dtype, T => {
+            BlasTensor::from_array(ArrayD::<T>::zeros(vec![plan.arena_len().max(1)]))
+        })
+    }
+
+    // copies the inputs into the arena, runs the instructions there and
+    // copies the outputs out
+    pub fn run_program(
+        &self,
+        program: &BlasProgram,
+        plan: &MemoryPlan,
+        arena: &mut BlasTensor,
+        inputs: &[BlasTensor],
+    ) -> Vec<BlasTensor> {
+        if arena.dtype() != program.dtype || arena.numel() < plan.arena_len() {
+            panic!(
+                "arena of {:?} x {} too small for a plan of {:?} x {}",
+                arena.dtype(),
+                arena.numel(),
+                program.dtype,
+                plan.arena_len()
+            );
+        }
+        if inputs.len() != program.inputs.len() {
+            panic!(
+                "program takes {} inputs, got {}",
+                program.inputs.len(),
+                inputs.len()
+            );
+        }
+        with_float_dtype!(program.dtype, T => {
+            let mut view = arena.view_mut::<T>();
+            let memory = view.as_slice_mut().unwrap();
+            for (id, input) in program.inputs.iter().zip(inputs) {
+                check_shapes(program.shape(*id), &input.shape);
+                let dst = &mut memory[plan.range(*id)];
+                for (dst, &src) in dst.iter_mut().zip(input.view::<T>().iter()) {
+                    *dst = src;
+                }
+            }
+            for inst in &program.instructions {
+                run_instruction::<T>(program, plan, inst, memory);
+            }
+            program
+                .outputs
+                .iter()
+                .map(|id| {
+                    let data = memory[plan.range(*id)].to_vec();
+                    BlasTensor::from_array(ArrayD::from_shape_vec(program.shape(*id), data).unwrap())
+                })
+                .collect()
+        })
+    }
+}
+
+fn run_instruction<T: TensorElement + Float + LinalgScalar>(
+    program: &BlasProgram,
+    plan: &MemoryPlan,
+    inst: &Instruction,
+    memory: &mut [T],
+) {
+    let ranges: Vec<Range<usize>> = inst.inputs.iter().map(|&id| plan.range(id)).collect();
+    let (out, operands) = split_arena(memory, plan.range(inst.output), &ranges);
+    let view =
+        |idx: usize| ArrayView::from_shape(program.shape(inst.inputs[idx]), operands[idx]).unwrap();
+    let mut out = ArrayViewMut::from_shape(program.shape(inst.output), out).unwrap();
+    match inst.op {
+        BlasOpCode::GemmF | BlasOpCode::GemmD => {
+            let a = view(0).into_dimensionality::<Ix2>().unwrap();
+            let b = view(1).into_dimensionality::<Ix2>().unwrap();
+            let mut out = out.into_dimensionality::<Ix2>().unwrap();
+            general_mat_mul(T::one(), &a, &b, T::zero(), &mut out);
+        }
+        BlasOpCode::GemvF | BlasOpCode::GemvD => {
+            let a = view(0).into_dimensionality::<Ix2>().unwrap();
+            let x = view(1).into_dimensionality::<Ix1>().unwrap();
+            let mut out = out.into_dimensionality::<Ix1>().unwrap();
+            general_mat_vec_mul(T::one(), &a, &x, T::zero(), &mut out);
+        }
+        op if is_binary(op) => {
+            let f = float_binary_fn::<T>(op);
+            Zip::from(&mut out)
+                .and(&view(0))
+                .and(&view(1))
+                .apply(|out, &x, &y| *out = f(x, y));
+        }
+        op => {
+            let f = float_map::<T>(op);
+            Zip::from(&mut out)
+                .and(&view(0))
+                .apply(|out, &x| *out = f(x));
+        }
+    }
+}
+
+// the output range mutably and the operand ranges shared; the plan keeps
+// the output apart from every operand
+fn split_arena<'a, T>(
+    memory: &'a mut [T],
+    out: Range<usize>,
+    operands: &[Range<usize>],
+) -> (&'a mut [T], Vec<&'a [T]>) {
+    let (head, rest) = memory.split_at_mut(out.start);
+    let (out_slice, tail) = rest.split_at_mut(out.end - out.start);
+    let (head, tail) = (&*head, &*tail);
+    let operands = operands
+        .iter()
+        .map(|range| {
+            if range.end <= out.start {
+                &head[range.clone()]
+            } else if range.start >= out.end {
+                &tail[range.start - out.end..range.end - out.end]
+            } else {
+                panic!("operand {:?} overlaps output {:?}", range, out)
+            }
+        })
+        .collect();
+    (out_slice, operands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // y = relu(x w + b) for a batch of 8 with 16 features
+    fn dense_layer() -> (BlasProgram, [TensorId; 3], TensorId) {
+        let mut program = BlasProgram::new(DType::Float);
+        let x = program.input(vec![8, 16]);
+        let w = program.input(vec![16, 16]);
+        let b = program.input(vec![8, 16]);
+        let xw = program.push(BlasOpCode::GemmF, &[x, w]);
+        let z = program.push(BlasOpCode::AddF, &[xw, b]);
+        let y = program.push(BlasOpCode::Relu, &[z]);
+        program.output(y);
+        (program, [x, w, b], y)
+    }
+
+    #[test]
+    fn test_plan_lifetimes() {
+        let (program, [x, w, b], y) = dense_layer();
+        let plan = program.plan();
+        assert_eq!(plan.lifetime(x), (0, 0));
+        assert_eq!(plan.lifetime(w), (0, 0));
+        assert_eq!(plan.lifetime(b), (0, 1));
+        assert_eq!(plan.lifetime(y), (2, 3));
+        assert_eq!(plan.unplanned_len(), 6 * 128 + 128);
+    }
+
+    #[test]
+    fn test_plan_reuses_dead_slots() {
+        // a chain of unary ops only ever needs two live slots
+        let mut program = BlasProgram::new(DType::Double);
+        let mut t = program.input(vec![10, 10]);
+        for _ in 0..6 {
+            t = program.push(BlasOpCode::Tanh, &[t]);
+        }
+        program.output(t);
+        let plan = program.plan();
+        assert_eq!(plan.unplanned_len(), 700);
+        assert_eq!(plan.arena_len(), 112 + 100);
+    }
+
+    #[test]
+    fn test_plan_keeps_overlapping_lifetimes_apart() {
+        let (program, _, _) = dense_layer();
+        let plan = program.plan();
+        let ids: Vec<TensorId> = (0..program.shapes.len()).map(TensorId).collect();
+        for &a in &ids {
+            assert_eq!(plan.range(a).start % ALIGN, 0);
+            for &b in &ids {
+                let ((a0, a1), (b0, b1)) = (plan.lifetime(a), plan.lifetime(b));
+                let (ra, rb) = (plan.range(a), plan.range(b));
+                if a != b && a0 <= b1 && b0 <= a1 {
+                    assert!(ra.end <= rb.start || rb.end <= ra.start, "{:?} {:?}", a, b);
+                }
+            }
+        }
+        assert!(plan.arena_len() < plan.unplanned_len());
+    }
+
+    #[test]
+    fn test_run_program_matches_eager() {
+        let exec = BlasExecutor::new();
+        let (program, _, _) = dense_layer();
+        let plan = program.plan();
+        let mut arena = exec.alloc_arena(&program, &plan);
+        assert_eq!(arena.shape(), [plan.arena_len()]);
+        // the arena is reused across runs with new inputs
+        for _ in 0..2 {
+            let x = BlasTensor::uniform(vec![8, 16], -1.0, 1.0);
+            let w = BlasTensor::uniform(vec![16, 16], -1.0, 1.0);
+            let b = BlasTensor::uniform(vec![8, 16], -1.0, 1.0);
+            let inputs = [x.clone(), w.clone(), b.clone()];
+            let y = exec.run_program(&program, &plan, &mut arena, &inputs);
+
+            let z = exec.binary_compute_owned(BlasOpCode::AddF, exec.gemm_owned(x, w), b);
+            let yref = exec.unary_compute_owned(BlasOpCode::Relu, z);
+            assert_eq!(y.len(), 1);
+            assert!(y[0].all_close(&yref, 1e-5));
+        }
+    }
+
+    #[test]
+    fn test_run_program_gemv_and_scalar() {
+        let exec = BlasExecutor::new();
+        let mut program = BlasProgram::new(DType::Double);
+        let a = program.input(vec![3, 2]);
+        let x = program.input(vec![2]);
+        let y = program.push(BlasOpCode::GemvD, &[a, x]);
+        let y2 = program.push(BlasOpCode::MulScalar(2.0), &[y]);
+        let z = program.push(BlasOpCode::Sub, &[y2, y]);
+        program.output(y);
+        program.output(z);
+        let plan = program.plan();
+        let mut arena = exec.alloc_arena(&program, &plan);
+        let inputs = [
+            BlasTensor::from_vec_shape_f64(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![3, 2]),
+            BlasTensor::from_vec_shape_f64(vec![1.0, -1.0], vec![2]),
+        ];
+        let out = exec.run_program(&program, &plan, &mut arena, &inputs);
+        let yref = BlasTensor::from_vec_shape_f64(vec![-1.0, -1.0, -1.0], vec![3]);
+        assert_eq!(out, vec![yref.clone(), yref]);
+    }
+
+    #[test]
+    #[should_panic(expected = "too small for a plan")]
+    fn test_run_program_small_arena() {
+        let exec = BlasExecutor::new();
+        let (program, _, _) = dense_layer();
+        let plan = program.plan();
+        let mut arena = BlasTensor::zeros(vec![16]);
+        exec.run_program(&program, &plan, &mut arena, &[]);
+    }
+}
@@ -0,0 +1,588 @@
+use std::collections::{HashMap, VecDeque};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{
+    Arc, Condvar, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+};
+use std::thread::JoinHandle;
+
+use crate::blas_autodiff::is_scalar_op;
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::BlasTensor;
+
+// handle of a tensor owned by a CommandQueue, valid once the command
+// writing it completes and until it is released or taken
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct TensorHandle(usize);
+
+// a tensor, or the failure of the command that should have written it
+type Value = Result<BlasTensor, String>;
+type Slot = Arc<RwLock<Option<Value>>>;
+type Job = Box<dyn FnOnce(&BlasExecutor) + Send>;
+
+// a command is dropped from the scheduler once it has run, so a missing
+// id means done
+struct Command {
+    job: Option<Job>,
+    output: Option<Slot>,
+    waiting_on: usize,
+    dependents: Vec<usize>,
+}
+
+// commands of the last write and the reads since, per tensor
+#[derive(Default)]
+struct Access {
+    writer: Option<usize>,
+    readers: Vec<usize>,
+}
+
+struct Entry {
+    slot: Slot,
+    access: Access,
+}
+
+#[derive(Default)]
+struct Scheduler {
+    commands: HashMap<usize, Command>,
+    next_id: usize,
+    ready: VecDeque<usize>,
+    pending: usize,
+    failures: Vec<String>,
+    shutdown: bool,
+}
+
+struct Shared {
+    exec: Arc<BlasExecutor>,
+    scheduler: Mutex<Scheduler>,
+    wakeup: Condvar,
+}
+
+// Runs executor ops on a pool of worker threads while the submitter keeps
+// going. Each command lists the tensors it reads and writes; it waits for
+// the last writer of everything it touches (read after write, write after
+// write) and, when writing, for the reads issued since (write after read),
+// so independent commands run in parallel and dependent ones in submission
+// order. A panicking command fails the tensor it writes and every tensor
+// computed from that one; reading any of them panics with the original
+// failure, and synchronize reports each failure once.
+// Tensors stay queued until released or taken; commands still using them
+// keep their values alive.
+pub struct CommandQueue {
+    shared: Arc<Shared>,
+    entries: HashMap<usize, Entry>,
+    next_handle: usize,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl CommandQueue {
+    pub fn new(exec: Arc<BlasExecutor>, workers: usize) -> Self {
+        if workers == 0 {
+            panic!("command queue needs at least one worker");
+        }
+        let shared = Arc::new(Shared {
+            exec,
+            scheduler: Mutex::new(Scheduler::default()),
+            wakeup: Condvar::new(),
+        });
+        let workers = (0..workers)
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || work(&shared))
+            })
+            .collect();
+        CommandQueue {
+            shared,
+            entries: HashMap::new(),
+            next_handle: 0,
+            workers,
+        }
+    }
+
+    pub fn upload(&mut self, tensor: BlasTensor) -> TensorHandle {
+        self.new_slot(Some(tensor))
+    }
+
+    // Add/Sub/Mul/Div and GemmF on two inputs, unary and tensor-scalar
+    // opcodes on one, into a new tensor
+    pub fn enqueue(&mut self, op: BlasOpCode, inputs: &[TensorHandle]) -> TensorHandle {
+        if !matches!(inputs.len(), 1 | 2) {
+            panic!("{} operands not valid for {:?}", inputs.len(), op);
+        }
+        self.submit(inputs, move |exec, inputs| match inputs {
+            [lhs, rhs] => exec.binary_compute_owned(op, (*lhs).clone(), (*rhs).clone()),
+            [input] if is_scalar_op(op) => exec.scalar_compute_owned(op, (*input).clone()),
+            [input] => exec.unary_compute_owned(op, (*input).clone()),
+            _ => unreachable!(),
+        })
+    }
+
+    // overwrites target with op applied to it and the optional rhs, like
+    // the executor's *_compute_inplace
+    pub fn enqueue_inplace(
+        &mut self,
+        op: BlasOpCode,
+        target: TensorHandle,
+        rhs: Option<TensorHandle>,
+    ) {
+        let reads: Vec<TensorHandle> = rhs.into_iter().collect();
+        let target_slot = self.entry(target).slot.clone();
+        let read_slots = self.read_slots(&reads);
+        let job = move |exec: &BlasExecutor| {
+            let rhs = read_slots
+                .first()
+                .filter(|slot| !Arc::ptr_eq(slot, &target_slot))
+                .map(read_lock);
+            let mut guard = write_lock(&target_slot);
+            let rhs = match rhs.as_deref().map(stored).transpose() {
+                Ok(rhs) => rhs,
+                Err(failure) => {
+                    *guard = Some(Err(failure));
+                    return;
+                }
+            };
+            // a failed target stays failed
+            let Ok(value) = guard.as_mut().expect("tensor never written") else {
+                return;
+            };
+            match (read_slots.first(), rhs) {
+                (_, Some(rhs)) => exec.binary_compute_inplace(op, value, rhs),
+                // the rhs is the target itself
+                (Some(_), None) => {
+                    let rhs = value.clone();
+                    exec.binary_compute_inplace(op, value, &rhs)
+                }
+                (None, _) if is_scalar_op(op) => exec.scalar_compute_inplace(op, value),
+                (None, _) => exec.unary_compute_inplace(op, value),
+            }
+        };
+        self.schedule(&reads, Some(target), Box::new(job));
+    }
+
+    // runs f on the inputs into a new tensor
+    pub fn submit<F>(&mut self, inputs: &[TensorHandle], f: F) -> TensorHandle
+    where
+        F: FnOnce(&BlasExecutor, &[&BlasTensor]) -> BlasTensor + Send + 'static,
+    {
+        let output = self.new_slot(None);
+        let out_slot = self.entry(output).slot.clone();
+        let read_slots = self.read_slots(inputs);
+        let job = move |exec: &BlasExecutor| {
+            let guards: Vec<_> = read_slots.iter().map(read_lock).collect();
+            let inputs: Result<Vec<&BlasTensor>, String> =
+                guards.iter().map(|guard| stored(guard)).collect();
+            let value = inputs.map(|inputs| f(exec, &inputs));
+            *write_lock(&out_slot) = Some(value);
+        };
+        self.schedule(inputs, Some(output), Box::new(job));
+        output
+    }
+
+    // false while a command writing the tensor is still queued or running
+    pub fn is_ready(&self, handle: TensorHandle) -> bool {
+        let scheduler = self.shared.scheduler.lock().unwrap();
+        self.entry(handle)
+            .access
+            .writer
+            .is_none_or(|writer| !scheduler.commands.contains_key(&writer))
+    }
+
+    // blocks until the tensor is written and returns a copy of it, panics
+    // with the failure that kept it from being written
+    pub fn read(&self, handle: TensorHandle) -> BlasTensor {
+        self.wait_written(handle);
+        read_value(&self.entry(handle).slot)
+    }
+
+    // blocks until the tensor is written and hands it out of the queue,
+    // copying only while queued commands still read it
+    pub fn take(&mut self, handle: TensorHandle) -> BlasTensor {
+        self.wait_written(handle);
+        let slot = self.entries.remove(&handle.0).unwrap().slot;
+        match Arc::try_unwrap(slot) {
+            Ok(lock) => into_tensor(lock.into_inner().unwrap_or_else(PoisonError::into_inner)),
+            Err(slot) => read_value(&slot),
+        }
+    }
+
+    // drops the queue's reference to the tensor; commands already queued on
+    // it still run
+    pub fn release(&mut self, handle: TensorHandle) {
+        if self.entries.remove(&handle.0).is_none() {
+            panic!("tensor handle {:?} not valid", handle);
+        }
+    }
+
+    // blocks until every submitted command has run, panics with the
+    // failures since the last synchronize
+    pub fn synchronize(&self) {
+        let scheduler = self.shared.scheduler.lock().unwrap();
+        let scheduler = self
+            .shared
+            .wakeup
+            .wait_while(scheduler, |scheduler| scheduler.pending > 0)
+            .unwrap();
+        check_failures(scheduler);
+    }
+
+    fn entry(&self, handle: TensorHandle) -> &Entry {
+        match self.entries.get(&handle.0) {
+            Some(entry) => entry,
+            None => panic!("tensor handle {:?} not valid", handle),
+        }
+    }
+
+    fn wait_written(&self, handle: TensorHandle) {
+        if let Some(writer) = self.entry(handle).access.writer {
+            let scheduler = self.shared.scheduler.lock().unwrap();
+            drop(
+                self.shared
+                    .wakeup
+                    .wait_while(scheduler, |scheduler| {
+                        scheduler.commands.contains_key(&writer)
+                    })
+                    .unwrap(),
+            );
+        }
+    }
+
+    fn new_slot(&mut self, value: Option<BlasTensor>) -> TensorHandle {
+        let handle = TensorHandle(self.next_handle);
+        self.next_handle += 1;
+        self.entries.insert(
+            handle.0,
+            Entry {
+                slot: Arc::new(RwLock::new(value.map(Ok))),
+                access: Access::default(),
+            },
+        );
+        handle
+    }
+
+    fn read_slots(&self, handles: &[TensorHandle]) -> Vec<Slot> {
+        handles
+            .iter()
+            .map(|&handle| self.entry(handle).slot.clone())
+            .collect()
+    }
+
+    fn schedule(&mut self, reads: &[TensorHandle], write: Option<TensorHandle>, job: Job) {
+        let mut scheduler = self.shared.scheduler.lock().unwrap();
+        let id = scheduler.next_id;
+        scheduler.next_id += 1;
+        let mut deps: Vec<usize> = vec![];
+        for &handle in reads.iter().chain(&write) {
+            deps.extend(self.entry(handle).access.writer);
+        }
+        if let Some(handle) = write {
+            deps.extend(&self.entry(handle).access.readers);
+        }
+        deps.sort_unstable();
+        deps.dedup();
+        deps.retain(|dep| scheduler.commands.contains_key(dep));
+        for dep in &deps {
+            scheduler.commands.get_mut(dep).unwrap().dependents.push(id);
+        }
+        let output = write.map(|handle| self.entry(handle).slot.clone());
+        scheduler.commands.insert(
+            id,
+            Command {
+                job: Some(job),
+                output,
+                waiting_on: deps.len(),
+                dependents: vec![],
+            },
+        );
+        scheduler.pending += 1;
+        if deps.is_empty() {
+            scheduler.ready.push_back(id);
+            self.shared.wakeup.notify_all();
+        }
+
+        // reads of finished commands no longer hold back a write
+        for handle in reads {
+            let access = &mut self.entries.get_mut(&handle.0).unwrap().access;
+            access
+                .readers
+                .retain(|reader| scheduler.commands.contains_key(reader));
+            access.readers.push(id);
+        }
+        drop(scheduler);
+        if let Some(handle) = write {
+            self.entries.get_mut(&handle.0).unwrap().access = Access {
+                writer: Some(id),
+                readers: vec![],
+            };
+        }
+    }
+}
+
+// drains the queue before stopping the workers
+impl Drop for CommandQueue {
+    fn drop(&mut self) {
+        self.shared.scheduler.lock().unwrap().shutdown = true;
+        self.shared.wakeup.notify_all();
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
+
+fn work(shared: &Shared) {
+    let mut scheduler = shared.scheduler.lock().unwrap();
+    loop {
+        if let Some(id) = scheduler.ready.pop_front() {
+            let command = scheduler.commands.get_mut(&id).unwrap();
+            let (job, output) = (command.job.take().unwrap(), command.output.take());
+            drop(scheduler);
+            let result = catch_unwind(AssertUnwindSafe(|| job(&shared.exec)));
+            scheduler = shared.scheduler.lock().unwrap();
+            if let Err(payload) = result {
+                let message = payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&str>().map(|msg| msg.to_string()))
+                    .unwrap_or_default();
+                let failure = format!("command {} failed: {}", id, message);
+                if let Some(slot) = output {
+                    *write_lock(&slot) = Some(Err(failure.clone()));
+                }
+                scheduler.failures.push(failure);
+            }
+            let finished = scheduler.commands.remove(&id).unwrap();
+            scheduler.pending -= 1;
+            for dependent in finished.dependents {
+                let command = scheduler.commands.get_mut(&dependent).unwrap();
+                command.waiting_on -= 1;
+                if command.waiting_on == 0 {
+                    scheduler.ready.push_back(dependent);
+                }
+            }
+            shared.wakeup.notify_all();
+        } else if scheduler.shutdown && scheduler.pending == 0 {
+            return;
+        } else {
+            scheduler = shared.wakeup.wait(scheduler).unwrap();
+        }
+    }
+}
+
+// A failed command's panic is reported through the scheduler, so a lock it
+// poisoned is taken over rather than failing every later user of the slot.
+fn read_lock(slot: &Slot) -> RwLockReadGuard<'_, Option<Value>> {
+    slot.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write_lock(slot: &Slot) -> RwLockWriteGuard<'_, Option<Value>> {
+    slot.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+// input of a command, or the failure it inherits
+fn stored(value: &Option<Value>) -> Result<&BlasTensor, String> {
+    value
+        .as_ref()
+        .expect("tensor never written")
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+fn into_tensor(value: Option<Value>) -> BlasTensor {
+    match value.expect("tensor never written") {
+        Ok(tensor) => tensor,
+        Err(failure) => panic!("{}", failure),
+    }
+}
+
+fn read_value(slot: &Slot) -> BlasTensor {
+    into_tensor(read_lock(slot).clone())
+}
+
+// clears and panics with the failures once the lock is released, so the
+// queue can still be dropped
+fn check_failures(mut scheduler: MutexGuard<'_, Scheduler>) {
+    let failures = std::mem::take(&mut scheduler.failures);
+    drop(scheduler);
+    if !failures.is_empty() {
+        panic!("{}", failures.join("\n"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn queue(workers: usize) -> CommandQueue {
+        CommandQueue::new(Arc::new(BlasExecutor::new()), workers)
+    }
+
+    #[test]
+    fn test_queue_matches_eager() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::uniform(vec![4, 3], -1.0, 1.0);
+        let b = BlasTensor::uniform(vec![3, 5], -1.0, 1.0);
+        let c = BlasTensor::uniform(vec![4, 5], -1.0, 1.0);
+
+        let mut queue = queue(3);
+        let (ha, hb, hc) = (
+            queue.upload(a.clone()),
+            queue.upload(b.clone()),
+            queue.upload(c.clone()),
+        );
+        let ab = queue.enqueue(BlasOpCode::GemmF, &[ha, hb]);
+        let sum = queue.enqueue(BlasOpCode::Add, &[ab, hc]);
+        let out = queue.enqueue(BlasOpCode::Tanh, &[sum]);
+        let scaled = queue.enqueue(BlasOpCode::MulScalar(0.5), &[out]);
+        queue.synchronize();
+        assert!(queue.is_ready(scaled));
+
+        let sum = exec.binary_compute_owned(BlasOpCode::Add, exec.gemm_owned(a, b), c);
+        let cref = exec.unary_compute_owned(BlasOpCode::Tanh, sum);
+        assert!(queue.read(out).all_close(&cref, 1e-6));
+        let cref = exec.scalar_compute_owned(BlasOpCode::MulScalar(0.5), cref);
+        assert!(queue.read(scaled).all_close(&cref, 1e-6));
+    }
+
+    #[test]
+    fn test_queue_inplace_keeps_order() {
+        // x = (x * 2 + y) / x, with a slow first write so later commands
+        // are queued behind it
+        let mut queue = queue(4);
+        let x = queue.upload(BlasTensor::from_vec(vec![1.0, 2.0]));
+        let y = queue.upload(BlasTensor::from_vec(vec![3.0, 4.0]));
+        let doubled = queue.submit(&[x], |exec, inputs| {
+            std::thread::sleep(Duration::from_millis(20));
+            exec.scalar_compute_owned(BlasOpCode::MulScalar(2.0), inputs[0].clone())
+        });
+        queue.enqueue_inplace(BlasOpCode::Add, doubled, Some(y));
+        queue.enqueue_inplace(BlasOpCode::Div, doubled, Some(x));
+        // aliasing rhs: doubled += doubled
+        queue.enqueue_inplace(BlasOpCode::Add, doubled, Some(doubled));
+        queue.enqueue_inplace(BlasOpCode::Neg, doubled, None);
+        assert_eq!(queue.read(doubled), BlasTensor::from_vec(vec![-10.0, -8.0]));
+    }
+
+    #[test]
+    fn test_queue_write_after_read() {
+        // the read of x must see it before the slow in-place overwrite is
+        // allowed to start
+        let mut queue = queue(2);
+        let x = queue.upload(BlasTensor::from_vec(vec![1.0, 2.0]));
+        let copy = queue.submit(&[x], |_, inputs| {
+            std::thread::sleep(Duration::from_millis(20));
+            inputs[0].clone()
+        });
+        queue.enqueue_inplace(BlasOpCode::MulScalar(0.0), x, None);
+        let after = queue.enqueue(BlasOpCode::AddScalar(1.0), &[x]);
+        assert_eq!(queue.read(copy), BlasTensor::from_vec(vec![1.0, 2.0]));
+        assert_eq!(queue.read(after), BlasTensor::from_vec(vec![1.0, 1.0]));
+    }
+
+    #[test]
+    fn test_queue_runs_independent_commands_in_parallel() {
+        // each command waits for the other to start, which only succeeds
+        // when both run at once
+        let mut queue = queue(2);
+        let started = Arc::new((Mutex::new(0), Condvar::new()));
+        let handles: Vec<TensorHandle> = (0..2)
+            .map(|_| {
+                let started = started.clone();
+                queue.submit(&[], move |_, _| {
+                    let (count, cond) = &*started;
+                    let mut count = count.lock().unwrap();
+                    *count += 1;
+                    cond.notify_all();
+                    let (count, _) = cond
+                        .wait_timeout_while(count, Duration::from_secs(5), |count| *count < 2)
+                        .unwrap();
+                    BlasTensor::from_vec(vec![*count as f32])
+                })
+            })
+            .collect();
+        queue.synchronize();
+        for handle in handles {
+            assert_eq!(queue.read(handle), BlasTensor::from_vec(vec![2.0]));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "command 0 failed: operands' shapes not match: [2] vs [3]")]
+    fn test_queue_reports_failure() {
+        let mut queue = queue(1);
+        let a = queue.upload(BlasTensor::zeros(vec![2]));
+        let b = queue.upload(BlasTensor::zeros(vec![3]));
+        let c = queue.enqueue(BlasOpCode::Add, &[a, b]);
+        queue.enqueue(BlasOpCode::Exp, &[c]);
+        queue.synchronize();
+    }
+
+    #[test]
+    fn test_queue_bookkeeping_bounded() {
+        let mut queue = queue(2);
+        let x = queue.upload(BlasTensor::from_vec(vec![1.0, 2.0]));
+        for i in 0..500 {
+            let y = queue.enqueue(BlasOpCode::AddScalar(1.0), &[x]);
+            if i % 2 == 0 {
+                queue.release(y);
+            } else {
+                assert_eq!(queue.take(y), BlasTensor::from_vec(vec![2.0, 3.0]));
+            }
+        }
+        queue.synchronize();
+        assert!(queue.shared.scheduler.lock().unwrap().commands.is_empty());
+        assert_eq!(queue.entries.len(), 1);
+        // finished reads are pruned when x is read again
+        let y = queue.enqueue(BlasOpCode::Neg, &[x]);
+        assert_eq!(queue.entry(x).access.readers.len(), 1);
+        assert_eq!(queue.take(y), BlasTensor::from_vec(vec![-1.0, -2.0]));
+        assert_eq!(queue.take(x), BlasTensor::from_vec(vec![1.0, 2.0]));
+        assert!(queue.entries.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "tensor handle TensorHandle(0) not valid")]
+    fn test_queue_released_handle() {
+        let mut queue = queue(1);
+        let x = queue.upload(BlasTensor::zeros(vec![2]));
+        queue.release(x);
+        queue.read(x);
+    }
+
+    #[test]
+    fn test_queue_failure_stays_with_its_handles() {
+        // the failed in-place add fails x and what is computed from it,
+        // while tensors independent of it keep working
+        let mut queue = queue(1);
+        let x = queue.upload(BlasTensor::from_vec(vec![1.0, 2.0]));
+        let y = queue.upload(BlasTensor::zeros(vec![3]));
+        queue.enqueue_inplace(BlasOpCode::Add, x, Some(y));
+        queue.enqueue_inplace(BlasOpCode::Neg, x, None);
+        let z = queue.enqueue(BlasOpCode::Exp, &[x]);
+        let w = queue.upload(BlasTensor::from_vec(vec![1.0, 2.0]));
+        let v = queue.enqueue(BlasOpCode::AddScalar(1.0), &[w]);
+        assert_eq!(queue.read(v), BlasTensor::from_vec(vec![2.0, 3.0]));
+        assert_eq!(queue.read(y), BlasTensor::zeros(vec![3]));
+
+        fn failure<T: std::fmt::Debug>(result: std::thread::Result<T>) -> String {
+            result
+                .unwrap_err()
+                .downcast_ref::<String>()
+                .unwrap()
+                .clone()
+        }
+        let expected = "command 0 failed: operands' shapes not match: [2] vs [3]";
+        assert_eq!(
+            failure(catch_unwind(AssertUnwindSafe(|| queue.read(x)))),
+            expected
+        );
+        assert_eq!(
+            failure(catch_unwind(AssertUnwindSafe(|| queue.read(z)))),
+            expected
+        );
+        // synchronize reports the failure once
+        let result = catch_unwind(AssertUnwindSafe(|| queue.synchronize()));
+        assert_eq!(failure(result), expected);
+        queue.synchronize();
+        let u = queue.enqueue(BlasOpCode::Neg, &[v]);
+        assert_eq!(queue.take(u), BlasTensor::from_vec(vec![-2.0, -3.0]));
+    }
+}
@@ -0,0 +1,343 @@
+use ndarray::prelude::*;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_dtype, BlasTensor, TensorElement};
+
+// axis is the logical axis of the tensor (not the one of its 2-D storage),
+// None reduces over all axes; a reduction that would end up with 0 dims
+// returns a single element vector
+impl BlasExecutor {
+    pub fn reduce_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: BlasTensor,
+        axis: Option<usize>,
+        keepdims: bool,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::ReduceSum => self.sum_owned(input, axis, keepdims),
+            BlasOpCode::ReduceMean => self.mean_owned(input, axis, keepdims),
+            BlasOpCode::ReduceMax => self.max_owned(input, axis, keepdims),
+            BlasOpCode::ReduceMin => self.min_owned(input, axis, keepdims),
+            BlasOpCode::ReduceProd => self.prod_owned(input, axis, keepdims),
+            BlasOpCode::ArgMax => self.argmax_owned(input, axis, keepdims),
+            BlasOpCode::ArgMin => self.argmin_owned(input, axis, keepdims),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    // integer sums and products wrap on overflow in every build profile
+    pub fn sum_owned(&self, input: BlasTensor, axis: Option<usize>, keepdims: bool) -> BlasTensor {
+        with_dtype!(input.dtype(), T => reduce(&input, axis, keepdims, sum_lane::<T>))
+    }
+
+    // integer tensors get a truncated mean in their own type, summed in i64
+    // so it does not overflow
+    pub fn mean_owned(&self, input: BlasTensor, axis: Option<usize>, keepdims: bool) -> BlasTensor {
+        with_dtype!(input.dtype(), T => reduce(&input, axis, keepdims, mean_lane::<T>))
+    }
+
+    pub fn max_owned(&self, input: BlasTensor, axis: Option<usize>, keepdims: bool) -> BlasTensor {
+        with_dtype!(input.dtype(), T => reduce(&input, axis, keepdims, |lane: ArrayView1<T>| {
+            lane[argbest_lane(lane, |x, best| x > best) as usize]
+        }))
+    }
+
+    pub fn min_owned(&self, input: BlasTensor, axis: Option<usize>, keepdims: bool) -> BlasTensor {
+        with_dtype!(input.dtype(), T => reduce(&input, axis, keepdims, |lane: ArrayView1<T>| {
+            lane[argbest_lane(lane, |x, best| x < best) as usize]
+        }))
+    }
+
+    pub fn prod_owned(&self, input: BlasTensor, axis: Option<usize>, keepdims: bool) -> BlasTensor {
+        with_dtype!(input.dtype(), T => reduce(&input, axis, keepdims, prod_lane::<T>))
+    }
+
+    // returns Int32 indices of the first max along axis, or into the
+    // flattened logical tensor when axis is None
+    pub fn argmax_owned(
+        &self,
+        input: BlasTensor,
+        axis: Option<usize>,
+        keepdims: bool,
+    ) -> BlasTensor {
+        with_dtype!(input.dtype(), T => reduce(&input, axis, keepdims, |lane: ArrayView1<T>| {
+            argbest_lane(lane, |x, best| x > best)
+        }))
+    }
+
+    pub fn argmin_owned(
+        &self,
+        input: BlasTensor,
+        axis: Option<usize>,
+        keepdims: bool,
+    ) -> BlasTensor {
+        with_dtype!(input.dtype(), T => reduce(&input, axis, keepdims, |lane: ArrayView1<T>| {
+            argbest_lane(lane, |x, best| x < best)
+        }))
+    }
+}
+
+// applies lane_fn to every 1-D lane along axis, or once to all elements
+fn reduce<T, U, F>(
+    input: &BlasTensor,
+    axis: Option<usize>,
+    keepdims: bool,
+    lane_fn: F,
+) -> BlasTensor
+where
+    T: TensorElement,
+    U: TensorElement,
+    F: Fn(ArrayView1<T>) -> U,
+{
+    let view = input.view::<T>();
+    let ndims = view.ndim();
+    let out = match axis {
+        Some(axis) => {
+            assert!(
+                axis < ndims,
+                "reduce axis {} out of range for tensor with {} dims",
+                axis,
+                ndims
+            );
+            let reduced = view.map_axis(Axis(axis), lane_fn);
+            if keepdims {
+                reduced.insert_axis(Axis(axis))
+            } else {
+                reduced
+            }
+        }
+        None => {
+            let value = match view.as_slice() {
+                Some(flat) => lane_fn(ArrayView1::from(flat)),
+                None => lane_fn(view.iter().cloned().collect::<Array1<T>>().view()),
+            };
+            let reduced = arr0(value).into_dyn();
+            if keepdims {
+                reduced.into_shape(vec![1; ndims]).unwrap()
+            } else {
+                reduced
+            }
+        }
+    };
+    BlasTensor::from_array(out)
+}
+
+fn sum_lane<T: Accumulate>(lane: ArrayView1<T>) -> T {
+    lane.iter().fold(T::ZERO, |acc, &x| acc.acc_add(x))
+}
+
+fn prod_lane<T: Accumulate>(lane: ArrayView1<T>) -> T {
+    lane.iter().fold(T::ONE, |acc, &x| acc.acc_mul(x))
+}
+
+fn mean_lane<T: Accumulate>(lane: ArrayView1<T>) -> T {
+    assert!(!lane.is_empty(), "cannot reduce an empty axis");
+    T::mean(lane)
+}
+
+// arithmetic of the accumulating reductions: plain for floats, wrapping
+// for integers
+trait Accumulate: TensorElement {
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn acc_add(self, rhs: Self) -> Self;
+
+    fn acc_mul(self, rhs: Self) -> Self;
+
+    fn mean(lane: ArrayView1<Self>) -> Self;
+}
+
+macro_rules! impl_accumulate_float {
+    ($elem:ty) => {
+        impl Accumulate for $elem {
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+
+            fn acc_add(self, rhs: Self) -> Self {
+                self + rhs
+            }
+
+            fn acc_mul(self, rhs: Self) -> Self {
+                self * rhs
+            }
+
+            fn mean(lane: ArrayView1<Self>) -> Self {
+                sum_lane(lane) / lane.len() as Self
+            }
+        }
+    };
+}
+
+macro_rules! impl_accumulate_int {
+    ($elem:ty) => {
+        impl Accumulate for $elem {
+            const ZERO: Self = 0;
+            const ONE: Self = 1;
+
+            fn acc_add(self, rhs: Self) -> Self {
+                self.wrapping_add(rhs)
+            }
+
+            fn acc_mul(self, rhs: Self) -> Self {
+                self.wrapping_mul(rhs)
+            }
+
+            fn mean(lane: ArrayView1<Self>) -> Self {
+                let sum: i64 = lane.iter().map(|&x| x as i64).sum();
+                (sum / lane.len() as i64) as Self
+            }
+        }
+    };
+}
+
+impl_accumulate_float!(f32);
+impl_accumulate_float!(f64);
+impl_accumulate_int!(i32);
+impl_accumulate_int!(i8);
+
+// index of the first element that no other element beats
+fn argbest_lane<T, F>(lane: ArrayView1<T>, beats: F) -> i32
+where
+    T: TensorElement,
+    F: Fn(T, T) -> bool,
+{
+    assert!(!lane.is_empty(), "cannot reduce an empty axis");
+    let mut best = 0;
+    for (idx, &x) in lane.iter().enumerate() {
+        if beats(x, lane[best]) {
+            best = idx;
+        }
+    }
+    best as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank3() -> BlasTensor {
+        BlasTensor::from_vec_shape((0..24).map(|x| x as f32).collect(), vec![2, 3, 4])
+    }
+
+    #[test]
+    fn test_sum_owned_rank3() {
+        let exec = BlasExecutor::new();
+        let c = exec.sum_owned(rank3(), Some(1), false);
+        let cref = BlasTensor::from_vec_shape(
+            vec![12.0, 15.0, 18.0, 21.0, 48.0, 51.0, 54.0, 57.0],
+            vec![2, 4],
+        );
+        assert_eq!(c.shape(), [2, 4]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_sum_owned_keepdims() {
+        let exec = BlasExecutor::new();
+        let c = exec.sum_owned(rank3(), Some(2), true);
+        let cref =
+            BlasTensor::from_vec_shape(vec![6.0, 22.0, 38.0, 54.0, 70.0, 86.0], vec![2, 3, 1]);
+        assert_eq!(c.ndims(), 3);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_sum_owned_all_axes() {
+        let exec = BlasExecutor::new();
+        let c = exec.sum_owned(rank3(), None, false);
+        assert_eq!(c, BlasTensor::from_vec(vec![276.0]));
+
+        let c = exec.sum_owned(rank3(), None, true);
+        assert_eq!(c, BlasTensor::from_vec_shape(vec![276.0], vec![1, 1, 1]));
+    }
+
+    #[test]
+    fn test_mean_owned_f64() {
+        let a = BlasTensor::from_vec_shape_f64(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        let exec = BlasExecutor::new();
+        let c = exec.mean_owned(a, Some(0), false);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_f64(vec![2.5, 3.5, 4.5], vec![3])
+        );
+    }
+
+    #[test]
+    fn test_max_min_owned_i32() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![3, -1, 7, 2, 9, -4], vec![2, 3]);
+        let c = exec.max_owned(a, Some(1), false);
+        assert_eq!(c, BlasTensor::from_vec_shape_i32(vec![7, 9], vec![2]));
+
+        let a = BlasTensor::from_vec_shape_i32(vec![3, -1, 7, 2, 9, -4], vec![2, 3]);
+        let c = exec.min_owned(a, None, false);
+        assert_eq!(c, BlasTensor::from_vec_shape_i32(vec![-4], vec![1]));
+    }
+
+    #[test]
+    fn test_prod_owned_i8() {
+        let a = BlasTensor::from_vec_shape_i8(vec![1, 2, 3, -1, 2, 2], vec![3, 2]);
+        let exec = BlasExecutor::new();
+        let c = exec.prod_owned(a, Some(0), true);
+        assert_eq!(c, BlasTensor::from_vec_shape_i8(vec![6, -4], vec![1, 2]));
+    }
+
+    #[test]
+    fn test_integer_sum_prod_wrap_and_mean_widens() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![i32::MAX, 1, 65536, 65536], vec![2, 2]);
+        let c = exec.sum_owned(a.clone(), Some(1), false);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![i32::MIN, 131072], vec![2])
+        );
+        let c = exec.prod_owned(a, Some(1), false);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![i32::MAX, 0], vec![2])
+        );
+
+        let a = BlasTensor::from_vec_shape_i8(vec![100, 100, 100, -128, -128, -127], vec![2, 3]);
+        let c = exec.mean_owned(a, Some(1), false);
+        assert_eq!(c, BlasTensor::from_vec_shape_i8(vec![100, -127], vec![2]));
+    }
+
+    #[test]
+    fn test_argmax_argmin_owned_rank4() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(
+            vec![0.5, 2.0, 2.0, -1.0, 4.0, 1.0, 3.0, 0.0],
+            vec![2, 1, 2, 2],
+        );
+        let c = exec.argmax_owned(a, Some(3), false);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![1, 0, 0, 0], vec![2, 1, 2])
+        );
+
+        let a = BlasTensor::from_vec_shape(
+            vec![0.5, 2.0, 2.0, -1.0, 4.0, 1.0, 3.0, 0.0],
+            vec![2, 1, 2, 2],
+        );
+        let c = exec.argmin_owned(a, None, false);
+        assert_eq!(c, BlasTensor::from_vec_shape_i32(vec![3], vec![1]));
+    }
+
+    #[test]
+    fn test_reduce_compute_owned() {
+        let exec = BlasExecutor::new();
+        let c = exec.reduce_compute_owned(BlasOpCode::ReduceMax, rank3(), Some(0), false);
+        let cref = BlasTensor::from_vec_shape((12..24).map(|x| x as f32).collect(), vec![3, 4]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn test_reduce_axis_out_of_range() {
+        let exec = BlasExecutor::new();
+        exec.sum_owned(rank3(), Some(3), false);
+    }
+}
@@ -0,0 +1,265 @@
+use ndarray::prelude::*;
+use num_traits::NumCast;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{BlasTensor, DType, TensorElement};
+
+impl BlasExecutor {
+    // consumes the tensor operand and reuses its buffer for the result
+    pub fn scalar_compute_owned(&self, op: BlasOpCode, input: BlasTensor) -> BlasTensor {
+        let mut out = input;
+        self.scalar_compute_inplace(op, &mut out);
+        out
+    }
+
+    pub fn scalar_compute_inplace(&self, op: BlasOpCode, inout: &mut BlasTensor) {
+        match inout.dtype() {
+            DType::Float => scalar_inplace(inout.view_mut::<f32>(), op),
+            DType::Double => scalar_inplace(inout.view_mut::<f64>(), op),
+            DType::Int32 => scalar_inplace(inout.view_mut::<i32>(), op),
+            DType::Int8 => scalar_inplace(inout.view_mut::<i8>(), op),
+            DType::Bool => panic!("opcode {:?} not supported for Bool tensors", op),
+        }
+    }
+}
+
+fn scalar_inplace<T: ScalarArith>(mut data: ArrayViewMutD<T>, op: BlasOpCode) {
+    match op {
+        BlasOpCode::AddScalar(imm) => {
+            let imm = immediate::<T>(imm);
+            data.mapv_inplace(|x| x.scalar_add(imm))
+        }
+        BlasOpCode::SubScalar(imm) => {
+            let imm = immediate::<T>(imm);
+            data.mapv_inplace(|x| x.scalar_sub(imm))
+        }
+        BlasOpCode::MulScalar(imm) => {
+            let imm = immediate::<T>(imm);
+            data.mapv_inplace(|x| x.scalar_mul(imm))
+        }
+        BlasOpCode::DivScalar(imm) => {
+            let imm = divisor::<T>(imm);
+            data.mapv_inplace(|x| x.scalar_div(imm))
+        }
+        BlasOpCode::PowScalar(imm) => {
+            T::check_exponent(imm);
+            data.mapv_inplace(|x| x.scalar_pow(imm))
+        }
+        BlasOpCode::MinScalar(imm) => {
+            let imm = immediate::<T>(imm);
+            data.mapv_inplace(|x| if imm < x { imm } else { x })
+        }
+        BlasOpCode::MaxScalar(imm) => {
+            let imm = immediate::<T>(imm);
+            data.mapv_inplace(|x| if imm > x { imm } else { x })
+        }
+        BlasOpCode::RSubScalar(imm) => {
+            let imm = immediate::<T>(imm);
+            data.mapv_inplace(|x| imm.scalar_sub(x))
+        }
+        BlasOpCode::RDivScalar(imm) => {
+            let imm = immediate::<T>(imm);
+            data.mapv_inplace(|x| imm.scalar_div(x))
+        }
+        _ => panic!("not wired opcode"),
+    }
+}
+
+// casts the immediate to the element type, rejecting values an integer
+// tensor cannot hold exactly
+fn immediate<T: TensorElement + NumCast>(imm: f64) -> T {
+    let value = T::from(imm);
+    match (value, T::DTYPE) {
+        (Some(value), DType::Float) | (Some(value), DType::Double) => value,
+        (Some(value), _) if value.as_f64() == imm => value,
+        _ => panic!("immediate {} not representable as {:?}", imm, T::DTYPE),
+    }
+}
+
+// floats divide by zero into inf or nan, integers have no such value
+fn divisor<T: TensorElement + NumCast>(imm: f64) -> T {
+    if imm == 0.0 && !matches!(T::DTYPE, DType::Float | DType::Double) {
+        panic!("cannot divide {:?} tensor by zero", T::DTYPE);
+    }
+    immediate(imm)
+}
+
+// arithmetic of the tensor-scalar ops: plain for floats, wrapping for
+// integers like the reductions
+trait ScalarArith: TensorElement + NumCast + PartialOrd {
+    fn scalar_add(self, rhs: Self) -> Self;
+
+    fn scalar_sub(self, rhs: Self) -> Self;
+
+    fn scalar_mul(self, rhs: Self) -> Self;
+
+    fn scalar_div(self, rhs: Self) -> Self;
+
+    fn check_exponent(exp: f64);
+
+    fn scalar_pow(self, exp: f64) -> Self;
+}
+
+macro_rules! impl_scalar_arith_float {
+    ($elem:ty) => {
+        impl ScalarArith for $elem {
+            fn scalar_add(self, rhs: Self) -> Self {
+                self + rhs
+            }
+
+            fn scalar_sub(self, rhs: Self) -> Self {
+                self - rhs
+            }
+
+            fn scalar_mul(self, rhs: Self) -> Self {
+                self * rhs
+            }
+
+            fn scalar_div(self, rhs: Self) -> Self {
+                self / rhs
+            }
+
+            fn check_exponent(_exp: f64) {}
+
+            fn scalar_pow(self, exp: f64) -> Self {
+                self.powf(exp as Self)
+            }
+        }
+    };
+}
+
+macro_rules! impl_scalar_arith_int {
+    ($elem:ty) => {
+        impl ScalarArith for $elem {
+            fn scalar_add(self, rhs: Self) -> Self {
+                self.wrapping_add(rhs)
+            }
+
+            fn scalar_sub(self, rhs: Self) -> Self {
+                self.wrapping_sub(rhs)
+            }
+
+            fn scalar_mul(self, rhs: Self) -> Self {
+                self.wrapping_mul(rhs)
+            }
+
+            // MIN / -1 wraps to MIN
+            fn scalar_div(self, rhs: Self) -> Self {
+                self.wrapping_div(rhs)
+            }
+
+            fn check_exponent(exp: f64) {
+                if exp < 0.0 || exp.fract() != 0.0 || exp > u32::MAX as f64 {
+                    panic!("integer tensors only support non-negative integral exponents");
+                }
+            }
+
+            fn scalar_pow(self, exp: f64) -> Self {
+                self.wrapping_pow(exp as u32)
+            }
+        }
+    };
+}
+
+impl_scalar_arith_float!(f32);
+impl_scalar_arith_float!(f64);
+impl_scalar_arith_int!(i32);
+impl_scalar_arith_int!(i8);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalar_compute_owned_f32() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], vec![2, 3]);
+        let c = exec.scalar_compute_owned(BlasOpCode::MulScalar(0.5), a);
+        let cref = BlasTensor::from_vec_shape(vec![0.5, 1.0, 1.5, 2.0, 2.5, 3.0], vec![2, 3]);
+        assert_eq!(c.shape(), [2, 3]);
+        assert_eq!(c, cref);
+
+        let c = exec.scalar_compute_owned(BlasOpCode::AddScalar(1.0), c);
+        let c = exec.scalar_compute_owned(BlasOpCode::SubScalar(0.5), c);
+        let c = exec.scalar_compute_owned(BlasOpCode::DivScalar(2.0), c);
+        let cref = BlasTensor::from_vec_shape(vec![0.5, 0.75, 1.0, 1.25, 1.5, 1.75], vec![2, 3]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_scalar_compute_reversed() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_f64(vec![1.0, 2.0, 4.0, 8.0], vec![2, 2]);
+        let c = exec.scalar_compute_owned(BlasOpCode::RDivScalar(1.0), a);
+        let cref = BlasTensor::from_vec_shape_f64(vec![1.0, 0.5, 0.25, 0.125], vec![2, 2]);
+        assert_eq!(c, cref);
+
+        let c = exec.scalar_compute_owned(BlasOpCode::RSubScalar(1.0), c);
+        let cref = BlasTensor::from_vec_shape_f64(vec![0.0, 0.5, 0.75, 0.875], vec![2, 2]);
+        assert_eq!(c, cref);
+    }
+
+    #[test]
+    fn test_scalar_compute_clamp_and_pow() {
+        let exec = BlasExecutor::new();
+        let mut a = BlasTensor::from_vec_shape(vec![-2.0, -0.5, 0.5, 3.0], vec![1, 2, 2]);
+        exec.scalar_compute_inplace(BlasOpCode::MaxScalar(-1.0), &mut a);
+        exec.scalar_compute_inplace(BlasOpCode::MinScalar(1.0), &mut a);
+        let cref = BlasTensor::from_vec_shape(vec![-1.0, -0.5, 0.5, 1.0], vec![1, 2, 2]);
+        assert_eq!(a, cref);
+
+        exec.scalar_compute_inplace(BlasOpCode::PowScalar(2.0), &mut a);
+        let cref = BlasTensor::from_vec_shape(vec![1.0, 0.25, 0.25, 1.0], vec![1, 2, 2]);
+        assert_eq!(a, cref);
+    }
+
+    #[test]
+    fn test_scalar_compute_int() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4, 5, 6], vec![2, 3]);
+        let c = exec.scalar_compute_owned(BlasOpCode::PowScalar(2.0), a);
+        let c = exec.scalar_compute_owned(BlasOpCode::RSubScalar(40.0), c);
+        let c = exec.scalar_compute_owned(BlasOpCode::DivScalar(3.0), c);
+        let cref = BlasTensor::from_vec_shape_i32(vec![13, 12, 10, 8, 5, 1], vec![2, 3]);
+        assert_eq!(c, cref);
+
+        let a = BlasTensor::from_vec_shape_i8(vec![-3, 7], vec![2]);
+        let c = exec.scalar_compute_owned(BlasOpCode::MulScalar(-2.0), a);
+        assert_eq!(c, BlasTensor::from_vec_shape_i8(vec![6, -14], vec![2]));
+    }
+
+    #[test]
+    fn test_scalar_compute_int_wraps() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![i32::MAX, i32::MIN, 3], vec![3]);
+        let c = exec.scalar_compute_owned(BlasOpCode::AddScalar(1.0), a.clone());
+        let cref = BlasTensor::from_vec_shape_i32(vec![i32::MIN, i32::MIN + 1, 4], vec![3]);
+        assert_eq!(c, cref);
+        let c = exec.scalar_compute_owned(BlasOpCode::MulScalar(2.0), a.clone());
+        assert_eq!(c, BlasTensor::from_vec_shape_i32(vec![-2, 0, 6], vec![3]));
+        let c = exec.scalar_compute_owned(BlasOpCode::DivScalar(-1.0), a);
+        let cref = BlasTensor::from_vec_shape_i32(vec![-i32::MAX, i32::MIN, -3], vec![3]);
+        assert_eq!(c, cref);
+
+        let a = BlasTensor::from_vec_shape_i8(vec![16, -3], vec![2]);
+        let c = exec.scalar_compute_owned(BlasOpCode::PowScalar(2.0), a);
+        assert_eq!(c, BlasTensor::from_vec_shape_i8(vec![0, 9], vec![2]));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot divide Int32 tensor by zero")]
+    fn test_scalar_compute_int_zero_divisor() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2], vec![2]);
+        exec.scalar_compute_owned(BlasOpCode::DivScalar(0.0), a);
+    }
+
+    #[test]
+    #[should_panic(expected = "not representable as Int32")]
+    fn test_scalar_compute_int_fractional_immediate() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4], vec![2, 2]);
+        exec.scalar_compute_owned(BlasOpCode::MulScalar(0.5), a);
+    }
+}
@@ -0,0 +1,163 @@
+use ndarray::prelude::*;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_tensor::{with_any_dtype, BlasTensor, Layout, TensorElement};
+
+// Logical shape changes on BlasTensor. The storage always stays standard
+// layout and only its 1-D/2-D folding changes, so these consume the tensor
+// and reuse its buffer instead of copying the elements. ColMajor or padded
+// tensors are copied to plain row-major first, channel tags are dropped.
+impl BlasTensor {
+    pub fn reshape(self, shape: &[usize]) -> BlasTensor {
+        let numel: usize = shape.iter().product();
+        if numel != self.numel() {
+            panic!(
+                "cannot reshape tensor of shape {:?} into {:?}",
+                self.shape, shape
+            );
+        }
+        with_any_dtype!(self.dtype(), T => refold::<T>(self, shape.to_vec()))
+    }
+
+    pub fn flatten(self) -> BlasTensor {
+        let numel = self.numel();
+        self.reshape(&[numel])
+    }
+
+    // drops the given size-1 axis, or every size-1 axis when None; a tensor
+    // of only size-1 axes ends up as a single element vector
+    pub fn squeeze(self, axis: Option<usize>) -> BlasTensor {
+        let mut shape = self.shape.clone();
+        match axis {
+            Some(axis) => {
+                if axis >= shape.len() || shape[axis] != 1 {
+                    panic!(
+                        "cannot squeeze axis {} of tensor with shape {:?}",
+                        axis, self.shape
+                    );
+                }
+                shape.remove(axis);
+            }
+            None => shape.retain(|&dim| dim != 1),
+        }
+        if shape.is_empty() {
+            shape.push(1);
+        }
+        self.reshape(&shape)
+    }
+
+    // inserts a size-1 axis before axis, axis == ndims appends one
+    pub fn unsqueeze(self, axis: usize) -> BlasTensor {
+        self.expand_dims(&[axis])
+    }
+
+    // inserts size-1 axes so they sit at the given positions of the result
+    pub fn expand_dims(self, axes: &[usize]) -> BlasTensor {
+        let ndims = self.ndims() + axes.len();
+        let mut sorted = axes.to_vec();
+        sorted.sort_unstable();
+        sorted.dedup();
+        if sorted.len() != axes.len() || sorted.iter().any(|&axis| axis >= ndims) {
+            panic!(
+                "invalid axes {:?} to expand tensor with shape {:?}",
+                axes, self.shape
+            );
+        }
+        let mut shape = self.shape.clone();
+        for axis in sorted {
+            shape.insert(axis, 1);
+        }
+        self.reshape(&shape)
+    }
+}
+
+fn refold<T: TensorElement>(tensor: BlasTensor, shape: Vec<usize>) -> BlasTensor {
+    let mut tensor = BlasExecutor::new().to_layout_owned(tensor, Layout::RowMajor);
+    if tensor.is_padded() {
+        tensor = BlasTensor::from_array(tensor.view::<T>().to_owned());
+    }
+    let storage = T::into_storage(tensor.data).unwrap();
+    let storage = if storage.is_standard_layout() {
+        storage
+    } else {
+        storage.as_standard_layout().into_owned()
+    };
+    let storage = storage
+        .into_shape(IxDyn(&BlasTensor::storage_dims(&shape)))
+        .unwrap();
+    BlasTensor {
+        data: T::wrap(storage),
+        shape,
+        layout: Layout::RowMajor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank3() -> BlasTensor {
+        BlasTensor::from_vec_shape((0..24).map(|x| x as f32).collect(), vec![2, 3, 4])
+    }
+
+    #[test]
+    fn test_reshape_reuses_buffer() {
+        let a = rank3();
+        let ptr = a.view::<f32>().as_ptr();
+        let c = a.reshape(&[4, 6]);
+        assert_eq!(c.shape(), [4, 6]);
+        assert_eq!(c.view::<f32>().as_ptr(), ptr);
+        assert_eq!(c.view::<f32>()[[1, 0]], 6.0);
+
+        let c = c.reshape(&[2, 2, 3, 2]);
+        assert_eq!(c.view::<f32>()[[1, 0, 2, 1]], 17.0);
+        assert_eq!(c.view::<f32>().as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_flatten() {
+        let a = BlasTensor::from_vec_shape_i32((0..8).collect(), vec![2, 2, 2]);
+        let c = a.flatten();
+        assert_eq!(c, BlasTensor::from_vec_shape_i32((0..8).collect(), vec![8]));
+    }
+
+    #[test]
+    fn test_squeeze_unsqueeze() {
+        let a = rank3().reshape(&[2, 1, 12]);
+        let c = a.clone().squeeze(None);
+        assert_eq!(c.shape(), [2, 12]);
+        assert_eq!(c.view::<f32>()[[1, 0]], 12.0);
+
+        let c = c.unsqueeze(2);
+        assert_eq!(c.shape(), [2, 12, 1]);
+
+        let c = c.squeeze(Some(2)).unsqueeze(1);
+        assert_eq!(c, a);
+
+        let c = BlasTensor::ones(vec![1, 1]).squeeze(None);
+        assert_eq!(c, BlasTensor::from_vec(vec![1.0]));
+    }
+
+    #[test]
+    fn test_expand_dims() {
+        let a = BlasTensor::from_vec_shape_bool(vec![true, false], vec![2]);
+        let c = a.expand_dims(&[0, 2]);
+        assert_eq!(c.shape(), [1, 2, 1]);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_bool(vec![true, false], vec![1, 2, 1])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot reshape tensor of shape [2, 3, 4] into [5, 5]")]
+    fn test_reshape_numel_mismatch() {
+        rank3().reshape(&[5, 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot squeeze axis 1")]
+    fn test_squeeze_non_unit_axis() {
+        rank3().squeeze(Some(1));
+    }
+}
@@ -0,0 +1,214 @@
+use ndarray::prelude::*;
+use ndarray::Zip;
+use num_traits::Float;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{BlasTensor, DType, TensorElement};
+
+// Softmax family along the last logical axis of Float/Double tensors. The
+// optional mask is added to the input first and broadcasts to its shape by
+// numpy rules, so -inf entries drop positions out; a lane masked out
+// entirely gets zeros from softmax and -inf from the log variants.
+impl BlasExecutor {
+    pub fn softmax_compute_owned(
+        &self,
+        op: BlasOpCode,
+        input: BlasTensor,
+        mask: Option<&BlasTensor>,
+    ) -> BlasTensor {
+        match op {
+            BlasOpCode::Softmax => self.softmax_owned(input, mask),
+            BlasOpCode::LogSoftmax => self.log_softmax_owned(input, mask),
+            BlasOpCode::LogSumExp => self.logsumexp_owned(input, mask),
+            _ => panic!("not wired opcode"),
+        }
+    }
+
+    pub fn softmax_owned(&self, input: BlasTensor, mask: Option<&BlasTensor>) -> BlasTensor {
+        match check_float(&input, mask) {
+            DType::Float => softmax::<f32>(input, mask, false),
+            _ => softmax::<f64>(input, mask, false),
+        }
+    }
+
+    pub fn log_softmax_owned(&self, input: BlasTensor, mask: Option<&BlasTensor>) -> BlasTensor {
+        match check_float(&input, mask) {
+            DType::Float => softmax::<f32>(input, mask, true),
+            _ => softmax::<f64>(input, mask, true),
+        }
+    }
+
+    // drops the last axis like the reductions without keepdims
+    pub fn logsumexp_owned(&self, input: BlasTensor, mask: Option<&BlasTensor>) -> BlasTensor {
+        match check_float(&input, mask) {
+            DType::Float => logsumexp::<f32>(input, mask),
+            _ => logsumexp::<f64>(input, mask),
+        }
+    }
+}
+
+fn softmax<T: TensorElement + Float>(
+    mut input: BlasTensor,
+    mask: Option<&BlasTensor>,
+    log: bool,
+) -> BlasTensor {
+    add_mask::<T>(&mut input, mask);
+    let axis = Axis(input.ndims() - 1);
+    for mut lane in input.view_mut::<T>().lanes_mut(axis) {
+        let max = lane_max(lane.view());
+        if max == T::neg_infinity() {
+            lane.fill(if log { max } else { T::zero() });
+            continue;
+        }
+        // shifting by the max keeps exp from overflowing
+        let sum = lane.fold(T::zero(), |sum, &x| sum + (x - max).exp());
+        if log {
+            let shift = max + sum.ln();
+            lane.mapv_inplace(|x| x - shift);
+        } else {
+            lane.mapv_inplace(|x| (x - max).exp() / sum);
+        }
+    }
+    input
+}
+
+fn logsumexp<T: TensorElement + Float>(
+    mut input: BlasTensor,
+    mask: Option<&BlasTensor>,
+) -> BlasTensor {
+    add_mask::<T>(&mut input, mask);
+    let axis = Axis(input.ndims() - 1);
+    let out_data = input.view::<T>().map_axis(axis, |lane| {
+        let max = lane_max(lane);
+        if max == T::neg_infinity() {
+            return max;
+        }
+        max + lane.fold(T::zero(), |sum, &x| sum + (x - max).exp()).ln()
+    });
+    BlasTensor::from_array(out_data)
+}
+
+fn lane_max<T: Float>(lane: ArrayView1<'_, T>) -> T {
+    lane.fold(T::neg_infinity(), |max, &x| if x > max { x } else { max })
+}
+
+fn add_mask<T: TensorElement + Float>(input: &mut BlasTensor, mask: Option<&BlasTensor>) {
+    let mask = match mask {
+        Some(mask) => mask,
+        None => return,
+    };
+    let shape = input.shape();
+    let mask_view = mask.view::<T>();
+    let mask_view = mask_view.broadcast(IxDyn(&shape)).unwrap_or_else(|| {
+        panic!(
+            "mask of shape {:?} not broadcastable to {:?}",
+            mask.shape(),
+            shape
+        )
+    });
+    Zip::from(&mut input.view_mut::<T>())
+        .and(&mask_view)
+        .apply(|x, &m| *x = *x + m);
+}
+
+fn check_float(input: &BlasTensor, mask: Option<&BlasTensor>) -> DType {
+    let dtype = input.dtype();
+    if !matches!(dtype, DType::Float | DType::Double) {
+        panic!(
+            "softmax only supports Float and Double tensors, got {:?}",
+            dtype
+        );
+    }
+    if let Some(mask) = mask {
+        if mask.dtype() != dtype {
+            panic!(
+                "operands' types not match: {:?} vs {:?}",
+                dtype,
+                mask.dtype()
+            );
+        }
+    }
+    dtype
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_softmax_stable() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 1000.0, 1001.0, 1002.0], vec![2, 3]);
+        let c = exec.softmax_compute_owned(BlasOpCode::Softmax, a, None);
+        let p = [0.090_030_57, 0.244_728_48, 0.665_240_94];
+        let cref = BlasTensor::from_vec_shape([p, p].concat(), vec![2, 3]);
+        assert!(c.all_close(&cref, 1e-6));
+    }
+
+    #[test]
+    fn test_log_softmax_matches_softmax() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::normal_double(vec![2, 3, 5], 0.0, 4.0);
+        let log_p = exec.log_softmax_owned(a.clone(), None);
+        let p = exec.softmax_owned(a, None);
+        let ln_p = exec.unary_compute_owned(BlasOpCode::Log, p);
+        assert!(log_p.all_close(&ln_p, 1e-12));
+    }
+
+    #[test]
+    fn test_logsumexp() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_f64(vec![0.0, 0.0, 800.0, 800.0], vec![2, 2]);
+        let c = exec.softmax_compute_owned(BlasOpCode::LogSumExp, a, None);
+        let ln2 = std::f64::consts::LN_2;
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_f64(vec![ln2, 800.0 + ln2], vec![2])
+        );
+
+        let c = exec.logsumexp_owned(BlasTensor::from_vec(vec![-1.0, 2.0]), None);
+        assert_eq!(c.shape(), [1]);
+        assert!((c.view::<f32>()[[0]] - 2.048_587_4).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_softmax_broadcast_mask() {
+        // causal mask over a [batch, query, key] score tensor
+        let exec = BlasExecutor::new();
+        let scores = BlasTensor::zeros(vec![2, 3, 3]);
+        let inf = f32::INFINITY;
+        let mask = BlasTensor::from_vec_shape(
+            vec![0.0, -inf, -inf, 0.0, 0.0, -inf, 0.0, 0.0, 0.0],
+            vec![3, 3],
+        );
+        let c = exec.softmax_owned(scores.clone(), Some(&mask));
+        let third = 1.0 / 3.0;
+        let row = vec![1.0, 0.0, 0.0, 0.5, 0.5, 0.0, third, third, third];
+        let cref = BlasTensor::from_vec_shape([row.clone(), row].concat(), vec![2, 3, 3]);
+        assert!(c.all_close(&cref, 1e-6));
+
+        let c = exec.log_softmax_owned(scores, Some(&mask));
+        assert_eq!(c.view::<f32>()[[1, 0, 2]], -inf);
+        assert_eq!(c.view::<f32>()[[1, 1, 0]], -std::f32::consts::LN_2);
+    }
+
+    #[test]
+    fn test_softmax_fully_masked_lane() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(vec![1.0, 2.0, 3.0, 4.0], vec![2, 2]);
+        let mask = BlasTensor::from_vec_shape(vec![f32::NEG_INFINITY, 0.0], vec![2, 1]);
+        let c = exec.softmax_owned(a.clone(), Some(&mask));
+        assert_eq!(c.view::<f32>()[[0, 0]], 0.0);
+        assert_eq!(c.view::<f32>()[[0, 1]], 0.0);
+        let c = exec.logsumexp_owned(a, Some(&mask));
+        assert_eq!(c.view::<f32>()[[0]], f32::NEG_INFINITY);
+    }
+
+    #[test]
+    #[should_panic(expected = "softmax only supports Float and Double tensors, got Int32")]
+    fn test_softmax_int() {
+        let exec = BlasExecutor::new();
+        exec.softmax_owned(BlasTensor::from_vec_shape_i32(vec![1, 2], vec![2]), None);
+    }
+}
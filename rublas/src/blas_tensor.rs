@@ -1,19 +1,13 @@
 use ndarray::prelude::*;
-use ndarray_rand::rand_distr::uniform::SampleUniform;
-use ndarray_rand::rand_distr::Distribution;
 use ndarray_rand::rand_distr::Normal;
 use ndarray_rand::rand_distr::Uniform;
-use num_traits::Zero;
 use std::fmt::Debug;
 
-use ndarray::prelude::*;
-use ndarray::Array;
-use ndarray::*;
+use ndarray::{Array, RawData, Slice};
 use ndarray_rand::RandomExt;
-use ndarray_rand::F32;
 
 // TODO consider hide TensorKind, and expose a into_raw_vec for BlasTensor
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum TensorKind {
     FloatVector(Array1<f32>),
     FloatMatrix(Array2<f32>),
@@ -23,6 +17,8 @@ pub enum TensorKind {
     Int32Matrix(Array2<i32>),
     Int8Vector(Array1<i8>),
     Int8Matrix(Array2<i8>),
+    BoolVector(Array1<bool>),
+    BoolMatrix(Array2<bool>),
 }
 
 impl From<Array1<f32>> for TensorKind {
@@ -61,6 +57,247 @@ impl From<Array2<i32>> for TensorKind {
     }
 }
 
+impl From<Array1<i8>> for TensorKind {
+    fn from(who: Array1<i8>) -> Self {
+        TensorKind::Int8Vector(who)
+    }
+}
+
+impl From<Array2<i8>> for TensorKind {
+    fn from(who: Array2<i8>) -> Self {
+        TensorKind::Int8Matrix(who)
+    }
+}
+
+impl From<Array1<bool>> for TensorKind {
+    fn from(who: Array1<bool>) -> Self {
+        TensorKind::BoolVector(who)
+    }
+}
+
+impl From<Array2<bool>> for TensorKind {
+    fn from(who: Array2<bool>) -> Self {
+        TensorKind::BoolMatrix(who)
+    }
+}
+
+// element type carried by a TensorKind, regardless of vector/matrix storage
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum DType {
+    Float,
+    Double,
+    Int32,
+    Int8,
+    Bool,
+}
+
+impl DType {
+    // position in the promotion lattice Bool -> Int8 -> Int32 -> Float -> Double
+    fn promotion_rank(self) -> u8 {
+        match self {
+            DType::Bool => 0,
+            DType::Int8 => 1,
+            DType::Int32 => 2,
+            DType::Float => 3,
+            DType::Double => 4,
+        }
+    }
+
+    // smallest type both operands convert to along the lattice
+    pub fn promote(self, other: DType) -> DType {
+        if self.promotion_rank() >= other.promotion_rank() {
+            self
+        } else {
+            other
+        }
+    }
+}
+
+// how a cast treats values the target integer type cannot hold; both modes
+// drop the fraction towards zero and map NaN to 0
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CastMode {
+    // clamp to the min/max of the target type
+    Saturating,
+    // keep the low bits of the integral value, like a C cast
+    Truncating,
+}
+
+// how pad fills the new border elements along each axis
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PadMode {
+    // the immediate cast to the element type, like the tensor-scalar opcodes
+    Constant(f64),
+    // mirror without repeating the edge element: [1, 2, 3] -> [3, 2, 1, 2, 3]
+    Reflect,
+    // repeat the edge element: [1, 2, 3] -> [1, 1, 1, 2, 3]
+    Replicate,
+}
+
+// bridges a rust scalar type to the TensorKind variants storing it, so kernels
+// can be written once over ArrayD<T> and wrapped back into the 1-D/2-D storage
+pub trait TensorElement: Copy + Debug + PartialEq + 'static {
+    const DTYPE: DType;
+
+    // borrow the raw 1-D/2-D storage, None if the kind holds another type
+    fn storage(kind: &TensorKind) -> Option<ArrayViewD<'_, Self>>;
+
+    fn storage_mut(kind: &mut TensorKind) -> Option<ArrayViewMutD<'_, Self>>;
+
+    // take the raw 1-D/2-D storage out, None if the kind holds another type
+    fn into_storage(kind: TensorKind) -> Option<ArrayD<Self>>;
+
+    // wrap an already storage-shaped (1-D or 2-D) array
+    fn wrap(storage: ArrayD<Self>) -> TensorKind;
+
+    fn as_f64(self) -> f64;
+}
+
+macro_rules! impl_tensor_element {
+    ($elem:ty, $dtype:ident, $vector:ident, $matrix:ident, $as_f64:expr) => {
+        impl TensorElement for $elem {
+            const DTYPE: DType = DType::$dtype;
+
+            fn storage(kind: &TensorKind) -> Option<ArrayViewD<'_, Self>> {
+                match kind {
+                    TensorKind::$vector(ref data) => Some(data.view().into_dyn()),
+                    TensorKind::$matrix(ref data) => Some(data.view().into_dyn()),
+                    _ => None,
+                }
+            }
+
+            fn storage_mut(kind: &mut TensorKind) -> Option<ArrayViewMutD<'_, Self>> {
+                match kind {
+                    TensorKind::$vector(ref mut data) => Some(data.view_mut().into_dyn()),
+                    TensorKind::$matrix(ref mut data) => Some(data.view_mut().into_dyn()),
+                    _ => None,
+                }
+            }
+
+            fn into_storage(kind: TensorKind) -> Option<ArrayD<Self>> {
+                match kind {
+                    TensorKind::$vector(data) => Some(data.into_dyn()),
+                    TensorKind::$matrix(data) => Some(data.into_dyn()),
+                    _ => None,
+                }
+            }
+
+            fn wrap(storage: ArrayD<Self>) -> TensorKind {
+                match storage.ndim() {
+                    1 => TensorKind::$vector(storage.into_dimensionality::<Ix1>().unwrap()),
+                    2 => TensorKind::$matrix(storage.into_dimensionality::<Ix2>().unwrap()),
+                    _ => panic!("storage of tensor must be 1-D or 2-D"),
+                }
+            }
+
+            fn as_f64(self) -> f64 {
+                ($as_f64)(self)
+            }
+        }
+    };
+}
+
+impl_tensor_element!(f32, Float, FloatVector, FloatMatrix, |x| x as f64);
+impl_tensor_element!(f64, Double, DoubleVector, DoubleMatrix, |x| x);
+impl_tensor_element!(i32, Int32, Int32Vector, Int32Matrix, |x| x as f64);
+impl_tensor_element!(i8, Int8, Int8Vector, Int8Matrix, |x| x as f64);
+impl_tensor_element!(bool, Bool, BoolVector, BoolMatrix, |x| x as u8 as f64);
+
+// binds the rust element type of a numeric DType to $elem and evaluates $body
+// once per type, e.g. with_dtype!(t.dtype(), T => t.view::<T>().sum());
+// Bool tensors panic since they only carry masks
+macro_rules! with_dtype {
+    ($dtype:expr, $elem:ident => $body:expr) => {
+        match $dtype {
+            $crate::blas_tensor::DType::Float => {
+                type $elem = f32;
+                $body
+            }
+            $crate::blas_tensor::DType::Double => {
+                type $elem = f64;
+                $body
+            }
+            $crate::blas_tensor::DType::Int32 => {
+                type $elem = i32;
+                $body
+            }
+            $crate::blas_tensor::DType::Int8 => {
+                type $elem = i8;
+                $body
+            }
+            $crate::blas_tensor::DType::Bool => panic!("op not supported for Bool tensors"),
+        }
+    };
+}
+pub(crate) use with_dtype;
+
+// like with_dtype! but Bool tensors bind $elem to bool too
+macro_rules! with_any_dtype {
+    ($dtype:expr, $elem:ident => $body:expr) => {
+        match $dtype {
+            $crate::blas_tensor::DType::Bool => {
+                type $elem = bool;
+                $body
+            }
+            dtype => $crate::blas_tensor::with_dtype!(dtype, $elem => $body),
+        }
+    };
+}
+pub(crate) use with_any_dtype;
+
+// like with_dtype! for the floating point types only, others panic
+macro_rules! with_float_dtype {
+    ($dtype:expr, $elem:ident => $body:expr) => {
+        match $dtype {
+            $crate::blas_tensor::DType::Float => {
+                type $elem = f32;
+                $body
+            }
+            $crate::blas_tensor::DType::Double => {
+                type $elem = f64;
+                $body
+            }
+            dtype => panic!("op only supports Float and Double tensors, got {:?}", dtype),
+        }
+    };
+}
+pub(crate) use with_float_dtype;
+
+// panics unless both operands share element type and logical shape
+pub(crate) fn check_operands(lhs: &BlasTensor, rhs: &BlasTensor) {
+    if lhs.dtype() != rhs.dtype() {
+        panic!(
+            "operands' types not match: {:?} vs {:?}",
+            lhs.dtype(),
+            rhs.dtype()
+        );
+    }
+    check_shapes(&lhs.shape, &rhs.shape);
+}
+
+// cuts the padding off the contiguous axis of a storage shaped view and
+// brings its axes to the logical order
+fn unpad_and_order<S: RawData>(
+    mut view: ArrayBase<S, IxDyn>,
+    shape: &[usize],
+    layout: Layout,
+) -> ArrayBase<S, IxDyn> {
+    let last = shape.len() - 1;
+    if layout == Layout::ColMajor {
+        view.slice_axis_inplace(Axis(last), Slice::from(..shape[0]));
+        view.reversed_axes()
+    } else {
+        view.slice_axis_inplace(Axis(last), Slice::from(..shape[last]));
+        view
+    }
+}
+
+pub(crate) fn check_shapes(lhs: &[usize], rhs: &[usize]) {
+    if lhs != rhs {
+        panic!("operands' shapes not match: {:?} vs {:?}", lhs, rhs);
+    }
+}
+
 // Purpose of this wrapper layer:
 // 1. simplify usage, hide generics configuration
 // 2. directly support high-order tensor but use <2D arrays for performance
@@ -72,12 +309,27 @@ impl From<Array2<i32>> for TensorKind {
 // to fit blas config
 // TODO maybe need a dedicated Shape/Dimension struct
 // TODO only support D1, D2, D3, D4 now
-// TODO only allow channel_last data layout that contineous along
-// last dims
-#[derive(Debug, PartialEq)]
+// TODO only Layout::ColMajor stores anything but the last logical dim
+// contiguous
+#[derive(Debug, PartialEq, Clone)]
 pub struct BlasTensor {
     pub data: TensorKind,
     pub shape: Vec<usize>,
+    pub layout: Layout,
+}
+
+// How the storage maps to the logical shape. All but ColMajor keep the last
+// logical dim contiguous; ChannelsFirst/ChannelsLast only tag rank-3/4
+// activations as NCW/NCHW or NWC/NHWC for ops that care about channels.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Layout {
+    #[default]
+    RowMajor,
+    // first logical dim contiguous, as handed over by Fortran ordered code;
+    // the storage holds the row-major data of the reversed shape
+    ColMajor,
+    ChannelsFirst,
+    ChannelsLast,
 }
 
 impl BlasTensor {
@@ -89,40 +341,184 @@ impl BlasTensor {
         self.shape.clone()
     }
 
+    pub fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn dtype(&self) -> DType {
+        match self.data {
+            TensorKind::FloatVector(_) | TensorKind::FloatMatrix(_) => DType::Float,
+            TensorKind::DoubleVector(_) | TensorKind::DoubleMatrix(_) => DType::Double,
+            TensorKind::Int32Vector(_) | TensorKind::Int32Matrix(_) => DType::Int32,
+            TensorKind::Int8Vector(_) | TensorKind::Int8Matrix(_) => DType::Int8,
+            TensorKind::BoolVector(_) | TensorKind::BoolMatrix(_) => DType::Bool,
+        }
+    }
+
+    // storage dims for a logical shape: D1 stays a vector, D2-D4 fold all
+    // leading dims into rows and keep the last dim contiguous
+    pub fn storage_dims(shape: &[usize]) -> Vec<usize> {
+        let dims = shape.len();
+        match dims {
+            0 => panic!("not support tensor with 0 dims"),
+            1 => vec![shape[0]],
+            2..=4 => vec![shape[..dims - 1].iter().product(), shape[dims - 1]],
+            _ => panic!("not support tensor with 5 dims or more"),
+        }
+    }
+
+    // build from an n-d array in its logical shape; a 0-d array becomes a
+    // single element vector
+    pub fn from_array<T: TensorElement>(array: ArrayD<T>) -> BlasTensor {
+        let mut shape = array.shape().to_vec();
+        if shape.is_empty() {
+            shape.push(1);
+        }
+        let storage = if array.is_standard_layout() {
+            array
+        } else {
+            array.as_standard_layout().into_owned()
+        };
+        let storage = storage
+            .into_shape(IxDyn(&Self::storage_dims(&shape)))
+            .unwrap();
+        BlasTensor {
+            data: T::wrap(storage),
+            shape,
+            layout: Layout::RowMajor,
+        }
+    }
+
+    // build from an n-d array in its logical shape, keeping the elements
+    // in Fortran order; no copy when the array already is
+    pub fn from_array_fortran<T: TensorElement>(array: ArrayD<T>) -> BlasTensor {
+        if array.ndim() < 2 {
+            return Self::from_array(array);
+        }
+        let shape = array.shape().to_vec();
+        let reversed = Self::from_array(array.reversed_axes());
+        BlasTensor {
+            data: reversed.data,
+            shape,
+            layout: Layout::ColMajor,
+        }
+    }
+
+    // raw_data lists the elements of the logical shape in Fortran order
+    pub fn from_vec_fortran<T: TensorElement>(raw_data: Vec<T>, shape: Vec<usize>) -> BlasTensor {
+        let array = ArrayD::from_shape_vec(IxDyn(&shape).f(), raw_data).unwrap();
+        Self::from_array_fortran(array)
+    }
+
+    // tags a row-major rank-3/4 tensor as channel-first or channel-last
+    // without moving data, see BlasExecutor::to_layout_owned for conversions
+    pub fn with_layout(mut self, layout: Layout) -> BlasTensor {
+        let tagged = matches!(layout, Layout::ChannelsFirst | Layout::ChannelsLast);
+        if self.layout == Layout::ColMajor || layout == Layout::ColMajor {
+            panic!("cannot tag {:?} tensor as {:?}", self.layout, layout);
+        }
+        if tagged && !(3..=4).contains(&self.ndims()) {
+            panic!("{:?} needs a tensor with 3 or 4 dims", layout);
+        }
+        self.layout = layout;
+        self
+    }
+
+    // n-d view over the storage in the logical shape
+    pub fn view<T: TensorElement>(&self) -> ArrayViewD<'_, T> {
+        let storage = T::storage(&self.data)
+            .unwrap_or_else(|| panic!("tensor's type not compatible with {:?}", T::DTYPE));
+        let dims = self.padded_dims(storage.shape());
+        unpad_and_order(storage.into_shape(dims).unwrap(), &self.shape, self.layout)
+    }
+
+    pub fn view_mut<T: TensorElement>(&mut self) -> ArrayViewMutD<'_, T> {
+        let dims = self.padded_dims(&self.storage_shape());
+        let (shape, layout) = (self.shape.clone(), self.layout);
+        let storage = T::storage_mut(&mut self.data)
+            .unwrap_or_else(|| panic!("tensor's type not compatible with {:?}", T::DTYPE));
+        unpad_and_order(storage.into_shape(dims).unwrap(), &shape, layout)
+    }
+
+    // dims of the raw 1-D/2-D storage, its last one is the leading dim
+    pub fn storage_shape(&self) -> Vec<usize> {
+        with_any_dtype!(self.dtype(), T => T::storage(&self.data).unwrap().shape().to_vec())
+    }
+
+    // elements between the starts of two consecutive storage rows; larger
+    // than the contiguous logical dim when the storage carries padding
+    pub fn leading_dim(&self) -> usize {
+        *self.storage_shape().last().unwrap()
+    }
+
+    pub fn is_padded(&self) -> bool {
+        self.storage_shape().iter().product::<usize>() != self.numel()
+    }
+
+    // the storage order of the logical dims, with the contiguous one widened
+    // to the leading dim of the storage
+    fn padded_dims(&self, storage_shape: &[usize]) -> IxDyn {
+        let mut dims = self.shape.clone();
+        if self.layout == Layout::ColMajor {
+            dims.reverse();
+        }
+        *dims.last_mut().unwrap() = *storage_shape.last().unwrap();
+        IxDyn(&dims)
+    }
+
+    // same type and shape, and every element within tol of the other one
+    pub fn all_close(&self, other: &BlasTensor, tol: f64) -> bool {
+        if self.dtype() != other.dtype() || self.shape != other.shape {
+            return false;
+        }
+        if self.dtype() == DType::Bool {
+            return self.view::<bool>() == other.view::<bool>();
+        }
+        with_dtype!(self.dtype(), T => self
+            .view::<T>()
+            .iter()
+            .zip(other.view::<T>().iter())
+            .all(|(&lhs, &rhs)| (lhs.as_f64() - rhs.as_f64()).abs() <= tol))
+    }
+
     pub fn from_vec(raw_data: Vec<f32>) -> BlasTensor {
         let raw_shape = vec![raw_data.len()];
         BlasTensor {
             data: TensorKind::from(Array::from(raw_data)),
             shape: raw_shape,
+            layout: Layout::RowMajor,
         }
     }
 
     pub fn from_vec_shape(raw_data: Vec<f32>, shape: Vec<usize>) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return BlasTensor {
+            BlasTensor {
                 data: TensorKind::from(
                     Array1::<f32>::from_shape_vec([shape[0]], raw_data).unwrap(),
                 ),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(
                     Array2::<f32>::from_shape_vec([shape[0], shape[1]], raw_data).unwrap(),
                 ),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(
                     Array2::<f32>::from_shape_vec([shape[0] * shape[1], shape[2]], raw_data)
                         .unwrap(),
                 ),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(
                     Array2::<f32>::from_shape_vec(
                         [shape[0] * shape[1] * shape[2], shape[3]],
@@ -130,8 +526,9 @@ impl BlasTensor {
                     )
                     .unwrap(),
                 ),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support float tensor with 5 dims or more");
         }
@@ -141,29 +538,32 @@ impl BlasTensor {
     pub fn from_vec_shape_i32(raw_data: Vec<i32>, shape: Vec<usize>) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return BlasTensor {
+            BlasTensor {
                 data: TensorKind::from(
                     Array1::<i32>::from_shape_vec([shape[0]], raw_data).unwrap(),
                 ),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(
                     Array2::<i32>::from_shape_vec([shape[0], shape[1]], raw_data).unwrap(),
                 ),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(
                     Array2::<i32>::from_shape_vec([shape[0] * shape[1], shape[2]], raw_data)
                         .unwrap(),
                 ),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(
                     Array2::<i32>::from_shape_vec(
                         [shape[0] * shape[1] * shape[2], shape[3]],
@@ -171,39 +571,56 @@ impl BlasTensor {
                     )
                     .unwrap(),
                 ),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support float tensor with 5 dims or more");
         }
     }
 
+    pub fn from_vec_shape_f64(raw_data: Vec<f64>, shape: Vec<usize>) -> BlasTensor {
+        Self::from_array(ArrayD::from_shape_vec(shape, raw_data).unwrap())
+    }
+
+    pub fn from_vec_shape_i8(raw_data: Vec<i8>, shape: Vec<usize>) -> BlasTensor {
+        Self::from_array(ArrayD::from_shape_vec(shape, raw_data).unwrap())
+    }
+
+    pub fn from_vec_shape_bool(raw_data: Vec<bool>, shape: Vec<usize>) -> BlasTensor {
+        Self::from_array(ArrayD::from_shape_vec(shape, raw_data).unwrap())
+    }
+
     // TODO patch match on shape len
     pub fn zeros(shape: Vec<usize>) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array1::<f32>::zeros([shape[0]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::zeros([shape[0], shape[1]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::zeros([shape[0] * shape[1], shape[2]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::zeros([
                     shape[0] * shape[1] * shape[2],
                     shape[3],
                 ])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support float tensor with 5 dims or more");
         }
@@ -212,28 +629,32 @@ impl BlasTensor {
     pub fn ones(shape: Vec<usize>) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array1::<f32>::ones([shape[0]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::ones([shape[0], shape[1]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::ones([shape[0] * shape[1], shape[2]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::ones([
                     shape[0] * shape[1] * shape[2],
                     shape[3],
                 ])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support float tensor with 5 dims or more");
         }
@@ -242,28 +663,32 @@ impl BlasTensor {
     pub fn zeros_double(shape: Vec<usize>) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array1::<f64>::zeros([shape[0]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::zeros([shape[0], shape[1]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::zeros([shape[0] * shape[1], shape[2]])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::zeros([
                     shape[0] * shape[1] * shape[2],
                     shape[3],
                 ])),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support float tensor with 5 dims or more");
         }
@@ -273,37 +698,41 @@ impl BlasTensor {
     pub fn uniform(shape: Vec<usize>, min: f32, max: f32) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array1::<f32>::random(
                     shape[0],
                     Uniform::<f32>::new(min, max),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::random(
                     [shape[0], shape[1]],
                     Uniform::<f32>::new(min, max),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::random(
                     [shape[0] * shape[1], shape[2]],
                     Uniform::<f32>::new(min, max),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::random(
                     [shape[0] * shape[1] * shape[2], shape[3]],
                     Uniform::<f32>::new(min, max),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support tensor with 5 dims or more");
         }
@@ -312,37 +741,41 @@ impl BlasTensor {
     pub fn uniform_double(shape: Vec<usize>, min: f64, max: f64) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array1::<f64>::random(
                     shape[0],
                     Uniform::<f64>::new(min, max),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::random(
                     [shape[0], shape[1]],
                     Uniform::<f64>::new(min, max),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::random(
                     [shape[0] * shape[1], shape[2]],
                     Uniform::<f64>::new(min, max),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::random(
                     [shape[0] * shape[1] * shape[2], shape[3]],
                     Uniform::<f64>::new(min, max),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support tensor with 5 dims or more");
         }
@@ -351,37 +784,41 @@ impl BlasTensor {
     pub fn normal(shape: Vec<usize>, mean: f32, std: f32) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array1::<f32>::random(
                     shape[0],
                     Normal::<f32>::new(mean, std).unwrap(),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::random(
                     [shape[0], shape[1]],
                     Normal::<f32>::new(mean, std).unwrap(),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::random(
                     [shape[0] * shape[1], shape[2]],
                     Normal::<f32>::new(mean, std).unwrap(),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f32>::random(
                     [shape[0] * shape[1] * shape[2], shape[3]],
                     Normal::<f32>::new(mean, std).unwrap(),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support tensor with 5 dims or more");
         }
@@ -390,37 +827,41 @@ impl BlasTensor {
     pub fn normal_double(shape: Vec<usize>, mean: f64, std: f64) -> BlasTensor {
         let dims = shape.len();
         if dims == 1 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array1::<f64>::random(
                     shape[0],
                     Normal::<f64>::new(mean, std).unwrap(),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 2 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::random(
                     [shape[0], shape[1]],
                     Normal::<f64>::new(mean, std).unwrap(),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 3 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::random(
                     [shape[0] * shape[1], shape[2]],
                     Normal::<f64>::new(mean, std).unwrap(),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else if dims == 4 {
-            return Self {
+            Self {
                 data: TensorKind::from(Array2::<f64>::random(
                     [shape[0] * shape[1] * shape[2], shape[3]],
                     Normal::<f64>::new(mean, std).unwrap(),
                 )),
-                shape: shape,
-            };
+                shape,
+                layout: Layout::RowMajor,
+            }
         } else {
             panic!("not support tensor with 5 dims or more");
         }
@@ -428,10 +869,8 @@ impl BlasTensor {
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
-    use ndarray::*;
     use ndarray_rand::rand_distr::Uniform;
 
     #[test]
@@ -494,59 +933,59 @@ mod tests {
     // TODO impl rand_isaac with fixed seed, then compare contents
     #[test]
     fn test_uniform_1d() {
-        let blast = BlasTensor::uniform(vec![64], -1.0, 1.0);
-        let reft = TensorKind::FloatVector(Array::random(64, Uniform::new(-1f32, 1.)));
+        let _blast = BlasTensor::uniform(vec![64], -1.0, 1.0);
+        let _reft = TensorKind::FloatVector(Array::random(64, Uniform::new(-1f32, 1.)));
         // assert_eq!(blast.data.into().shape(), reft.into().shape());
     }
 
     #[test]
     fn test_uniform_double_1d() {
-        let blast = BlasTensor::uniform(vec![64], -1f32, 1.0);
-        let reft = TensorKind::DoubleVector(Array::random(64, Uniform::new(-1f64, 1.)));
+        let _blast = BlasTensor::uniform(vec![64], -1f32, 1.0);
+        let _reft = TensorKind::DoubleVector(Array::random(64, Uniform::new(-1f64, 1.)));
         // assert_eq!(blast.data, reft);
     }
 
     #[test]
     fn test_uniform_2d() {
-        let blast = BlasTensor::uniform(vec![64, 32], -1f32, 1.0);
-        let reft = TensorKind::FloatMatrix(Array::random([64, 32], Uniform::new(-1f32, 1.)));
+        let _blast = BlasTensor::uniform(vec![64, 32], -1f32, 1.0);
+        let _reft = TensorKind::FloatMatrix(Array::random([64, 32], Uniform::new(-1f32, 1.)));
         // assert_eq!(blast.data, reft);
     }
 
     #[test]
     fn test_normal_2d() {
-        let blast = BlasTensor::normal(vec![64, 32], 0.0f32, 1.0);
-        let reft =
+        let _blast = BlasTensor::normal(vec![64, 32], 0.0f32, 1.0);
+        let _reft =
             TensorKind::FloatMatrix(Array::random([64, 32], Normal::new(0.0f32, 1.).unwrap()));
         // assert_eq!(blast.data, reft);
     }
 
     #[test]
     fn test_normal_double_2d() {
-        let blast = BlasTensor::uniform_double(vec![64, 32], -1f64, 1.0);
-        let reft =
+        let _blast = BlasTensor::uniform_double(vec![64, 32], -1f64, 1.0);
+        let _reft =
             TensorKind::DoubleMatrix(Array::random([64, 32], Normal::new(-1f64, 1.).unwrap()));
         // assert_eq!(blast.data, reft);
     }
 
     #[test]
     fn test_uniform_double_2d() {
-        let blast = BlasTensor::uniform_double(vec![64, 32], -1f64, 1.0);
-        let reft = TensorKind::DoubleMatrix(Array::random([64, 32], Uniform::new(-1f64, 1.)));
+        let _blast = BlasTensor::uniform_double(vec![64, 32], -1f64, 1.0);
+        let _reft = TensorKind::DoubleMatrix(Array::random([64, 32], Uniform::new(-1f64, 1.)));
         // assert_eq!(blast.data, reft);
     }
 
     #[test]
     fn test_uniform_3d() {
-        let blast = BlasTensor::uniform(vec![8, 64, 32], -1f32, 1.0);
-        let reft = TensorKind::FloatMatrix(Array::random([512, 32], Uniform::new(-1f32, 1.)));
+        let _blast = BlasTensor::uniform(vec![8, 64, 32], -1f32, 1.0);
+        let _reft = TensorKind::FloatMatrix(Array::random([512, 32], Uniform::new(-1f32, 1.)));
         // assert_eq!(blast.data, reft);
     }
 
     #[test]
     fn test_uniform_4d() {
-        let blast = BlasTensor::uniform(vec![8, 2, 64, 32], -1f32, 1.0);
-        let reft = TensorKind::FloatMatrix(Array::random([1024, 32], Uniform::new(-1f32, 1.)));
+        let _blast = BlasTensor::uniform(vec![8, 2, 64, 32], -1f32, 1.0);
+        let _reft = TensorKind::FloatMatrix(Array::random([1024, 32], Uniform::new(-1f32, 1.)));
         // assert_eq!(blast.data, reft);
     }
 
@@ -578,4 +1017,15 @@ mod tests {
         assert_eq!(blast.shape(), vec![2, 4]);
         assert_eq!(blast.data, reft);
     }
+
+    #[test]
+    fn test_build_from_3d_bool() {
+        let blast = BlasTensor::from_vec_shape_bool(vec![true, false, false, true], vec![2, 1, 2]);
+        let reft = TensorKind::BoolMatrix(
+            Array::from_shape_vec([2, 2], vec![true, false, false, true]).unwrap(),
+        );
+        assert_eq!(blast.shape(), vec![2, 1, 2]);
+        assert_eq!(blast.dtype(), DType::Bool);
+        assert_eq!(blast.data, reft);
+    }
 }
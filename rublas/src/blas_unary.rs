@@ -0,0 +1,238 @@
+use num_traits::{Float, PrimInt, Signed, WrappingNeg};
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{BlasTensor, DType};
+
+impl BlasExecutor {
+    // consumes the operand and reuses its buffer for the result
+    pub fn unary_compute_owned(&self, op: BlasOpCode, input: BlasTensor) -> BlasTensor {
+        let mut out = input;
+        self.unary_compute_inplace(op, &mut out);
+        out
+    }
+
+    pub fn unary_compute_inplace(&self, op: BlasOpCode, inout: &mut BlasTensor) {
+        match inout.dtype() {
+            DType::Float => inout.view_mut::<f32>().mapv_inplace(float_unary_fn(op)),
+            DType::Double => inout.view_mut::<f64>().mapv_inplace(float_unary_fn(op)),
+            DType::Int32 => inout.view_mut::<i32>().mapv_inplace(int_unary_fn(op)),
+            DType::Int8 => inout.view_mut::<i8>().mapv_inplace(int_unary_fn(op)),
+            DType::Bool => inout.view_mut::<bool>().mapv_inplace(bool_unary_fn(op)),
+        }
+    }
+}
+
+pub(crate) fn float_unary_fn<T: Float>(op: BlasOpCode) -> fn(T) -> T {
+    match op {
+        BlasOpCode::Neg => |x: T| -x,
+        BlasOpCode::Abs => |x: T| x.abs(),
+        BlasOpCode::Exp => |x: T| x.exp(),
+        BlasOpCode::Log => |x: T| x.ln(),
+        BlasOpCode::Sqrt => |x: T| x.sqrt(),
+        BlasOpCode::Rsqrt => |x: T| x.sqrt().recip(),
+        BlasOpCode::Sin => |x: T| x.sin(),
+        BlasOpCode::Cos => |x: T| x.cos(),
+        BlasOpCode::Tanh => |x: T| x.tanh(),
+        BlasOpCode::Sigmoid => |x: T| (T::one() + (-x).exp()).recip(),
+        BlasOpCode::Relu => |x: T| if x > T::zero() { x } else { T::zero() },
+        // exact form 0.5 * x * (1 + erf(x / sqrt(2))), not the tanh approximation
+        BlasOpCode::Gelu => |x: T| {
+            let erf = erf(x.to_f64().unwrap() * FRAC_1_SQRT_2);
+            x * T::from(0.5 * (1.0 + erf)).unwrap()
+        },
+        BlasOpCode::Erf => |x: T| T::from(erf(x.to_f64().unwrap())).unwrap(),
+        BlasOpCode::Floor => |x: T| x.floor(),
+        BlasOpCode::Ceil => |x: T| x.ceil(),
+        // halfway cases round away from zero
+        BlasOpCode::Round => |x: T| x.round(),
+        BlasOpCode::Sign => |x: T| if x == T::zero() { x } else { x.signum() },
+        BlasOpCode::Reciprocal => |x: T| x.recip(),
+        _ => panic!("not wired opcode"),
+    }
+}
+
+// Neg and Abs wrap like the integer reductions, so MIN maps to itself
+pub(crate) fn int_unary_fn<T: PrimInt + Signed + WrappingNeg>(op: BlasOpCode) -> fn(T) -> T {
+    match op {
+        BlasOpCode::Neg => |x: T| x.wrapping_neg(),
+        BlasOpCode::Abs => |x: T| if x < T::zero() { x.wrapping_neg() } else { x },
+        BlasOpCode::Relu => |x: T| if x > T::zero() { x } else { T::zero() },
+        BlasOpCode::Sign => |x: T| x.signum(),
+        BlasOpCode::Floor | BlasOpCode::Ceil | BlasOpCode::Round => |x: T| x,
+        _ => panic!("opcode {:?} not supported for integer tensors", op),
+    }
+}
+
+pub(crate) fn bool_unary_fn(op: BlasOpCode) -> fn(bool) -> bool {
+    match op {
+        BlasOpCode::LogicalNot => |x: bool| !x,
+        _ => panic!("opcode {:?} not supported for Bool tensors", op),
+    }
+}
+
+// maclaurin series near zero and the continued fraction of erfc in the
+// tails, accurate to ~1e-15 across the range
+pub(crate) fn erf(x: f64) -> f64 {
+    if x.is_nan() {
+        return x;
+    }
+    let ax = x.abs();
+    let value = if ax < 2.5 {
+        let x2 = ax * ax;
+        let mut term = ax;
+        let mut sum = ax;
+        let mut n = 0.0;
+        loop {
+            n += 1.0;
+            term *= -x2 / n;
+            let delta = term / (2.0 * n + 1.0);
+            sum += delta;
+            if delta.abs() <= 1e-17 * sum.abs() {
+                break;
+            }
+        }
+        sum * 2.0 / PI.sqrt()
+    } else if ax < 6.0 {
+        let mut frac = ax;
+        for k in (1..=60).rev() {
+            frac = ax + (k as f64 / 2.0) / frac;
+        }
+        1.0 - (-ax * ax).exp() / (PI.sqrt() * frac)
+    } else {
+        1.0
+    };
+    value.copysign(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unary_compute_owned_f32() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape(vec![-2.0, -0.5, 0.0, 0.5, 1.0, 4.0], vec![2, 3]);
+        let c = exec.unary_compute_owned(BlasOpCode::Abs, a);
+        let cref = BlasTensor::from_vec_shape(vec![2.0, 0.5, 0.0, 0.5, 1.0, 4.0], vec![2, 3]);
+        assert_eq!(c.shape(), [2, 3]);
+        assert_eq!(c, cref);
+
+        let c = exec.unary_compute_owned(BlasOpCode::Sqrt, c);
+        let half = 0.5f32.sqrt();
+        let cref =
+            BlasTensor::from_vec_shape(vec![2.0f32.sqrt(), half, 0.0, half, 1.0, 2.0], vec![2, 3]);
+        assert!(c.all_close(&cref, 1e-6));
+    }
+
+    #[test]
+    fn test_unary_compute_inplace_f64() {
+        let exec = BlasExecutor::new();
+        let mut a = BlasTensor::from_vec_shape_f64(vec![0.0, 1.0, -1.0, 2.0], vec![2, 1, 2]);
+        exec.unary_compute_inplace(BlasOpCode::Exp, &mut a);
+        let cref = BlasTensor::from_vec_shape_f64(
+            vec![1.0, 1.0f64.exp(), (-1.0f64).exp(), 2.0f64.exp()],
+            vec![2, 1, 2],
+        );
+        assert_eq!(a, cref);
+
+        exec.unary_compute_inplace(BlasOpCode::Log, &mut a);
+        let cref = BlasTensor::from_vec_shape_f64(vec![0.0, 1.0, -1.0, 2.0], vec![2, 1, 2]);
+        assert!(a.all_close(&cref, 1e-12));
+    }
+
+    #[test]
+    fn test_activations() {
+        let exec = BlasExecutor::new();
+        let x = vec![-3.0, -1.0, 0.0, 1.0, 3.0];
+
+        let c = exec.unary_compute_owned(BlasOpCode::Relu, BlasTensor::from_vec(x.clone()));
+        assert_eq!(c, BlasTensor::from_vec(vec![0.0, 0.0, 0.0, 1.0, 3.0]));
+
+        let c = exec.unary_compute_owned(BlasOpCode::Sigmoid, BlasTensor::from_vec(x.clone()));
+        let cref = BlasTensor::from_vec(vec![0.047425874, 0.26894143, 0.5, 0.7310586, 0.95257413]);
+        assert!(c.all_close(&cref, 1e-6));
+
+        let c = exec.unary_compute_owned(BlasOpCode::Gelu, BlasTensor::from_vec(x));
+        let cref = BlasTensor::from_vec(vec![-0.0040496, -0.15865526, 0.0, 0.8413447, 2.9959502]);
+        assert!(c.all_close(&cref, 1e-6));
+    }
+
+    #[test]
+    fn test_erf() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_f64(vec![-0.5, 0.0, 1.0, 2.0, 3.0, 7.0], vec![6]);
+        let c = exec.unary_compute_owned(BlasOpCode::Erf, a);
+        let cref = BlasTensor::from_vec_shape_f64(
+            vec![
+                -0.5204998778130465,
+                0.0,
+                0.8427007929497149,
+                0.9953222650189527,
+                0.9999779095030014,
+                1.0,
+            ],
+            vec![6],
+        );
+        assert!(c.all_close(&cref, 1e-14));
+    }
+
+    #[test]
+    fn test_rounding_and_sign() {
+        let exec = BlasExecutor::new();
+        let x = vec![-1.5, -0.2, 0.0, 0.5, 2.7];
+        let c = exec.unary_compute_owned(BlasOpCode::Floor, BlasTensor::from_vec(x.clone()));
+        assert_eq!(c, BlasTensor::from_vec(vec![-2.0, -1.0, 0.0, 0.0, 2.0]));
+        let c = exec.unary_compute_owned(BlasOpCode::Ceil, BlasTensor::from_vec(x.clone()));
+        assert_eq!(c, BlasTensor::from_vec(vec![-1.0, -0.0, 0.0, 1.0, 3.0]));
+        let c = exec.unary_compute_owned(BlasOpCode::Round, BlasTensor::from_vec(x.clone()));
+        assert_eq!(c, BlasTensor::from_vec(vec![-2.0, -0.0, 0.0, 1.0, 3.0]));
+        let c = exec.unary_compute_owned(BlasOpCode::Sign, BlasTensor::from_vec(x));
+        assert_eq!(c, BlasTensor::from_vec(vec![-1.0, -1.0, 0.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn test_unary_compute_i32() {
+        let exec = BlasExecutor::new();
+        let mut a = BlasTensor::from_vec_shape_i32(vec![-3, 0, 5, -7], vec![2, 2]);
+        exec.unary_compute_inplace(BlasOpCode::Neg, &mut a);
+        assert_eq!(
+            a,
+            BlasTensor::from_vec_shape_i32(vec![3, 0, -5, 7], vec![2, 2])
+        );
+
+        let c = exec.unary_compute_owned(BlasOpCode::Relu, a);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![3, 0, 0, 7], vec![2, 2])
+        );
+
+        let c = exec.unary_compute_owned(BlasOpCode::Sign, c);
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape_i32(vec![1, 0, 0, 1], vec![2, 2])
+        );
+    }
+
+    #[test]
+    fn test_unary_int_min_wraps() {
+        let exec = BlasExecutor::new();
+        for op in [BlasOpCode::Neg, BlasOpCode::Abs] {
+            let a = BlasTensor::from_vec_shape_i32(vec![i32::MIN, -5], vec![2]);
+            let cref = BlasTensor::from_vec_shape_i32(vec![i32::MIN, 5], vec![2]);
+            assert_eq!(exec.unary_compute_owned(op, a), cref);
+            let a = BlasTensor::from_vec_shape_i8(vec![i8::MIN, -5], vec![2]);
+            let cref = BlasTensor::from_vec_shape_i8(vec![i8::MIN, 5], vec![2]);
+            assert_eq!(exec.unary_compute_owned(op, a), cref);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "not supported for integer tensors")]
+    fn test_unary_compute_i32_float_only_op() {
+        let exec = BlasExecutor::new();
+        let a = BlasTensor::from_vec_shape_i32(vec![1, 2, 3, 4], vec![2, 2]);
+        exec.unary_compute_owned(BlasOpCode::Exp, a);
+    }
+}
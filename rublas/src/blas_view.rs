@@ -0,0 +1,269 @@
+use ndarray::prelude::*;
+pub use ndarray::Slice;
+
+use crate::blas_executor::BlasExecutor;
+use crate::blas_opcode::BlasOpCode;
+use crate::blas_tensor::{with_any_dtype, BlasTensor, DType, TensorElement};
+
+// Borrowing, possibly strided window over a BlasTensor. Each logical axis
+// keeps the tensor index of its first element, its length and its step, so
+// slicing a view again composes into a new window over the same tensor
+// without touching the data. Slices follow ndarray's s![] semantics: the
+// range is applied first and a negative step walks it backwards.
+#[derive(Debug, Clone)]
+pub struct BlasTensorView<'a> {
+    tensor: &'a BlasTensor,
+    starts: Vec<usize>,
+    steps: Vec<isize>,
+    shape: Vec<usize>,
+}
+
+impl<'a> BlasTensorView<'a> {
+    pub fn ndims(&self) -> usize {
+        self.shape.len()
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn numel(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    pub fn dtype(&self) -> DType {
+        self.tensor.dtype()
+    }
+
+    // true when the window covers the whole tensor in order
+    pub fn is_full(&self) -> bool {
+        self.shape == self.tensor.shape
+            && self.starts.iter().all(|&start| start == 0)
+            && self.steps.iter().all(|&step| step == 1)
+    }
+
+    // one slice per logical axis, the window is narrowed axis by axis
+    pub fn slice(&self, slices: &[Slice]) -> BlasTensorView<'a> {
+        if slices.len() != self.ndims() {
+            panic!(
+                "{} slices given for tensor view with shape {:?}",
+                slices.len(),
+                self.shape
+            );
+        }
+        let mut out = self.clone();
+        for (axis, &slice) in slices.iter().enumerate() {
+            out.slice_axis_inplace(axis, slice);
+        }
+        out
+    }
+
+    // keeps len elements of axis starting at start
+    pub fn narrow(&self, axis: usize, start: usize, len: usize) -> BlasTensorView<'a> {
+        if axis >= self.ndims() {
+            panic!(
+                "narrow axis {} out of range for tensor view with shape {:?}",
+                axis, self.shape
+            );
+        }
+        let mut out = self.clone();
+        out.slice_axis_inplace(axis, Slice::from(start..start + len));
+        out
+    }
+
+    // strided ndarray view in the logical shape of the window
+    pub fn view<T: TensorElement>(&self) -> ArrayViewD<'a, T> {
+        let mut view = self.tensor.view::<T>();
+        for axis in 0..self.ndims() {
+            let (start, len, step) = (
+                self.starts[axis] as isize,
+                self.shape[axis],
+                self.steps[axis],
+            );
+            let slice = if len == 0 {
+                Slice::new(start, Some(start), 1)
+            } else {
+                let last = start + (len as isize - 1) * step;
+                if step > 0 {
+                    Slice::new(start, Some(last + 1), step)
+                } else {
+                    Slice::new(last, Some(start + 1), step)
+                }
+            };
+            view.slice_axis_inplace(Axis(axis), slice);
+        }
+        view
+    }
+
+    // copies the window into a new contiguous tensor
+    pub fn to_owned(&self) -> BlasTensor {
+        if self.is_full() {
+            return self.tensor.clone();
+        }
+        with_any_dtype!(self.dtype(), T => BlasTensor::from_array(self.view::<T>().to_owned()))
+    }
+
+    fn slice_axis_inplace(&mut self, axis: usize, slice: Slice) {
+        let len = self.shape[axis] as isize;
+        let resolve = |index: isize| {
+            let index = if index < 0 { index + len } else { index };
+            if index < 0 || index > len || slice.step == 0 {
+                panic!(
+                    "slice {:?} out of range for axis {} of tensor view with shape {:?}",
+                    slice, axis, self.shape
+                );
+            }
+            index
+        };
+        let start = resolve(slice.start);
+        let end = resolve(slice.end.unwrap_or(len)).max(start);
+        let stride = slice.step.abs();
+        let count = (end - start + stride - 1) / stride;
+        // a negative step starts from the end of the range
+        let first = if slice.step > 0 || count == 0 {
+            start
+        } else {
+            end - 1
+        };
+        let step = self.steps[axis];
+        self.starts[axis] = (self.starts[axis] as isize + first * step) as usize;
+        self.steps[axis] = step * slice.step;
+        self.shape[axis] = count as usize;
+    }
+}
+
+impl<'a> From<&'a BlasTensor> for BlasTensorView<'a> {
+    fn from(tensor: &'a BlasTensor) -> Self {
+        BlasTensorView {
+            tensor,
+            starts: vec![0; tensor.ndims()],
+            steps: vec![1; tensor.ndims()],
+            shape: tensor.shape.clone(),
+        }
+    }
+}
+
+impl BlasTensor {
+    pub fn as_view(&self) -> BlasTensorView<'_> {
+        BlasTensorView::from(self)
+    }
+
+    pub fn slice(&self, slices: &[Slice]) -> BlasTensorView<'_> {
+        self.as_view().slice(slices)
+    }
+
+    pub fn narrow(&self, axis: usize, start: usize, len: usize) -> BlasTensorView<'_> {
+        self.as_view().narrow(axis, start, len)
+    }
+}
+
+// view operands are read in place, only the result gets a fresh buffer
+impl BlasExecutor {
+    pub fn binary_compute_view(
+        &self,
+        op: BlasOpCode,
+        lhs: &BlasTensorView,
+        rhs: &BlasTensorView,
+    ) -> BlasTensor {
+        let mut out = lhs.to_owned();
+        self.binary_compute_inplace_view(op, &mut out, rhs);
+        out
+    }
+
+    pub fn unary_compute_view(&self, op: BlasOpCode, input: &BlasTensorView) -> BlasTensor {
+        let mut out = input.to_owned();
+        self.unary_compute_inplace(op, &mut out);
+        out
+    }
+
+    pub fn scalar_compute_view(&self, op: BlasOpCode, input: &BlasTensorView) -> BlasTensor {
+        let mut out = input.to_owned();
+        self.scalar_compute_inplace(op, &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank3() -> BlasTensor {
+        BlasTensor::from_vec_shape((0..24).map(|x| x as f32).collect(), vec![2, 3, 4])
+    }
+
+    #[test]
+    fn test_slice_strided() {
+        let a = rank3();
+        let v = a.slice(&[
+            Slice::from(1..),
+            Slice::from(..).step_by(2),
+            Slice::from(1..4).step_by(2),
+        ]);
+        assert_eq!(v.shape(), [1, 2, 2]);
+        assert_eq!(
+            v.to_owned(),
+            BlasTensor::from_vec_shape(vec![13.0, 15.0, 21.0, 23.0], vec![1, 2, 2])
+        );
+        // borrows the storage of the tensor
+        assert_eq!(
+            v.view::<f32>().as_ptr(),
+            &a.view::<f32>()[[1, 0, 1]] as *const f32
+        );
+    }
+
+    #[test]
+    fn test_slice_negative_step_and_index() {
+        let a = BlasTensor::from_vec_shape_i32((0..6).collect(), vec![6]);
+        let v = a.slice(&[Slice::new(1, Some(-1), -1)]);
+        assert_eq!(
+            v.to_owned(),
+            BlasTensor::from_vec_shape_i32(vec![4, 3, 2, 1], vec![4])
+        );
+
+        let v = v.slice(&[Slice::from(..).step_by(-2)]);
+        assert_eq!(
+            v.to_owned(),
+            BlasTensor::from_vec_shape_i32(vec![1, 3], vec![2])
+        );
+    }
+
+    #[test]
+    fn test_narrow_composes() {
+        let a = rank3();
+        let v = a.narrow(2, 1, 3).narrow(1, 1, 2).narrow(2, 1, 1);
+        assert_eq!(v.shape(), [2, 2, 1]);
+        assert_eq!(
+            v.to_owned(),
+            BlasTensor::from_vec_shape(vec![6.0, 10.0, 18.0, 22.0], vec![2, 2, 1])
+        );
+        assert!(a.as_view().is_full());
+        assert!(!v.is_full());
+    }
+
+    #[test]
+    fn test_executor_view_inputs() {
+        let exec = BlasExecutor::new();
+        let a = rank3();
+        let b = BlasTensor::from_vec_shape_i32(vec![1; 6], vec![2, 3, 1]);
+        let lhs = a.narrow(2, 3, 1);
+        let c = exec.binary_compute_view(BlasOpCode::Add, &lhs, &b.as_view());
+        let cref =
+            BlasTensor::from_vec_shape(vec![4.0, 8.0, 12.0, 16.0, 20.0, 24.0], vec![2, 3, 1]);
+        assert_eq!(c, cref);
+
+        let c = exec.unary_compute_view(BlasOpCode::Neg, &a.narrow(0, 1, 1).narrow(1, 0, 1));
+        assert_eq!(
+            c,
+            BlasTensor::from_vec_shape(vec![-12.0, -13.0, -14.0, -15.0], vec![1, 1, 4])
+        );
+
+        let c = exec.scalar_compute_view(BlasOpCode::MulScalar(2.0), &a.narrow(1, 2, 1));
+        assert_eq!(c.view::<f32>()[[1, 0, 3]], 46.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range for axis 1")]
+    fn test_slice_out_of_range() {
+        rank3().slice(&[Slice::from(..), Slice::from(1..5), Slice::from(..)]);
+    }
+}
@@ -2,9 +2,35 @@ extern crate ndarray;
 extern crate ndarray_linalg;
 extern crate ndarray_rand;
 
+pub mod blas_attention;
+pub mod blas_autodiff;
+pub mod blas_buffer;
+pub mod blas_cast;
+pub mod blas_compare;
+pub mod blas_concat;
+pub mod blas_conv;
+pub mod blas_error;
 pub mod blas_executor;
+pub mod blas_graph;
+pub mod blas_index;
+pub mod blas_layout;
+pub mod blas_loss;
+pub mod blas_norm;
 pub mod blas_opcode;
+pub mod blas_ops;
+pub mod blas_optim;
+pub mod blas_pad;
+pub mod blas_permute;
+pub mod blas_pool;
+pub mod blas_program;
+pub mod blas_queue;
+pub mod blas_reduce;
+pub mod blas_scalar;
+pub mod blas_shape;
+pub mod blas_softmax;
 pub mod blas_tensor;
+pub mod blas_unary;
+pub mod blas_view;
 
 /// Prelude module for users to import
 pub mod prelude {
@@ -13,9 +39,21 @@ pub mod prelude {
     pub use ndarray_linalg::*;
 
     // prelude
+    pub use crate::blas_attention::*;
+    pub use crate::blas_autodiff::*;
+    pub use crate::blas_buffer::*;
+    pub use crate::blas_conv::*;
+    pub use crate::blas_error::*;
     pub use crate::blas_executor::*;
+    pub use crate::blas_graph::*;
+    pub use crate::blas_loss::*;
     pub use crate::blas_opcode::*;
+    pub use crate::blas_optim::*;
+    pub use crate::blas_pool::*;
+    pub use crate::blas_program::*;
+    pub use crate::blas_queue::*;
     pub use crate::blas_tensor::*;
+    pub use crate::blas_view::*;
 }
 
 // TODO use custom measurements: TFLOPS for criterion
@@ -49,6 +87,6 @@ mod tests {
     fn mat_inv_test() {
         // let a = Array2::<f32>::zeros((64, 32));
         let a: Array2<f32> = random((3, 3));
-        a.inv();
+        let _ = a.inv();
     }
 }